@@ -221,5 +221,5 @@ mod tests {
 
 fn main() {
     let circuit = MyCircuit::<TermField>(PhantomData);
-    ExtractingAssignment::run(&circuit, "Fibonacci.Ex1", &[]).unwrap();
+    ExtractingAssignment::run(&circuit, "Fibonacci.Ex1", &[], &mut std::io::stdout()).unwrap();
 }
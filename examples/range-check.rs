@@ -116,7 +116,7 @@ fn main() {
     const RANGE: usize = 10;
     let circuit = MyCircuit::<TermField, RANGE> {value: Value::known(TermField::from(5).into())};
 
-    ExtractingAssignment::run(&circuit, "RangeCheck", &[]).unwrap();
+    ExtractingAssignment::run(&circuit, "RangeCheck", &[], &mut std::io::stdout()).unwrap();
 }
 
 //     #[test]
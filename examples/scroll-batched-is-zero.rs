@@ -107,5 +107,5 @@ fn main() {
         expect_is_zero: Some(true),
         _marker: PhantomData,
     };
-    ExtractingAssignment::run(&circuit, "BatchedIsZero", &[]).unwrap();
+    ExtractingAssignment::run(&circuit, "BatchedIsZero", &[], &mut std::io::stdout()).unwrap();
 }
@@ -79,5 +79,5 @@ impl Circuit<TermField> for TestCircuit {
 
 fn main() {
     let circuit = TestCircuit {};
-    ExtractingAssignment::run(&circuit, "BinaryNumber", &[]).unwrap();
+    ExtractingAssignment::run(&circuit, "BinaryNumber", &[], &mut std::io::stdout()).unwrap();
 }
\ No newline at end of file
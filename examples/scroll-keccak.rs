@@ -4,7 +4,8 @@ use halo2_extr::{
 
 fn main() {
     let num_rows = 2_usize.pow(10);
-    println!("-- {:?}", KeccakCircuit::capacity_for_row(num_rows));
+    // Stdout only carries the Lean, so the capacity goes to stderr
+    eprintln!("capacity: {:?}", KeccakCircuit::capacity_for_row(num_rows));
     let circuit = KeccakCircuit::new(num_rows, vec![vec![0,0]]);
-    ExtractingAssignment::run(&circuit, "Keccak", &[]).unwrap();
+    ExtractingAssignment::run(&circuit, "Keccak", &[], &mut std::io::stdout()).unwrap();
 }
@@ -134,18 +134,10 @@ impl<F: Field> Circuit<F> for MyCircuit<F> {
 }
 
 fn main() {
-    let input_0 = ["a1", "a2", "a3", "a4"]
-        .map(|e| Value::known(TermField::create_symbol(e)))
-        .to_vec();
-    let input_1 = ["b1", "b2", "b3", "b4"]
-        .map(|e| TermField::create_symbol(e))
-        .to_vec();
-    let shuffle_0 = ["c1", "c2", "c3", "c4"]
-        .map(|e| Value::known(TermField::create_symbol(e)))
-        .to_vec();
-    let shuffle_1 = ["d1", "d2", "d3", "d4"]
-        .map(|e| Value::known(TermField::create_symbol(e)))
-        .to_vec();
+    let input_0 = TermField::create_symbol_vec("a", 4).into_iter().map(Value::known).collect();
+    let input_1 = TermField::create_symbol_vec("b", 4);
+    let shuffle_0 = TermField::create_symbol_vec("c", 4).into_iter().map(Value::known).collect();
+    let shuffle_1 = TermField::create_symbol_vec("d", 4).into_iter().map(Value::known).collect();
 
     let circuit = MyCircuit::<TermField> {
         input_0,
@@ -154,7 +146,7 @@ fn main() {
         shuffle_1,
     };
 
-    ExtractingAssignment::run(&circuit, "ShuffleExample", &["a1", "a2", "a3", "a4", "b1", "b2", "b3", "b4", "c1", "c2", "c3", "c4", "d1", "d2", "d3", "d4"]).unwrap();
+    ExtractingAssignment::run(&circuit, "ShuffleExample", &[], &mut std::io::stdout()).unwrap();
 }
 
 #[test]
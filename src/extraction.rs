@@ -1,12 +1,14 @@
+use std::cell::Cell;
 use std::collections::BTreeMap;
-use std::fs;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 use std::marker::PhantomData;
 use std::path::Path;
 
 use halo2_frontend::plonk::sealed::SealedPhase;
 use halo2_frontend::plonk::{sealed, Phase};
-use halo2_proofs::plonk::{Circuit, ConstraintSystem, Expression, FirstPhase};
-use itertools::Itertools;
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, FirstPhase};
 
 use halo2_proofs::{
     arithmetic::Field,
@@ -14,44 +16,68 @@ use halo2_proofs::{
     plonk::{Advice, Any, Assigned, Assignment, Column, Error, Fixed, FloorPlanner, Instance, Selector},
 };
 
-use crate::field::TermField;
-use crate::utils::{get_group_annotations, group_values, make_lean_comment, print_grouped_props, update_column_annotation, update_row_annotation};
+use crate::field::{
+    clear_symbols, conflicting_symbols, registered_symbols, take_assumptions, take_policy_report, validate_symbol_name, SymbolDecl, SymbolicField, Term,
+};
+use crate::lean::{print_circuit, write_modules};
+use crate::model::{self, CircuitModel, ColumnAnnotations, Constraint, CopyConstraint, FixedFill, Gate, Lookup, RegionEvent, RegionSelectors, Shuffle, MODEL_VERSION};
+use crate::project::LakeProject;
+use crate::utils::{update_column_annotation, update_row_annotation};
+
+/// Errors that can occur while extracting a circuit
+#[derive(Debug)]
+pub enum ExtractionError {
+    /// The circuit failed to synthesize
+    Synthesis(Error),
+    /// The Lean output could not be written to the sink
+    Io(io::Error),
+    /// A symbol can't be emitted as a field of the Lean `Circuit` structure
+    InvalidSymbol { name: String, reason: &'static str },
+}
+
+impl Display for ExtractionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExtractionError::Synthesis(err) => write!(f, "Circuit synthesis failed: {err:?}"),
+            ExtractionError::Io(err) => write!(f, "Failed to write extracted Lean: {err}"),
+            ExtractionError::InvalidSymbol { name, reason } => write!(f, "Invalid symbol {name:?}: {reason}"),
+        }
+    }
+}
 
-const GROUPING_SIZE: usize = 10;
+impl std::error::Error for ExtractionError {}
+
+impl From<Error> for ExtractionError {
+    fn from(value: Error) -> Self {
+        ExtractionError::Synthesis(value)
+    }
+}
+
+impl From<io::Error> for ExtractionError {
+    fn from(value: io::Error) -> Self {
+        ExtractionError::Io(value)
+    }
+}
 
 pub struct ExtractingAssignment<F: Field> {
     _marker: PhantomData<F>,
-    advice_column_annotations: BTreeMap<usize, (Option<String>, BTreeMap<usize, String>)>,
+    advice_column_annotations: BTreeMap<usize, ColumnAnnotations>,
     current_region: Option<String>,
-    copies: Vec<((Column<Any>, usize), (Column<Any>, usize))>,
+    copies: Vec<CopyConstraint>,
     selectors: BTreeMap<usize, BTreeMap<usize, String>>,
-    fixed: BTreeMap<usize, BTreeMap<usize, String>>,
-    fixed_column_annotations: BTreeMap<usize, (Option<String>, BTreeMap<usize, String>)>,
-    fixed_fill: BTreeMap<usize, (usize, String)>,
-    instance_column_annotations: BTreeMap<usize, (Option<String>, BTreeMap<usize, String>)>,
+    fixed: BTreeMap<usize, BTreeMap<usize, Term>>,
+    fixed_column_annotations: BTreeMap<usize, ColumnAnnotations>,
+    fixed_fill: BTreeMap<usize, (usize, Term)>,
+    instance_column_annotations: BTreeMap<usize, ColumnAnnotations>,
     current_phase: sealed::Phase,
-    usable_rows_filename: String,
+    regions: Vec<RegionEvent>,
+    region_selectors: Vec<RegionSelectors>,
+    // One more than the highest row touched so far. A Cell because query_instance only has &self
+    usable_rows: Cell<usize>,
 }
 
-impl<F: Field> Drop for ExtractingAssignment<F> {
-    fn drop(&mut self) {
-        std::fs::remove_file(&self.usable_rows_filename).expect("Failed to delete usable_rows file. Feel free to manually delete");
-    }
-}
-
-// impl<F: Field + From<String> + Display> ExtractingAssignment<F> {
-impl ExtractingAssignment<TermField> {
+impl<F: SymbolicField> ExtractingAssignment<F> {
     pub fn new() -> Self {
-        let usable_rows_filename = if Path::new("./usable_rows").exists() {
-            let mut i = 1;
-            while Path::new(&format!("./usable_rows_{i}")).exists() {
-                i += 1;
-            }
-            format!("usable_rows_{i}")
-        } else {
-            "usable_rows".to_string()
-        };
-        fs::write(&usable_rows_filename, "0").expect("Failed to write to usable_rows");
         Self {
             _marker: PhantomData,
             advice_column_annotations: BTreeMap::new(),
@@ -63,7 +89,9 @@ impl ExtractingAssignment<TermField> {
             fixed_fill: BTreeMap::new(),
             instance_column_annotations: BTreeMap::new(),
             current_phase: FirstPhase.to_sealed(),
-            usable_rows_filename,
+            regions: vec![],
+            region_selectors: vec![],
+            usable_rows: Cell::new(0),
         }
     }
 
@@ -71,367 +99,75 @@ impl ExtractingAssignment<TermField> {
         self.current_phase == phase.to_sealed()
     }
 
-    fn print_copy_constraints(&self) {
-
-        let format_side = |col: &Column<Any>, row| {
-            match col.column_type() {
-                Any::Advice => format!("c.get_advice {} {}", col.index(), row),
-                Any::Fixed => format!("c.get_fixed {} {}", col.index(), row),
-                Any::Instance => format!("c.get_instance {} {}", col.index(), row),
-            }
-        };
-
-        let props = self
-            .copies
-            .iter()
-            .map(|((left_column, left_row), (right_column, right_row))| {
-                format!("{} = {}", format_side(left_column, left_row), format_side(right_column, right_row))
-            })
-            .collect_vec();
-
-        print_grouped_props("copy_", "all_copy_constraints", &props, GROUPING_SIZE);
-    }
-
-    // TODO grouping, annotations
-    fn print_selectors(&self) {
-        for (col, row_set) in &self.selectors {
-            if let Some((&start, _)) = row_set.first_key_value() {
-                let runs = {
-                    let mut start = start;
-                    // End is inclusive
-                    let mut end = start;
-                    let mut runs = vec![];
-    
-                    // Iterate through the true rows, to collect the consecutive runs
-                    for (&i, _) in row_set.iter().skip(1) {
-                        if end == i - 1 {
-                            // We have found a row that connects to the current run
-                            end = i;
-                        } else {
-                            runs.push((start, end));
-                            start = i;
-                            end = i;
-                        }
-                    }
-    
-                    runs.push((start, end));
-                    runs
-                };
-
-                let body = runs
-                    .iter()
-                    .map(|(start, end)| {
-                        if *start == 0 {
-                            format!("if row < {} then 1", end+1)
-                        } else {
-                            format!("if row < {start} then 0\n  else if row < {} then 1", end+1)
-                        }
-                    })
-                    .join("\n  else ");
-                println!("def selector_func_col_{col} (c: ValidCircuit P P_Prime) : ℕ → ZMod P :=");
-                println!("  λ row =>");
-                println!("  {body}");
-                println!("  else 0");
-            } else {
-                println!("def selector_func_col_{col} (c: ValidCircuit P P_Prime) : ℕ → ZMod P :=");
-                println!("  λ _ => 0");
-            }
-
-        }
-        println!("def selector_func (c: ValidCircuit P P_Prime) : ℕ → ℕ → ZMod P :=");
-        println!("  λ col row => match col with");
-        for col in self.selectors.keys() {
-            println!("    | {col} => selector_func_col_{col} c row")
-        }
-        println!("    | _ => 0");
-    }
-
-    fn print_fixed(&self) {
-        for (col, row_set) in &self.fixed {
-            // (value, start, end, annotations already printed)
-            let mut entries = group_values(row_set)
-                .into_iter()
-                .map(|(a,b,c)| (a,b,c,false))
-                .collect_vec();
-
-            assert!(GROUPING_SIZE > 1);
-
-            while entries.len() > GROUPING_SIZE {
-                let mut new_entries = vec![];
-    
-                while entries.len() > GROUPING_SIZE {
-                    {
-                        let start = entries[0].1;
-                        let end = entries[GROUPING_SIZE-1].2.unwrap_or(entries[GROUPING_SIZE-1].1);
-                        let name = format!("fixed_func_col_{col}_{start}_to_{end}");
-                        println!("def {name} (c: ValidCircuit P P_Prime) : ℕ → ZMod P :=");
-                        println!("  λ row =>");
-                        new_entries.push((
-                            format!("{name} c row"),
-                            start,
-                            Some(end),
-                            true
-                        ));
-                    }
-                    let mut first = true;
-                    for _ in 0..GROUPING_SIZE {
-                        let value = &entries[0].0;
-                        let start = entries[0].1;
-                        let prefix = if first {
-                            first = false;
-                            "  "
-                        } else {
-                            "  else "
-                        };
-                        let print_annotations = !entries[0].3;
-                        if let Some(end) = entries[0].2 {
-                            let annotation = match (print_annotations, self.fixed_column_annotations.get(&col)) {
-                                (true, Some((_, row_annotations))) => get_group_annotations(row_annotations, start, end),
-                                _ => None,
-                            };
-                            
-                            if let Some(annotation) = annotation {
-                                if annotation.contains("\n") {
-                                    println!("{annotation}");
-                                    println!("{prefix}if row ≥ {start} ∧ row ≤ {end} then {value}")
-                                } else {
-                                    println!("{prefix}if row ≥ {start} ∧ row ≤ {end} then {value} -- {annotation}")
-                                }
-                            } else {
-                                println!("{prefix}if row ≥ {start} ∧ row ≤ {end} then {value}")
-                            }
-                        } else {
-                            let annotation = match (print_annotations, self.fixed_column_annotations.get(&col)) {
-                                (true, Some((_, row_annotations))) => get_group_annotations(row_annotations, start, start),
-                                _ => None,
-                            };
-
-                            if let Some(annotation) = annotation {
-                                println!("{prefix}if row = {start} then {value}{annotation}")
-                            } else {
-                                println!("{prefix}if row = {start} then {value}")
-                            }
-                        }
-                        entries.remove(0);
-                    }
-                    println!("  else c.1.FixedUnassigned {col} row");
-                }
-
-                for new_entry in new_entries.into_iter().rev() {
-                    entries.insert(0, new_entry);
-                }
-            }
-
-            println!("def fixed_func_col_{col} (c: ValidCircuit P P_Prime) : ℕ → ZMod P :=");
-            println!("  λ row =>");
-            let mut first = true;
-            for (value, start, end, _) in entries {
-                if first {
-                    first = false;
-                    print!("  ");
-                } else {
-                    print!("  else ");
-                }
-                if let Some(end) = end {
-                    println!("if row ≥ {start} ∧ row ≤ {end} then {value}")
-                } else {
-                    println!("if row = {start} then {value}")
-                }
-            }
-            if let Some ((fill_row, fill_value)) = self.fixed_fill.get(&col) {
-                if first {
-                    print!("  ");
-                } else {
-                    print!("  else ");
-                }
-                println!("if row ≥ {fill_row} ∧ row < c.usable_rows then {fill_value}")
-            }
-            println!("  else c.1.FixedUnassigned {col} row");
-        }
-
-        println!("def fixed_func (c: ValidCircuit P P_Prime) : ℕ → ℕ → ZMod P :=");
-        println!("  λ col row => match col with");
-        for col in self.fixed.keys() {
-            if let Some((Some(annotation), _)) = self.fixed_column_annotations.get(col) {
-                println!("    | {col} => fixed_func_col_{col} c row {}", make_lean_comment(annotation));
-            } else {
-                println!("    | {col} => fixed_func_col_{col} c row");
-            }
-        }
-        println!("    | _ => c.1.FixedUnassigned col row");
-    }
-
-    fn print_advice_phase(&self, cs: &ConstraintSystem<TermField>) {
-        println!("def advice_phase (c: ValidCircuit P P_Prime) : ℕ → ℕ :=");
-        println!("  λ col => match col with");
-        for (col, phase) in cs.advice_column_phase().iter().enumerate() {
-            if *phase != 0 {
-                println!("  | {col} => {phase}");
-            }
-        }
-        println!("  | _ => 0");
-    }
-
-    fn print_advice_annotations(&self) {
-        println!("  -- Advice column annotations:");
-        if self.advice_column_annotations.is_empty() {
-            println!("  -- None");
-        }
-        self.advice_column_annotations
-            .iter()
-            .for_each(|(col, (column_annotation, rows))| {
-                println!("-- Advice Column {col}");
-                if let Some(column_annotation) = column_annotation {
-                    println!("{}", make_lean_comment(column_annotation));
-                }
-                if let Some((start, _)) = rows.first_key_value() {
-                    if let Some ((end, _)) = rows.last_key_value() {
-                        if let Some(comments) = get_group_annotations(rows, *start, *end) {
-                            println!("{comments}");
-                        }
-                    }
-                }
-            });
-    }
-
-    fn print_instance_annotations(&self) {
-        println!("  -- Instance column annotations:");
-        if self.instance_column_annotations.is_empty() {
-            println!("  -- None");
-        }
-        self.instance_column_annotations
-            .iter()
-            .for_each(|(col, (column_annotation, rows))| {
-                println!("-- Instance Column {col}");
-                if let Some(column_annotation) = column_annotation {
-                    println!("{}", make_lean_comment(column_annotation));
-                }
-                if let Some((start, _)) = rows.first_key_value() {
-                    if let Some ((end, _)) = rows.last_key_value() {
-                        if let Some(comments) = get_group_annotations(rows, *start, *end) {
-                            println!("{comments}");
-                        }
-                    }
-                }
-            });
-    }
-
-    fn print_gates(&self, cs: &ConstraintSystem<TermField>) {
-        let constraints = cs
+    /// Combine everything collected during synthesis with the constraint system into a `CircuitModel`
+    pub fn into_model(self, namespace: &str, symbols: Vec<SymbolDecl>, cs: &ConstraintSystem<F>) -> CircuitModel {
+        let gates = cs
             .gates()
             .iter()
-            .enumerate()
-            .flat_map(|(gate_idx, gate)| {
-                // Each gate can contain many polynomials, so we need an inner iteration
-                gate
+            .map(|gate| Gate {
+                name: gate.name().to_string(),
+                constraints: gate
                     .polynomials()
                     .iter()
                     .enumerate()
-                    .filter(move |(poly_idx, polynomial)| {
-                        match polynomial {
-                            Expression::Constant(TermField::Val(0)) => {
-                                println!(
-                                    "  -- Gate number {} name: \"{}\" part {}/{} {} is trivially true",
-                                    gate_idx+1,
-                                    gate.name(),
-                                    poly_idx+1,
-                                    gate.polynomials().len(),
-                                    gate.constraint_name(*poly_idx)
-                                );
-                                false
-                            },
-                            _ => true
-                        }
-                    })
-                    .map(move |(poly_idx, polynomial)| {
-                        format!(
-                            "-- Gate number {} name: \"{}\" part {}/{} {}\n  ∀ row: ℕ, {} = 0",
-                            gate_idx+1,
-                            gate.name(),
-                            poly_idx+1,
-                            gate.polynomials().len(),
-                            gate.constraint_name(poly_idx),
-                            expression_to_value_string(polynomial, "row")
-                        )
+                    .map(|(idx, polynomial)| Constraint {
+                        name: gate.constraint_name(idx).to_string(),
+                        polynomial: polynomial.into(),
                     })
+                    .collect(),
             })
-            .collect_vec();
-
-        print_grouped_props("gate_", "all_gates", &constraints, GROUPING_SIZE);
-    }
+            .collect();
 
-    fn print_lookups(&self, cs: &ConstraintSystem<TermField>) {
         let lookups = cs
             .lookups()
             .iter()
-            .enumerate()
-            .map(|(idx, lookup)| {
-                let lhs = lookup.input_expressions()
-                    .iter()
-                    .map(|expr| {
-                        expression_to_value_string(expr, "row")
-                    })
-                    .join(", ");
-                let rhs = lookup.table_expressions()
-                    .iter()
-                    .map(|expr| {
-                        expression_to_value_string(expr, "lookup_row")
-                    })
-                    .join(", ");
-                format!(
-                    "∀ row : ℕ, row < c.usable_rows → ∃ lookup_row : ℕ, lookup_row < c.usable_rows ∧ -- Lookup number {} name: \"{}\"\n  ({lhs}) = ({rhs})\n  ",
-                    idx+1,
-                    lookup.name()
-                )
+            .map(|lookup| Lookup {
+                name: lookup.name().to_string(),
+                inputs: lookup.input_expressions().iter().map(Into::into).collect(),
+                table: lookup.table_expressions().iter().map(Into::into).collect(),
             })
-            .collect_vec();
-
-        print_grouped_props("lookup_", "all_lookups", &lookups, GROUPING_SIZE);
-    }
-
-    pub fn print_grouping_props(&self, cs: &ConstraintSystem<TermField>) {
-        println!("");
-        println!("");
-        self.print_copy_constraints();
-        self.print_selectors();
-        self.print_fixed();
-        self.print_advice_phase(&cs);
-        self.print_advice_annotations();
-        self.print_instance_annotations();
-        self.print_gates(&cs);
-        self.print_lookups(&cs);
-        
-
-        // Shuffles
-        {
-            let mut shuffle_names = vec![];
-            for shuffle in cs.shuffles() {
-                let name = format!("shuffle_{}", shuffle.name().replace("_", "__").replace(" ", "_")); // TODO mangle if necessary
-                shuffle_names.push(name.clone());
-                let lhs = shuffle.input_expressions()
-                    .iter()
-                    .map(|expr| {
-                        expression_to_value_string(expr, "row")
-                    })
-                    .join(", ");
-                let rhs = shuffle.shuffle_expressions()
-                    .iter()
-                    .map(|expr| {
-                        expression_to_value_string(expr, "(shuffle row)")
-                    })
-                    .join(", ");
-                println!("def {name} (c: ValidCircuit P P_Prime): Prop := ∃ shuffle, is_shuffle c shuffle ∧ (∀ row : ℕ, row < c.usable_rows → ({lhs}) = ({rhs}))");
-            }
-    
-            let all_shuffles_body = if shuffle_names.is_empty() {
-                "true".to_string()
-            } else {
-                shuffle_names
-                    .iter()
-                    .map(|name| format!("{name} c"))
-                    .join(" ∧ ")
-            };
-            println!("def all_shuffles (c: ValidCircuit P P_Prime) : Prop := {all_shuffles_body}");
+            .collect();
+
+        let shuffles = cs
+            .shuffles()
+            .iter()
+            .map(|shuffle| Shuffle {
+                name: shuffle.name().to_string(),
+                inputs: shuffle.input_expressions().iter().map(Into::into).collect(),
+                shuffles: shuffle.shuffle_expressions().iter().map(Into::into).collect(),
+            })
+            .collect();
+
+        CircuitModel {
+            version: MODEL_VERSION,
+            namespace: namespace.to_string(),
+            modulus: F::modulus().map(|modulus| modulus.to_string()),
+            symbols,
+            num_advice_columns: cs.num_advice_columns(),
+            num_fixed_columns: cs.num_fixed_columns(),
+            num_instance_columns: cs.num_instance_columns(),
+            num_selectors: cs.num_selectors(),
+            advice_phases: cs.advice_column_phase(),
+            minimum_rows: cs.minimum_rows(),
+            blinding_factors: cs.blinding_factors(),
+            usable_rows: self.usable_rows(),
+            regions: self.regions,
+            region_selectors: self.region_selectors,
+            copies: self.copies,
+            selectors: self.selectors,
+            fixed: self.fixed,
+            fixed_fill: self.fixed_fill
+                .into_iter()
+                .map(|(col, (row, value))| (col, FixedFill { row, value }))
+                .collect(),
+            advice_annotations: self.advice_column_annotations,
+            fixed_annotations: self.fixed_column_annotations,
+            instance_annotations: self.instance_column_annotations,
+            gates,
+            lookups,
+            shuffles,
+            assumptions: take_assumptions(),
+            policy_report: take_policy_report(),
         }
     }
 
@@ -447,7 +183,7 @@ impl ExtractingAssignment<TermField> {
     }
 
     // Assign a cell in the fixed map, adjusting fixed_fill if necessary
-    fn set_fixed_checked(&mut self, col: usize, row: usize, val: String) {
+    fn set_fixed_checked(&mut self, col: usize, row: usize, val: Term) {
         let fill = self.fixed_fill.get(&col);
 
         // If assigning a cell beyond the fill, push the fill back and write it into the map
@@ -463,7 +199,7 @@ impl ExtractingAssignment<TermField> {
 
     // Assign a cell into the fixed map, creating a new inner map if necessary
     // Does not check fixed_fill
-    fn set_fixed_unchecked(&mut self, col: usize, row: usize, val: String) {
+    fn set_fixed_unchecked(&mut self, col: usize, row: usize, val: Term) {
         let fixed_column_opt = self.fixed.get_mut(&col);
 
         if let Some(fixed_column) = fixed_column_opt {
@@ -475,7 +211,7 @@ impl ExtractingAssignment<TermField> {
         };
     }
 
-    fn set_fixed_fill(&mut self, col: usize, row: usize, val: String) {
+    fn set_fixed_fill(&mut self, col: usize, row: usize, val: Term) {
         // Insert the new fill, and get the old one
         let old_fill = self.fixed_fill.insert(col, (row, val));
 
@@ -490,16 +226,64 @@ impl ExtractingAssignment<TermField> {
         }
     }
 
-    pub fn run<ConcreteCircuit: Circuit<TermField>>(
+    /// Every registered symbol, with those in `order` first and the rest in the order they were created
+    ///
+    /// Symbols in `order` that haven't been created are included as scalars
+    pub fn resolve_symbols(order: &[&str]) -> Result<Vec<SymbolDecl>, ExtractionError> {
+        if let Some(name) = conflicting_symbols().into_iter().next() {
+            return Err(ExtractionError::InvalidSymbol { name, reason: "created more than once with a different length or domain" });
+        }
+
+        let registered = registered_symbols();
+        let mut symbols: Vec<SymbolDecl> = vec![];
+        for name in order {
+            if symbols.iter().any(|symbol| symbol.name == *name) {
+                return Err(ExtractionError::InvalidSymbol { name: name.to_string(), reason: "listed more than once" });
+            }
+            let decl = registered
+                .iter()
+                .find(|symbol| symbol.name == *name)
+                .cloned()
+                .unwrap_or_else(|| SymbolDecl::scalar(name));
+            symbols.push(decl);
+        }
+        for decl in registered {
+            if !symbols.iter().any(|symbol| symbol.name == decl.name) {
+                symbols.push(decl);
+            }
+        }
+        for symbol in &symbols {
+            validate_symbol_name(&symbol.name).map_err(|reason| ExtractionError::InvalidSymbol { name: symbol.name.clone(), reason })?;
+        }
+        Ok(symbols)
+    }
+
+    /// Synthesize `circuit` and collect its constraints into a `CircuitModel` without printing anything
+    ///
+    /// The model includes the symbols created on this thread since the last extraction, which are then forgotten, so
+    /// create a circuit's symbols again to extract it a second time
+    pub fn extract_model<ConcreteCircuit: Circuit<F>>(
         circuit: &ConcreteCircuit,
         namespace: &str,
-        symbol_names: &[&str]
-    ) -> Result<(), Error> {
-        
+        symbol_names: &[&str],
+    ) -> Result<CircuitModel, ExtractionError> {
+        let model = Self::synthesize_model(circuit, namespace, symbol_names);
+        clear_symbols();
+        model
+    }
+
+    fn synthesize_model<ConcreteCircuit: Circuit<F>>(
+        circuit: &ConcreteCircuit,
+        namespace: &str,
+        symbol_names: &[&str],
+    ) -> Result<CircuitModel, ExtractionError> {
+        // Anything answered before the extraction, such as while the circuit was built, isn't part of it
+        take_policy_report();
+        take_assumptions();
+
         let mut cs = ConstraintSystem::default();
         let config = ConcreteCircuit::configure_with_params(&mut cs, circuit.params());
         let cs = cs;
-        print_preamble(namespace, symbol_names, &cs);
 
         let mut prover = ExtractingAssignment::new();
 
@@ -510,29 +294,103 @@ impl ExtractingAssignment<TermField> {
                 circuit,
                 config.clone(),
                 cs.constants().clone(),
-            )?;
+            ).map_err(Error::from)?;
         }
 
-        prover.print_grouping_props(&cs);
+        let symbols = Self::resolve_symbols(symbol_names)?;
+        let model = prover.into_model(namespace, symbols, &cs);
+        let report = &model.policy_report;
+        let assumed = report.equality.assumed + report.ordering.assumed + report.inversion.assumed;
+        if assumed > 0 {
+            log::info!("The extraction policy answered {assumed} questions about symbolic values, recording {} distinct assumptions", model.assumptions.len());
+        }
+        Ok(model)
+    }
 
-        print_postamble(namespace, &cs);
+    /// Extract `circuit` into the Lean namespace `namespace`, writing the generated Lean to `out`
+    ///
+    /// Every symbol created with `create_symbol` is included automatically. `symbol_names` only overrides their order,
+    /// and can be left empty.
+    ///
+    /// The model the Lean was printed from is returned for any further processing
+    pub fn run<ConcreteCircuit: Circuit<F>>(
+        circuit: &ConcreteCircuit,
+        namespace: &str,
+        symbol_names: &[&str],
+        out: &mut impl Write,
+    ) -> Result<CircuitModel, ExtractionError> {
+        // Synthesize before writing anything, so a failing circuit doesn't leave partial output in the sink
+        let model = Self::extract_model(circuit, namespace, symbol_names)?;
+        print_circuit(out, &model)?;
+        out.flush()?;
+        Ok(model)
+    }
+
+    /// Extract `circuit` and return the generated Lean as a string
+    pub fn extract_to_string<ConcreteCircuit: Circuit<F>>(
+        circuit: &ConcreteCircuit,
+        namespace: &str,
+        symbol_names: &[&str],
+    ) -> Result<String, ExtractionError> {
+        let mut buffer = vec![];
+        Self::run(circuit, namespace, symbol_names, &mut buffer)?;
+        Ok(String::from_utf8(buffer).expect("Extracted Lean is always valid UTF-8"))
+    }
+
+    /// Extract `circuit` and write the generated Lean to the file at `path`, replacing any existing contents
+    pub fn extract_to_file<ConcreteCircuit: Circuit<F>>(
+        circuit: &ConcreteCircuit,
+        namespace: &str,
+        symbol_names: &[&str],
+        path: impl AsRef<Path>,
+    ) -> Result<(), ExtractionError> {
+        let mut out = BufWriter::new(File::create(path)?);
+        Self::run(circuit, namespace, symbol_names, &mut out)?;
         Ok(())
+    }
+
+    /// Extract `circuit` and write the generated Lean as a tree of modules under `dir`, see `lean::write_modules`
+    ///
+    /// Use this rather than `extract_to_file` for large circuits, whose single file Lean elaborates slowly
+    pub fn extract_to_dir<ConcreteCircuit: Circuit<F>>(
+        circuit: &ConcreteCircuit,
+        namespace: &str,
+        symbol_names: &[&str],
+        dir: impl AsRef<Path>,
+    ) -> Result<CircuitModel, ExtractionError> {
+        let model = Self::extract_model(circuit, namespace, symbol_names)?;
+        write_modules(dir, &model)?;
+        Ok(model)
+    }
 
+    /// Extract `circuit` into the Lake project `project`, creating the project if it doesn't exist yet
+    ///
+    /// Files that are not generated, such as `Spec.lean`, are left alone when extracting again
+    pub fn extract_to_project<ConcreteCircuit: Circuit<F>>(
+        circuit: &ConcreteCircuit,
+        namespace: &str,
+        symbol_names: &[&str],
+        project: &LakeProject,
+    ) -> Result<CircuitModel, ExtractionError> {
+        let model = Self::extract_model(circuit, namespace, symbol_names)?;
+        project.write(&model)?;
+        Ok(model)
+    }
+
+    /// The number of rows the circuit requires to be usable, based on the cells touched so far
+    pub fn usable_rows(&self) -> usize {
+        self.usable_rows.get()
     }
 
     fn assert_row_usable(&self, row: usize) {
-        let usable_rows = str::parse::<usize>(
-            &fs::read_to_string(&self.usable_rows_filename)
-                .expect("Failed to read usable_rows file")
-        ).expect("Failed to parse contents of usable_rows file");
-        if row >= usable_rows {
+        if row >= self.usable_rows.get() {
             // row+1 because of 0-indexing
-            fs::write(&self.usable_rows_filename, (row+1).to_string()).expect("Failed to write usable_rows file");
+            self.usable_rows.set(row+1);
         }
     }
 }
 
-impl Assignment<TermField> for ExtractingAssignment<TermField>
+impl<F: SymbolicField> Assignment<F> for ExtractingAssignment<F>
 {
     fn enter_region<NR, N>(&mut self, name_fn: N)
     where
@@ -540,13 +398,16 @@ impl Assignment<TermField> for ExtractingAssignment<TermField>
         N: FnOnce() -> NR,
     {
         let x: String = name_fn().into();
-        println!("\n-- Entered region: {x}");
+        self.regions.push(RegionEvent::Entered(x.clone()));
+        self.region_selectors.push(RegionSelectors {
+            name: x.clone(),
+            enabled: vec![],
+        });
         self.current_region = Some(x.clone());
     }
 
     fn exit_region(&mut self) {
-        println!("-- Exited region: {}", self.current_region.as_ref().unwrap());
-        self.current_region = None;
+        self.regions.push(RegionEvent::Exited(self.current_region.take().unwrap()));
     }
 
     fn enable_selector<A, AR>(
@@ -560,12 +421,17 @@ impl Assignment<TermField> for ExtractingAssignment<TermField>
         AR: Into<String>,
     {
         if !self.in_phase(FirstPhase) {
-            println!("--WARNING: Attempted to assign selector {} {} outside or first phase", selector.index(), row);
+            log::warn!("Attempted to assign selector {} {} outside of first phase", selector.index(), row);
             return Ok(());
         }
 
         self.assert_row_usable(row);
 
+        if self.current_region.is_some() {
+            if let Some(region) = self.region_selectors.last_mut() {
+                region.enabled.push((selector.index(), row));
+            }
+        }
         self.set_selector(selector.index(), row, annotation().into());
         Ok(())
     }
@@ -574,14 +440,10 @@ impl Assignment<TermField> for ExtractingAssignment<TermField>
         &self,
         column: Column<Instance>,
         row: usize,
-    ) -> Result<Value<TermField>, halo2_frontend::plonk::Error> {
+    ) -> Result<Value<F>, halo2_frontend::plonk::Error> {
         self.assert_row_usable(row);
 
-        Ok(Value::known(TermField::from(format!(
-            "instance_to_field (c.1.Instance {} {})",
-            column.index(),
-            row
-        ))))
+        Ok(Value::known(F::instance(column.index(), row)))
     }
 
     fn assign_advice<V, VR, A, AR>(
@@ -593,7 +455,7 @@ impl Assignment<TermField> for ExtractingAssignment<TermField>
     ) -> Result<(), halo2_frontend::plonk::Error>
     where
         V: FnOnce() -> Value<VR>,
-        VR: Into<Assigned<TermField>>,
+        VR: Into<Assigned<F>>,
         A: FnOnce() -> AR,
         AR: Into<String>,
     {
@@ -617,7 +479,7 @@ impl Assignment<TermField> for ExtractingAssignment<TermField>
     ) -> Result<(), halo2_frontend::plonk::Error>
     where
         V: FnOnce() -> Value<VR>,
-        VR: Into<Assigned<TermField>>,
+        VR: Into<Assigned<F>>,
         A: FnOnce() -> AR,
         AR: Into<String>,
     {
@@ -632,7 +494,7 @@ impl Assignment<TermField> for ExtractingAssignment<TermField>
             self.set_fixed_checked(
                 column.index(),
                 row,
-                v.into().evaluate().to_string()
+                v.into().evaluate().to_term()
             );
         });
         Ok(())
@@ -652,7 +514,10 @@ impl Assignment<TermField> for ExtractingAssignment<TermField>
         self.assert_row_usable(left_row);
         self.assert_row_usable(right_row);
 
-        self.copies.push(((left_column, left_row), (right_column, right_row)));
+        self.copies.push(CopyConstraint {
+            left: model::Cell::new(&left_column, left_row),
+            right: model::Cell::new(&right_column, right_row),
+        });
         Ok(())
     }
 
@@ -660,7 +525,7 @@ impl Assignment<TermField> for ExtractingAssignment<TermField>
         &mut self,
         column: Column<Fixed>,
         row: usize,
-        to: Value<Assigned<TermField>>,
+        to: Value<Assigned<F>>,
     ) -> Result<(), halo2_frontend::plonk::Error> {
         if !self.in_phase(FirstPhase) {
             return Ok(());
@@ -668,7 +533,7 @@ impl Assignment<TermField> for ExtractingAssignment<TermField>
 
         self.assert_row_usable(row);
 
-        let fill_val = to.assign()?.evaluate().to_string();
+        let fill_val = to.assign()?.evaluate().to_term();
         self.set_fixed_fill(column.index(), row, fill_val);
         Ok(())
     }
@@ -694,132 +559,212 @@ impl Assignment<TermField> for ExtractingAssignment<TermField>
         };
     }
 
-    fn get_challenge(&self, challenge: halo2_proofs::plonk::Challenge) -> Value<TermField> {
-        Value::known(TermField::from(format!("c.get_challenge {} {}", challenge.index(), challenge.phase())))
+    fn get_challenge(&self, challenge: halo2_proofs::plonk::Challenge) -> Value<F> {
+        Value::known(F::challenge(challenge.index(), challenge.phase()))
     }
 }
 
-pub fn print_preamble(namespace: &str, symbol_names: &[&str], cs: &ConstraintSystem<TermField>) {
-    println!("import Mathlib.Data.Nat.Prime.Defs");
-    println!("import Mathlib.Data.Nat.Prime.Basic");
-    println!("import Mathlib.Data.ZMod.Defs");
-    println!("import Mathlib.Data.ZMod.Basic\n");
-
-    println!("set_option linter.unusedVariables false\n");
-
-    println!("namespace {namespace}\n");
-
-    println!("def S_T_from_P (S T P : ℕ) : Prop :=");
-    println!("  (2^S * T = P - 1) ∧");
-    println!("  (∀ s' t': ℕ, 2^s' * t' = P - 1 → s' ≤ S)");
-    
-    println!("def multiplicative_generator (P: ℕ) (mult_gen: ZMod P) : Prop :=");
-    println!("  mult_gen ^ P = 1");
-    
-    println!("structure Circuit (P: ℕ) (P_Prime: Nat.Prime P) where");
-    println!("  Advice: ℕ → ℕ → ZMod P");
-    println!("  AdviceUnassigned: ℕ → ℕ → ZMod P");
-    println!("  AdvicePhase: ℕ → ℕ");
-    println!("  Fixed: ℕ → ℕ → ZMod P");
-    println!("  FixedUnassigned: ℕ → ℕ → ZMod P");
-    println!("  Instance: ℕ → ℕ → ZMod P");
-    println!("  InstanceUnassigned: ℕ → ℕ → ZMod P");
-    println!("  Selector: ℕ → ℕ → ZMod P");
-    println!("  Challenges: (ℕ → ℕ → ZMod P) → ℕ → ℕ → ZMod P");
-    println!("  num_blinding_factors: ℕ");
-    println!("  S: ℕ");
-    println!("  T: ℕ");
-    println!("  k: ℕ");
-    println!("  mult_gen: ZMod P");
-    for symbol_name in symbol_names {
-        println!("  sym_{symbol_name}: ZMod P")
-    }
-    
-    println!("variable {{P: ℕ}} {{P_Prime: Nat.Prime P}}");
-    println!("def Circuit.isValid (c: Circuit P P_Prime) : Prop :=");
-    println!("  S_T_from_P c.S c.T P ∧");
-    println!("  multiplicative_generator P c.mult_gen ∧ (");
-    println!("  ∀ advice1 advice2: ℕ → ℕ → ZMod P, ∀ phase: ℕ,");
-    println!("    (∀ row col, (col < {} ∧ c.AdvicePhase col ≤ phase) → advice1 col row = advice2 col row) →", cs.num_advice_columns());
-    println!("    (∀ i, c.Challenges advice1 i phase = c.Challenges advice2 i phase)");
-    println!("  )");
-
-    println!("abbrev ValidCircuit (P: ℕ) (P_Prime: Nat.Prime P) : Type := {{c: Circuit P P_Prime // c.isValid}}");
-    println!("namespace ValidCircuit");
-    println!("def get_advice (c: ValidCircuit P P_Prime) : ℕ → ℕ → ZMod P :=");
-    println!("  λ col row => c.1.Advice col row");
-    println!("def get_fixed (c: ValidCircuit P P_Prime) : ℕ → ℕ → ZMod P :=");
-    println!("  λ col row => c.1.Fixed col row");
-    println!("def get_instance (c: ValidCircuit P P_Prime) : ℕ → ℕ → ZMod P :=");
-    println!("  λ col row => c.1.Instance col row");
-    println!("def get_selector (c: ValidCircuit P P_Prime) : ℕ → ℕ → ZMod P :=");
-    println!("  λ col row => c.1.Selector col row");
-    println!("def get_challenge (c: ValidCircuit P P_Prime) : ℕ → ℕ → ZMod P :=");
-    println!("  λ idx phase => c.1.Challenges c.1.Advice idx phase");
-    println!("def k (c: ValidCircuit P P_Prime) := c.1.k");
-    println!("def n (c: ValidCircuit P P_Prime) := 2^c.k");
-    println!("def usable_rows (c: ValidCircuit P P_Prime) := c.n - (c.1.num_blinding_factors + 1)");
-    println!("def S (c: ValidCircuit P P_Prime) := c.1.S");
-    println!("def T (c: ValidCircuit P P_Prime) := c.1.T");
-    println!("def mult_gen (c: ValidCircuit P P_Prime) := c.1.mult_gen");
-    println!("def root_of_unity (c: ValidCircuit P P_Prime) : ZMod P := c.mult_gen ^ c.T");
-    println!("def delta (c: ValidCircuit P P_Prime) : ZMod P := c.mult_gen ^ (2^c.S)");
-    println!("end ValidCircuit");
-
-    println!("def is_shuffle (c: ValidCircuit P P_Prime) (shuffle: ℕ → ℕ): Prop :=");
-    println!("  ∃ inv: ℕ → ℕ,");
-    println!("  ∀ row: ℕ,");
-    println!("    inv (shuffle row) = row ∧");
-    println!("    (row ≥ c.usable_rows → shuffle row = row)");
-
-    println!("def sufficient_rows (c: ValidCircuit P P_Prime) : Prop :=");
-    println!("  c.n ≥ {} --cs.minimum_rows", cs.minimum_rows());
-
-    println!("--End preamble");
-}
+#[cfg(test)]
+mod test {
+    use halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner, Value},
+        plonk::{Advice, Column, ErrorFront, Expression, Selector},
+        poly::Rotation,
+    };
 
-pub fn print_postamble(name: &str, cs: &ConstraintSystem<TermField>) {
-    let usable_rows = str::parse::<usize>(&fs::read_to_string("./usable_rows").expect("Failed to read usable_rows")).expect("Failed to parse usable_rows");
-
-    println!("def meets_constraints (c: ValidCircuit P P_Prime): Prop :=");
-    println!("  sufficient_rows c ∧");
-    println!("  c.1.num_blinding_factors = {} ∧", cs.blinding_factors());
-    println!("  c.1.Selector = selector_func c ∧");
-    println!("  c.1.Fixed = fixed_func c ∧");
-    println!("  c.1.AdvicePhase = advice_phase c ∧");
-    println!("  c.usable_rows ≥ {usable_rows} ∧");
-    println!("  all_gates c ∧");
-    println!("  all_copy_constraints c ∧");
-    println!("  all_lookups c ∧");
-    println!("  all_shuffles c ∧");
-    println!("  ∀ col row: ℕ, (row < c.n ∧ row ≥ c.usable_rows) → c.1.Instance col row = c.1.InstanceUnassigned col row");
-    println!("end {name}");
-}
+    use crate::field::{install_policy, ExtractionPolicy, SymbolDomain, TermField};
+    use crate::model::ModelExpr;
 
-pub fn expression_to_value_string(expr: &Expression<TermField>, row_name: &str) -> String {
-    let format_lookup = |identifier, column, rotation: i32| {
-        if rotation == 0 {
-            format!("{} {} {row_name}", identifier, column)
-        } else if rotation > 0 {
-            format!("{} {} (({row_name} + {}) % c.n)", identifier, column, rotation)
-        } else {
-            format!("{} {} (({row_name} + c.n - ({} % c.n)) % c.n)", identifier, column, -rotation)
+    use super::*;
+
+    #[derive(Clone, Debug)]
+    struct CounterConfig {
+        q_step: Selector,
+        count: Column<Advice>,
+    }
+
+    /// Counts from 0 in `rows` rows, checking each row is one more than the previous
+    struct CounterCircuit {
+        rows: usize,
+    }
+
+    impl Circuit<TermField> for CounterCircuit {
+        type Config = CounterConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+        type Params = ();
+
+        fn without_witnesses(&self) -> Self {
+            Self { rows: self.rows }
         }
-    };
 
-    match expr {
-        Expression::Constant(value) => format!("({})", value),
-        Expression::Selector(selector) => format!("c.get_selector {} {row_name}", selector.0),
-        Expression::Fixed(query) => format_lookup("c.get_fixed", query.column_index(), query.rotation().0),
-        Expression::Advice(query) => format_lookup("c.get_advice", query.column_index(), query.rotation().0),
-        Expression::Instance(query) => format_lookup("c.get_instance", query.column_index(), query.rotation().0),
-        Expression::Challenge(challenge) => format!("c.get_challenge {} {}", challenge.index(), challenge.phase()),
-        Expression::Negated(expression) => format!("-({})", expression_to_value_string(expression, row_name)),
-        Expression::Sum(expression, expression1) =>
-            format!("({}) + ({})", expression_to_value_string(expression, row_name), expression_to_value_string(expression1, row_name)),
-        Expression::Product(expression, expression1) =>
-            format!("({}) * ({})", expression_to_value_string(expression, row_name), expression_to_value_string(expression1, row_name)),
-        Expression::Scaled(expression, factor) =>
-        format!("({}) * ({})", factor.to_string(), expression_to_value_string(expression, row_name)),
+        fn configure(meta: &mut ConstraintSystem<TermField>) -> Self::Config {
+            let q_step = meta.selector();
+            let count = meta.advice_column();
+            meta.create_gate("step", |meta| {
+                let q_step = meta.query_selector(q_step);
+                let cur = meta.query_advice(count, Rotation::cur());
+                let next = meta.query_advice(count, Rotation::next());
+                vec![q_step * (next - cur - Expression::Constant(TermField::ONE))]
+            });
+            CounterConfig { q_step, count }
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<TermField>,
+        ) -> Result<(), ErrorFront> {
+            layouter.assign_region(
+                || "counter",
+                |mut region| {
+                    for row in 0..self.rows {
+                        if row + 1 < self.rows {
+                            config.q_step.enable(&mut region, row)?;
+                        }
+                        region.assign_advice(
+                            || "count",
+                            config.count,
+                            row,
+                            || Value::known(TermField::from(row as u64)),
+                        )?;
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    /// Inverts a symbol, which can't be decided without assuming it is non-zero
+    struct InvertingCircuit;
+
+    impl Circuit<TermField> for InvertingCircuit {
+        type Config = ();
+        type FloorPlanner = SimpleFloorPlanner;
+        type Params = ();
+
+        fn without_witnesses(&self) -> Self {
+            Self
+        }
+
+        fn configure(_meta: &mut ConstraintSystem<TermField>) -> Self::Config {}
+
+        fn synthesize(&self, _config: Self::Config, _layouter: impl Layouter<TermField>) -> Result<(), ErrorFront> {
+            let x = TermField::create_symbol("x");
+            x.invert().into_option().ok_or(ErrorFront::Synthesis)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sinks() {
+        let circuit = CounterCircuit { rows: 4 };
+        let lean = ExtractingAssignment::extract_to_string(&circuit, "Counter", &[]).unwrap();
+        assert!(lean.contains("namespace Counter\n"));
+        assert!(lean.contains("  ∀ row : ℕ, c.get_selector 0 row * (c.get_advice 0 ((row + 1) % c.n) + -c.get_advice 0 row + -1) = 0\n"));
+        assert!(lean.contains("  if row < 3 then 1\n  else 0\n"));
+        assert!(lean.contains("  c.usable_rows ≥ 4 ∧\n"));
+        assert!(lean.ends_with("end Counter\n"));
+
+        let path = std::env::temp_dir().join("halo2_extr_sinks_test.lean");
+        ExtractingAssignment::extract_to_file(&circuit, "Counter", &[], &path).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, lean.into_bytes());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut model = ExtractingAssignment::extract_model(&CounterCircuit { rows: 4 }, "Counter", &[]).unwrap();
+        let deep = |depth: usize| {
+            (0..depth).fold(ModelExpr::Advice { column: 0, rotation: 0 }, |expr, i| {
+                ModelExpr::Sum(Box::new(expr), Box::new(ModelExpr::Scaled(Box::new(ModelExpr::Selector(0)), Term::Const((i as i64).into()))))
+            })
+        };
+
+        model.gates[0].constraints[0].polynomial = deep(50);
+        assert_eq!(model, CircuitModel::from_json(&model.to_json().unwrap()).unwrap());
+
+        // Past serde_json's recursion limit the model is still written, but reading it back fails cleanly
+        model.gates[0].constraints[0].polynomial = deep(70);
+        let error = CircuitModel::from_json(&model.to_json().unwrap()).unwrap_err();
+        assert!(error.to_string().contains("recursion limit exceeded"));
+    }
+
+    #[test]
+    fn test_extraction_assumptions() {
+        let _guard = install_policy(ExtractionPolicy::recording());
+        let lean = ExtractingAssignment::extract_to_string(&InvertingCircuit, "Inverting", &[]).unwrap();
+        assert!(lean.contains(
+            "def extraction_assumptions (c : ValidCircuit P P_Prime) : Prop :=\n  -- assumed by invert\n  (c.1.sym_x) ≠ 0\n"
+        ));
+
+        // Without assumptions the definition is trivially true
+        let lean = ExtractingAssignment::extract_to_string(&CounterCircuit { rows: 2 }, "Counter", &[]).unwrap();
+        assert!(lean.contains("def extraction_assumptions (c : ValidCircuit P P_Prime) : Prop := True\n"));
+    }
+
+    #[test]
+    fn test_symbol_fields() {
+        TermField::create_symbol("b");
+        TermField::create_symbol("a");
+        TermField::create_symbol("b");
+        let lean = ExtractingAssignment::extract_to_string(&CounterCircuit { rows: 2 }, "Counter", &["a"]).unwrap();
+        assert!(lean.contains(concat!(
+            "structure Circuit (P : ℕ) (P_Prime : Nat.Prime P) extends Halva.Circuit P P_Prime where\n",
+            "  sym_a : ZMod P\n",
+            "  sym_b : ZMod P\n",
+            "variable",
+        )));
+
+        // The extraction forgot its symbols, so only those listed are declared in the next one
+        let model = ExtractingAssignment::extract_model(&CounterCircuit { rows: 2 }, "Counter", &["c"]).unwrap();
+        assert_eq!(model.symbols, [SymbolDecl::scalar("c")]);
+
+        let invalid = |order: &[&str]| match ExtractingAssignment::extract_model(&CounterCircuit { rows: 2 }, "Counter", order) {
+            Err(ExtractionError::InvalidSymbol { name, reason }) => (name, reason),
+            result => panic!("expected an invalid symbol, got {result:?}"),
+        };
+        assert_eq!(invalid(&["c", "c"]), ("c".to_string(), "listed more than once"));
+        TermField::create_symbol("not-an-identifier");
+        assert_eq!(invalid(&[]).0, "not-an-identifier");
+        TermField::create_symbol("d");
+        TermField::create_symbol_vec("d", 3);
+        assert_eq!(invalid(&[]), ("d".to_string(), "created more than once with a different length or domain"));
+    }
+
+    #[test]
+    fn test_symbol_domains() {
+        TermField::create_symbol_vec_in("preimage", 32, SymbolDomain::Byte);
+        TermField::create_symbol_in("flag", SymbolDomain::Boolean);
+        TermField::create_symbol_in("limb", SymbolDomain::Bits(64));
+        let state = TermField::create_symbol_vec("state", 4);
+        assert_eq!(state[2].to_term().to_string(), "c.1.sym_state 2");
+        let lean = ExtractingAssignment::extract_to_string(&CounterCircuit { rows: 2 }, "Counter", &[]).unwrap();
+        assert!(lean.contains(concat!(
+            "structure Circuit (P : ℕ) (P_Prime : Nat.Prime P) extends Halva.Circuit P P_Prime where\n",
+            "  sym_preimage : ℕ → ZMod P\n",
+            "  sym_flag : ZMod P\n",
+            "  sym_limb : ZMod P\n",
+            "  sym_state : ℕ → ZMod P\n",
+            "variable {P : ℕ} {P_Prime : Nat.Prime P}\n",
+            "def Circuit.isValid (c : Circuit P P_Prime) : Prop :=\n",
+            "  c.toCircuit.isValid 1 ∧\n",
+            "  (∀ i < 32, (c.sym_preimage i).val < 256) ∧\n",
+            "  (c.sym_flag = 0 ∨ c.sym_flag = 1) ∧\n",
+            "  ((c.sym_limb).val < 2^64)\n",
+        )));
+    }
+
+    #[test]
+    fn test_parallel_usable_rows() {
+        // Each extraction counts its own rows, so concurrent extractions don't see each other's cells
+        let models = std::thread::scope(|scope| {
+            let handles = [3, 10].map(|rows| {
+                scope.spawn(move || ExtractingAssignment::extract_model(&CounterCircuit { rows }, "Counter", &[]).unwrap())
+            });
+            handles.map(|handle| handle.join().unwrap())
+        });
+        assert_eq!(models[0].usable_rows, 3);
+        assert_eq!(models[1].usable_rows, 10);
     }
 }
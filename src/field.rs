@@ -4,60 +4,73 @@ use std::{
 
 use arrayvec::ArrayString;
 use ff::{Field, FromUniformBytes, PrimeField};
-use num_bigint::BigUint;
+use num_bigint::{BigInt, BigUint};
+use num_traits::{Signed, Zero};
 use subtle::{Choice, ConditionallySelectable, ConstantTimeEq, CtOption};
 
+mod assumption;
+mod modular;
+mod policy;
+mod shadow;
+mod symbol;
+mod term;
+
+pub use assumption::{take_assumptions, Assumption};
+pub use policy::{
+    current_policy, install_policy, take_policy_report, ExtractionPolicy, OperationCounts, Policy, PolicyGuard, PolicyReport, ReprPolicy,
+};
+use policy::{Operation, Outcome};
+pub use modular::ModularTermField;
+pub use shadow::{install_sample_witness, SampleWitness, ShadowFunction, ShadowGuard};
+pub use symbol::{clear_symbols, conflicting_symbols, registered_symbols, validate_symbol_name, SymbolDecl, SymbolDomain};
+pub use term::{arena, Builtin, Term, TermArena, TermId, TermNode};
+
 const EXPRESSION_MAX_SIZE: usize = 16384;
 
+/// A field whose elements are terms the extractor can read back out
+///
+/// Implemented by `TermField`, whose modulus is the symbolic `P`, and `ModularTermField`, which is bound to a concrete prime
+pub trait SymbolicField: Field {
+    /// The value of an instance cell
+    fn instance(column: usize, row: usize) -> Self;
+
+    /// The value of a challenge
+    fn challenge(index: usize, phase: u8) -> Self;
+
+    /// An owned copy of this term, for handing to a backend
+    fn to_term(&self) -> Term;
+
+    /// The concrete prime the field is bound to, or None if the modulus is symbolic
+    fn modulus() -> Option<BigUint>;
+}
+
 // Field requires Copy, Sized, and 'static
-// This means we have to use a stack allocated, dynamically generatable, fixed length string
+// Small constants are stored inline, everything else is a handle to a node in the global term arena
 #[derive(Clone, Copy)]
 pub enum TermField {
     Val(i64),
-    Expr(*const String),
-    TwoInv,
-    MultiplicativeGenerator,
-    S,
-    RootOfUnity,
-    RootOfUnityInv,
-    Delta
+    Expr(TermId),
 }
 
 impl PartialEq for TermField {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::Val(x), Self::Val(y)) => if x == y {
-                true
-            } else { // Two identical values are equal, but distinct values could still be equal because the field modulus is symbolic
-                #[cfg(not(feature = "unsafe-equality"))]
-                panic!("Unable to determine whether {} and {} are equal without the unsafe-equality feature (which will consider them not equal)", x, y);
-
-                #[cfg(feature = "unsafe-equality")]
-                false
-            }
-            (Self::Expr(l0), Self::Expr(r0)) => {
-                unsafe {
-                    if l0 == r0 {
-                        true
-                    } else {
-                        #[cfg(not(feature = "unsafe-equality"))]
-                        panic!("Unable to determine whether {} and {} are equal without the unsafe-equality feature (which will consider them not equal)", **l0, **r0);
-        
-                        #[cfg(feature = "unsafe-equality")]
-                        false
-                    }
-                }
-            },
-            _ => if core::mem::discriminant(self) == core::mem::discriminant(other) {
-                true
-            } else {
-                #[cfg(not(feature = "unsafe-equality"))]
-                panic!("Unable to determine whether {} and {} are equal without the unsafe-equality feature (which will consider them not equal)", self.to_expr(), other.to_expr());
-        
-                #[cfg(feature = "unsafe-equality")]
-                false
-            }
+        if self.is_identical(other) {
+            policy::count(Operation::Equality, Outcome::Decided);
+            return true;
+        }
+
+        // Distinct terms could still be equal because the field modulus is symbolic, unless a sample witness decides it
+        if let (Some(x), Some(y)) = (self.shadow(), other.shadow()) {
+            policy::count(Operation::Equality, Outcome::Shadowed);
+            return x == y;
         }
+
+        policy::assume(
+            Operation::Equality,
+            || format!("Unable to determine whether {} and {} are equal. Set the equality policy to Assume or Record to consider them not equal", self, other),
+            || Assumption::NotEqual(self.to_term(), other.to_term()),
+        );
+        false
     }
 }
 
@@ -66,21 +79,16 @@ impl Eq for TermField {}
 impl Debug for TermField {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
-            TermField::Val(_) => write!(f, "Val: {}", self.to_expr()),
-            TermField::Expr(_) => write!(f, "Expr: {}", self.to_expr()),
-            TermField::TwoInv => write!(f, "TwoInv: {}", self.to_expr()),
-            TermField::MultiplicativeGenerator => write!(f, "MGen: {}", self.to_expr()),
-            TermField::S => write!(f, "S: {}", self.to_expr()),
-            TermField::RootOfUnity => write!(f, "RootOfUnity: {}", self.to_expr()),
-            TermField::RootOfUnityInv => write!(f, "RootOfUnityInv: {}", self.to_expr()),
-            TermField::Delta => write!(f, "Delta: {}", self.to_expr()),
+            TermField::Val(_) => write!(f, "Val: {}", self),
+            TermField::Expr(_) => write!(f, "Expr: {}", self),
         }
     }
 }
 
+// Renders as Lean
 impl Display for TermField {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.to_expr())
+        write!(f, "{}", self.to_term())
     }
 }
 
@@ -94,45 +102,179 @@ impl TermField {
     }
 
     pub const fn two_inv() -> Self {
-        TermField::TwoInv
+        TermField::Expr(TermId::TWO_INV)
     }
 
-    // Note that this requires the symbol also be passed to print_preamble
+    /// A symbolic value, which becomes the field `sym_{name}` of the Lean `Circuit` structure
     pub fn create_symbol(name: &str) -> Self {
-        TermField::from(format!("c.1.sym_{name}"))
+        symbol::register(SymbolDecl::scalar(name));
+        Self::intern(TermNode::Symbol(name.to_string()))
     }
 
-    fn to_expr(&self) -> String {
-        match self {
-            TermField::Val(x) => x.to_string(),
-            TermField::Expr(x) => {
-                unsafe {
-                    (**x).clone()
-                }
-            }
-            TermField::TwoInv => String::from("(2: ZMod P).inv"),
-            TermField::MultiplicativeGenerator => String::from("c.mult_gen"),
-            TermField::S => String::from("c.S"),
-            TermField::RootOfUnity => String::from("c.root_of_unity"),
-            TermField::RootOfUnityInv => String::from("c.root_of_unity.inv"),
-            TermField::Delta => String::from("c.delta"),
-        }
+    /// A symbolic value known to lie in `domain`
+    pub fn create_symbol_in(name: &str, domain: SymbolDomain) -> Self {
+        symbol::register(SymbolDecl {
+            domain: Some(domain),
+            ..SymbolDecl::scalar(name)
+        });
+        Self::intern(TermNode::Symbol(name.to_string()))
+    }
+
+    /// `len` symbolic values, which become the single field `sym_{name} : ℕ → ZMod P` of the Lean `Circuit` structure
+    pub fn create_symbol_vec(name: &str, len: usize) -> Vec<Self> {
+        Self::symbol_vec(name, len, None)
+    }
+
+    /// `len` symbolic values, each known to lie in `domain`
+    pub fn create_symbol_vec_in(name: &str, len: usize, domain: SymbolDomain) -> Vec<Self> {
+        Self::symbol_vec(name, len, Some(domain))
+    }
+
+    fn symbol_vec(name: &str, len: usize, domain: Option<SymbolDomain>) -> Vec<Self> {
+        symbol::register(SymbolDecl {
+            name: name.to_string(),
+            len: Some(len),
+            domain,
+        });
+        (0..len)
+            .map(|index| Self::intern(TermNode::SymbolAt(name.to_string(), index)))
+            .collect()
+    }
+
+    /// The value of an instance cell
+    pub fn instance(column: usize, row: usize) -> Self {
+        Self::intern(TermNode::Instance { column, row })
+    }
+
+    /// The value of a challenge
+    pub fn challenge(index: usize, phase: u8) -> Self {
+        Self::intern(TermNode::Challenge { index, phase })
+    }
+
+    /// An uninterpreted function `name` applied to `args`, which has to be defined on the Lean side
+    pub fn apply(name: &str, args: &[TermField]) -> Self {
+        let mut arena = arena();
+        let args = args.iter().map(|arg| arg.id(&mut arena)).collect();
+        Self::Expr(arena.intern(TermNode::Apply(name.to_string(), args)))
     }
 
     pub fn create_s() -> Self {
         Self::from("S")
     }
+
+    /// An owned copy of this term, for handing to a backend
+    pub fn to_term(&self) -> Term {
+        match self {
+            TermField::Val(x) => Term::Const((*x).into()),
+            TermField::Expr(id) => arena().to_term(*id),
+        }
+    }
+
+    fn intern(node: TermNode) -> Self {
+        Self::Expr(arena().intern(node))
+    }
+
+    // The arena id of this term, interning inline constants if necessary
+    fn id(&self, arena: &mut TermArena) -> TermId {
+        match self {
+            TermField::Val(x) => arena.intern(TermNode::Const((*x).into())),
+            TermField::Expr(id) => *id,
+        }
+    }
+
+    fn unary(op: fn(TermId) -> TermNode, x: Self) -> Self {
+        let mut arena = arena();
+        let x = x.id(&mut arena);
+        Self::Expr(arena.intern(op(x)))
+    }
+
+    fn binary(op: fn(TermId, TermId) -> TermNode, lhs: Self, rhs: Self) -> Self {
+        let mut arena = arena();
+        let lhs = lhs.id(&mut arena);
+        let rhs = rhs.id(&mut arena);
+        Self::Expr(arena.intern(op(lhs, rhs)))
+    }
+
+    // Whether both are the same term. Constants are inline whenever they fit, so equal constants are always identical
+    fn is_identical(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Val(x), Self::Val(y)) => x == y,
+            // Terms are hash-consed, so identical ids mean structurally identical terms
+            (Self::Expr(l0), Self::Expr(r0)) => l0 == r0,
+            _ => false,
+        }
+    }
+
+    /// The exact value of this term, if it is a constant
+    pub fn constant(&self) -> Option<BigInt> {
+        match self {
+            TermField::Val(x) => Some((*x).into()),
+            TermField::Expr(id) => match arena().get(*id) {
+                TermNode::Const(x) => Some(x.clone()),
+                _ => None,
+            },
+        }
+    }
+
+    /// The concrete value of this term under the installed sample witness, if there is one
+    pub fn shadow(&self) -> Option<BigUint> {
+        match self {
+            // Inline constants are reduced directly rather than interned for the lookup
+            TermField::Val(x) => shadow::constant(&BigInt::from(*x)),
+            TermField::Expr(id) => shadow::value(*id),
+        }
+    }
+
+    fn builtin(&self) -> Option<Builtin> {
+        match self {
+            TermField::Val(_) => None,
+            TermField::Expr(id) => match arena().get(*id) {
+                TermNode::Builtin(builtin) => Some(*builtin),
+                _ => None,
+            },
+        }
+    }
+
+    // Constants are kept inline whenever they fit, so that equal constants always have the same representation
+    fn from_bigint(value: BigInt) -> Self {
+        match i64::try_from(&value) {
+            Ok(x) => Self::Val(x),
+            Err(_) => Self::intern(TermNode::Const(value)),
+        }
+    }
+
+    // Fold `op` over two constants exactly, or return None if either side is symbolic
+    fn fold(lhs: Self, rhs: Self, op: fn(BigInt, BigInt) -> BigInt) -> Option<Self> {
+        Some(Self::from_bigint(op(lhs.constant()?, rhs.constant()?)))
+    }
+}
+
+impl SymbolicField for TermField {
+    fn instance(column: usize, row: usize) -> Self {
+        TermField::instance(column, row)
+    }
+
+    fn challenge(index: usize, phase: u8) -> Self {
+        TermField::challenge(index, phase)
+    }
+
+    fn to_term(&self) -> Term {
+        TermField::to_term(self)
+    }
+
+    fn modulus() -> Option<BigUint> {
+        None
+    }
 }
 
 impl From<&str> for TermField {
     fn from(s: &str) -> Self {
-        let num = str::parse::<i64>(s);
-        if let Ok(val) = num {
+        if let Ok(val) = str::parse::<i64>(s) {
             Self::Val(val)
+        } else if let Ok(val) = str::parse::<BigInt>(s) {
+            Self::from_bigint(val)
         } else {
-            let heap_str = Box::new(String::from(s));
-            let ptr = Box::into_raw(heap_str).cast_const();
-            Self::Expr(ptr)
+            Self::intern(TermNode::Raw(s.to_string()))
         }
     }
 }
@@ -149,15 +291,32 @@ impl From<&String> for TermField {
     }
 }
 
+// The inverse of `to_term`, for terms read back from a model
+impl From<&Term> for TermField {
+    fn from(term: &Term) -> Self {
+        match term {
+            Term::Const(x) => Self::from_bigint(x.clone()),
+            Term::Symbol(name) => Self::intern(TermNode::Symbol(name.clone())),
+            Term::SymbolAt(name, index) => Self::intern(TermNode::SymbolAt(name.clone(), *index)),
+            Term::Builtin(builtin) => Self::intern(TermNode::Builtin(*builtin)),
+            Term::Add(lhs, rhs) => Self::binary(TermNode::Add, lhs.as_ref().into(), rhs.as_ref().into()),
+            Term::Mul(lhs, rhs) => Self::binary(TermNode::Mul, lhs.as_ref().into(), rhs.as_ref().into()),
+            Term::Neg(x) => Self::unary(TermNode::Neg, x.as_ref().into()),
+            Term::Inv(x) => Self::unary(TermNode::Inv, x.as_ref().into()),
+            Term::Instance { column, row } => Self::instance(*column, *row),
+            Term::Challenge { index, phase } => Self::challenge(*index, *phase),
+            Term::Apply(name, args) => Self::apply(name, &args.iter().map(Self::from).collect::<Vec<_>>()),
+            Term::Raw(text) => Self::intern(TermNode::Raw(text.clone())),
+        }
+    }
+}
+
 impl Default for TermField {
     fn default() -> Self {
         Self::zero()
     }
 }
 
-unsafe impl Sync for TermField {}
-unsafe impl Send for TermField {}
-
 impl ConditionallySelectable for TermField {
     fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
         if choice.unwrap_u8() == 0 {
@@ -182,10 +341,15 @@ impl Neg for TermField {
     type Output = Self;
 
     fn neg(self) -> Self::Output {
-        match self {
-            // i64's lower bound is -2^63, but its upper bound is 2^63-1
-            Self::Val(x) if x > 0x8000000000000000u64 as i64 => Self::Val(-x),
-            _ => Self::from(&format!("-({})", self.to_expr())),
+        // i64's lower bound is -2^63, but its upper bound is 2^63-1, so this can overflow
+        if let Self::Val(x) = self {
+            if let Some(res) = x.checked_neg() {
+                return Self::Val(res);
+            }
+        }
+        match self.constant() {
+            Some(x) => Self::from_bigint(-x),
+            None => Self::unary(TermNode::Neg, self),
         }
     }
 }
@@ -195,19 +359,10 @@ impl Add for TermField {
 
     fn add(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Self::Val(x), Self::Val(y)) => {
-                let big_x = x as i128;
-                let big_y = y as i128;
-                let big_res = big_x + big_y;
-                if let Ok(res) = TryInto::<i64>::try_into(big_res) {
-                    Self::Val(res)
-                } else {
-                    Self::from(format!("{}", big_res))
-                }
-            },
+            (Self::Val(x), Self::Val(y)) if x.checked_add(y).is_some() => Self::Val(x + y),
             (Self::Val(0), _) => rhs,
             (_, Self::Val(0)) => self,
-            _ => Self::from(&format!("({}) + ({})", self.to_expr(), rhs.to_expr())),
+            _ => Self::fold(self, rhs, |x, y| x + y).unwrap_or_else(|| Self::binary(TermNode::Add, self, rhs)),
         }
     }
 }
@@ -217,19 +372,10 @@ impl Sub for TermField {
 
     fn sub(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Self::Val(x), Self::Val(y)) => {
-                let big_x = x as i128;
-                let big_y = y as i128;
-                let big_res = big_x - big_y;
-                if let Ok(res) = TryInto::<i64>::try_into(big_res) {
-                    Self::Val(res)
-                } else {
-                    Self::from(format!("{}", big_res))
-                }
-            },
+            (Self::Val(x), Self::Val(y)) if x.checked_sub(y).is_some() => Self::Val(x - y),
             (Self::Val(0), _) => rhs.neg(),
             (_, Self::Val(0)) => self,
-            _ => Self::from(&format!("({}) - ({})", self.to_expr(), rhs.to_expr())),
+            _ => Self::fold(self, rhs, |x, y| x - y).unwrap_or_else(|| Self::binary(TermNode::Add, self, rhs.neg())),
         }
     }
 }
@@ -239,19 +385,10 @@ impl Mul for TermField {
 
     fn mul(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Self::Val(x), Self::Val(y)) => {
-                let big_x = x as i128;
-                let big_y = y as i128;
-                let big_res = big_x * big_y;
-                if let Ok(res) = TryInto::<i64>::try_into(big_res) {
-                    Self::Val(res)
-                } else {
-                    Self::from(format!("{}", big_res))
-                }
-            },
+            (Self::Val(x), Self::Val(y)) if x.checked_mul(y).is_some() => Self::Val(x * y),
             (Self::Val(1), _) => rhs,
             (_, Self::Val(1)) => self,
-            _ => Self::from(&format!("({}) * ({})", self.to_expr(), rhs.to_expr())),
+            _ => Self::fold(self, rhs, |x, y| x * y).unwrap_or_else(|| Self::binary(TermNode::Mul, self, rhs)),
         }
     }
 }
@@ -341,22 +478,45 @@ impl<'a> Product<&'a TermField> for TermField {
 }
 
 impl PartialOrd for TermField {
-    fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
-        #[cfg(not(feature = "unsafe-ord"))]
-        panic!("partial_cmp requires the unsafe-ord feature enabled because it cannot be calculated correctly for symbolic values. Enabling it will return a placeholder value (Less)");
-
-        #[cfg(feature = "unsafe-ord")]
-        Some(std::cmp::Ordering::Less)
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for TermField {
-    fn cmp(&self, _other: &Self) -> std::cmp::Ordering {
-        #[cfg(not(feature = "unsafe-ord"))]
-        panic!("cmp requires the unsafe-ord feature enabled because it cannot be calculated correctly for symbolic values. Enabling it will return a placeholder value (Less)");
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        if self.is_identical(other) {
+            policy::count(Operation::Ordering, Outcome::Decided);
+            return std::cmp::Ordering::Equal;
+        }
+        // A non-negative constant is its own value, as long as P is larger than any constant in the circuit
+        if let (Some(x), Some(y)) = (self.constant(), other.constant()) {
+            if !x.is_negative() && !y.is_negative() {
+                policy::count(Operation::Ordering, Outcome::Decided);
+                return x.cmp(&y);
+            }
+        }
+        if let (Some(x), Some(y)) = (self.shadow(), other.shadow()) {
+            policy::count(Operation::Ordering, Outcome::Shadowed);
+            return x.cmp(&y);
+        }
 
-        #[cfg(feature = "unsafe-ord")]
-        Some(std::cmp::Ordering::Less)
+        // A pair that was already given an order keeps it, so that both orientations are never assumed
+        let (lhs, rhs) = {
+            let mut arena = arena();
+            (self.id(&mut arena), other.id(&mut arena))
+        };
+        if let Some(order) = assumption::assumed_order(lhs, rhs) {
+            policy::count(Operation::Ordering, Outcome::Assumed);
+            return order;
+        }
+        policy::assume(
+            Operation::Ordering,
+            || "cmp cannot be calculated correctly for symbolic values. Set the ordering policy to Assume or Record to return a placeholder value (Less)".to_string(),
+            || Assumption::Less(self.to_term(), other.to_term()),
+        );
+        assumption::assume_less(lhs, rhs);
+        std::cmp::Ordering::Less
     }
 }
 
@@ -377,12 +537,19 @@ impl Field for TermField {
     }
 
     fn invert(&self) -> CtOption<Self> {
-        #[cfg(not(feature = "unsafe-invert"))]
-        panic!("Field::invert requires the unsafe-invert flag. This is because it is not always possible to determine whether a TermField is equal to zero");
+        // The inverse is still recorded symbolically, the sample witness only decides whether it exists
+        if let Some(x) = self.shadow() {
+            policy::count(Operation::Inversion, Outcome::Shadowed);
+            return CtOption::new(Self::unary(TermNode::Inv, *self), Choice::from(u8::from(!x.is_zero())));
+        }
 
-        #[cfg(feature = "unsafe-invert")]
+        policy::assume(
+            Operation::Inversion,
+            || "Field::invert cannot determine whether a TermField is equal to zero. Set the inversion policy to Assume or Record to always invert".to_string(),
+            || Assumption::NonZero(self.to_term()),
+        );
         CtOption::new(
-            Self::from(format!("(({}: ZMod P).inv)", self)),
+            Self::unary(TermNode::Inv, *self),
             Choice::from(1),
         )
     }
@@ -404,11 +571,7 @@ impl From<bool> for TermField {
 
 impl From<u64> for TermField {
     fn from(value: u64) -> Self {
-        match value {
-            0 => Self::zero(),
-            1 => Self::one(),
-            value => Self::from(value.to_string()),
-        }
+        Self::from_bigint(value.into())
     }
 }
 
@@ -436,33 +599,24 @@ impl AsRef<[u8]> for TermFieldBytes {
 impl PrimeField for TermField {
     type Repr = TermFieldBytes;
 
-    #[cfg(all(feature = "repr-text", feature = "repr-number"))]
-    fn from_repr(_repr: Self::Repr) -> CtOption<Self> {
-        compile_error!("features `halo2-extractor/repr-text` and `halo2-extractor/repr-number` are mutually exclusive");
-    }
-
-    #[cfg(not(any(feature = "repr-text", feature = "repr-number")))]
-    fn from_repr(_repr: Self::Repr) -> CtOption<Self> {        
-        panic!("from_repr requires either the repr-text flag or the repr-number feature to be enabled");
-    }
-
-    #[cfg(all(feature = "repr-number", not(feature = "repr-text")))]
     fn from_repr(repr: Self::Repr) -> CtOption<Self> {
-        let x = BigUint::from_bytes_le(&repr.0.as_bytes());
-        CtOption::new(Self::from(x.to_str_radix(10)), Choice::from(1))
-    }
-
-    #[cfg(all(feature = "repr-text", not(feature = "repr-number")))]
-    fn from_repr(repr: Self::Repr) -> CtOption<Self> {
-        CtOption::new(Self::Expr(repr.0), Choice::from(1))
+        policy::count_repr();
+        match current_policy().repr {
+            ReprPolicy::Unsupported => panic!("from_repr requires the Text or Number repr policy"),
+            ReprPolicy::Number => {
+                let x = BigUint::from_bytes_le(repr.0.as_bytes());
+                CtOption::new(Self::from_bigint(x.into()), Choice::from(1))
+            },
+            ReprPolicy::Text => CtOption::new(Self::from(repr.0.as_str()), Choice::from(1)),
+        }
     }
 
     fn to_repr(&self) -> Self::Repr {
-        #[cfg(not(feature = "repr-text"))]
-        unimplemented!("to_repr requires the repr-text feature");
-        
-        #[cfg(feature = "repr-text")]
-        TermFieldBytes(self.to_expr())
+        policy::count_repr();
+        match current_policy().repr {
+            ReprPolicy::Text => TermFieldBytes(ArrayString::from(&self.to_string()).expect("Term is too long to fit in a TermFieldBytes")),
+            _ => unimplemented!("to_repr requires the Text repr policy"),
+        }
     }
 
     fn is_odd(&self) -> Choice {
@@ -475,25 +629,25 @@ impl PrimeField for TermField {
 
     const CAPACITY: u32 = unimplemented!();
 
-    const TWO_INV: Self = Self::TwoInv;
+    const TWO_INV: Self = Self::two_inv();
 
-    const MULTIPLICATIVE_GENERATOR: Self = Self::MultiplicativeGenerator;
+    const MULTIPLICATIVE_GENERATOR: Self = Self::Expr(TermId::MULTIPLICATIVE_GENERATOR);
 
     // The value of S cannot be known at the Rust level
     // However the create_s method does exist for referring to it at the Lean level
     const S: u32 = unimplemented!();
 
-    const ROOT_OF_UNITY: Self = Self::RootOfUnity;
+    const ROOT_OF_UNITY: Self = Self::Expr(TermId::ROOT_OF_UNITY);
 
-    const ROOT_OF_UNITY_INV: Self = Self::RootOfUnityInv;
+    const ROOT_OF_UNITY_INV: Self = Self::Expr(TermId::ROOT_OF_UNITY_INV);
 
-    const DELTA: Self = Self::Delta;
+    const DELTA: Self = Self::Expr(TermId::DELTA);
 }
 
 impl FromUniformBytes<64> for TermField {
     fn from_uniform_bytes(bytes: &[u8; 64]) -> Self {
         let x = BigUint::from_bytes_le(bytes);
-        Self::from(x.to_str_radix(10))
+        Self::from_bigint(x.into())
     }
 }
 
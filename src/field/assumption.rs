@@ -0,0 +1,139 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::cell::RefCell;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Term, TermId};
+
+/// A fact the extraction policy assumed in order to give a concrete answer about symbolic values
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Assumption {
+    /// Assumed by `eq` when it returns false for terms that aren't identical
+    NotEqual(Term, Term),
+    /// Assumed by `cmp` and `partial_cmp`, which return `Less` for a pair they can't order, and the same answer
+    /// whenever they are asked about that pair again
+    Less(Term, Term),
+    /// Assumed by `invert`, which always returns an inverse
+    NonZero(Term),
+}
+
+impl Assumption {
+    /// The operation that made the assumption
+    pub fn source(&self) -> &'static str {
+        match self {
+            Assumption::NotEqual(..) => "eq",
+            Assumption::Less(..) => "cmp",
+            Assumption::NonZero(_) => "invert",
+        }
+    }
+}
+
+// Renders as a Lean Prop
+impl Display for Assumption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Assumption::NotEqual(lhs, rhs) => write!(f, "({lhs}) ≠ ({rhs})"),
+            Assumption::Less(lhs, rhs) => write!(f, "(({lhs}): ZMod P).val < (({rhs}): ZMod P).val"),
+            Assumption::NonZero(x) => write!(f, "({x}) ≠ 0"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Assumptions {
+    // In the order they were first made
+    made: Vec<Assumption>,
+    seen: HashSet<Assumption>,
+    // Each pair `cmp` answered `Less` for without knowing, whatever the policy
+    ordered: HashSet<(TermId, TermId)>,
+}
+
+thread_local! {
+    // Per thread, like the extraction policy that makes them
+    static ASSUMPTIONS: RefCell<Assumptions> = RefCell::new(Assumptions::default());
+}
+
+pub(super) fn record(assumption: Assumption) {
+    log::debug!("{assumption} assumed by {}", assumption.source());
+    ASSUMPTIONS.with_borrow_mut(|assumptions| {
+        if assumptions.seen.insert(assumption.clone()) {
+            assumptions.made.push(assumption);
+        }
+    });
+}
+
+/// The order `cmp` already assumed for `lhs` and `rhs`, in either orientation
+pub(super) fn assumed_order(lhs: TermId, rhs: TermId) -> Option<Ordering> {
+    ASSUMPTIONS.with_borrow(|assumptions| {
+        if assumptions.ordered.contains(&(lhs, rhs)) {
+            Some(Ordering::Less)
+        } else if assumptions.ordered.contains(&(rhs, lhs)) {
+            Some(Ordering::Greater)
+        } else {
+            None
+        }
+    })
+}
+
+pub(super) fn assume_less(lhs: TermId, rhs: TermId) {
+    ASSUMPTIONS.with_borrow_mut(|assumptions| assumptions.ordered.insert((lhs, rhs)));
+}
+
+/// Every distinct assumption made on this thread since the last call, in the order they were made
+///
+/// Each extraction starts by discarding these, so its own are just those made during the extraction
+pub fn take_assumptions() -> Vec<Assumption> {
+    ASSUMPTIONS.take().made
+}
+
+#[cfg(test)]
+mod test {
+    use ff::Field;
+
+    use crate::field::{install_policy, ExtractionPolicy, Policy, TermField};
+
+    use super::*;
+
+    #[test]
+    fn test_recorded_assumptions() {
+        let _guard = install_policy(ExtractionPolicy::recording());
+        let x = TermField::from("recorded_x");
+        let y = TermField::from("recorded_y");
+        take_assumptions();
+
+        assert!(x != y);
+        assert!(x != y);
+        assert!(bool::from(x.invert().is_some()));
+        assert!(x < y);
+        let (x, y) = (x.to_term(), y.to_term());
+        assert_eq!(
+            take_assumptions(),
+            [Assumption::NotEqual(x.clone(), y.clone()), Assumption::NonZero(x.clone()), Assumption::Less(x.clone(), y.clone())],
+        );
+        assert!(take_assumptions().is_empty());
+
+        assert_eq!(Assumption::NotEqual(x.clone(), y.clone()).to_string(), "(recorded_x) ≠ (recorded_y)");
+        assert_eq!(Assumption::Less(x.clone(), y).to_string(), "((recorded_x): ZMod P).val < ((recorded_y): ZMod P).val");
+        assert_eq!(Assumption::NonZero(x).to_string(), "(recorded_x) ≠ 0");
+    }
+
+    #[test]
+    fn test_consistent_order() {
+        // Even without recording, a pair keeps the order it was first given in both orientations
+        let _guard = install_policy(ExtractionPolicy { ordering: Policy::Assume, ..ExtractionPolicy::strict() });
+        let x = TermField::from("ordered_x");
+        let y = TermField::from("ordered_y");
+        assert_eq!(y.cmp(&x), Ordering::Less);
+        assert_eq!(y.cmp(&x), Ordering::Less);
+        assert_eq!(x.cmp(&y), Ordering::Greater);
+        assert!(x > y && y < x);
+        assert!(take_assumptions().is_empty());
+
+        // Identical terms and non-negative constants are decided, not assumed
+        assert_eq!(x.cmp(&x), Ordering::Equal);
+        assert!(TermField::from(u64::MAX) * TermField::from(2u64) > TermField::from(3u64));
+    }
+}
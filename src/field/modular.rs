@@ -0,0 +1,520 @@
+use std::{
+    any::TypeId, collections::HashMap, fmt::{Debug, Display}, iter::{Product, Sum}, marker::PhantomData, ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign}, sync::{Mutex, OnceLock}
+};
+
+use ff::{Field, FromUniformBytes, PrimeField};
+use num_bigint::{BigInt, BigUint};
+use num_traits::{Num, One, Zero};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq, CtOption};
+
+use super::{Builtin, SymbolDomain, SymbolicField, Term, TermField, TermFieldBytes, TermId};
+
+// The generator is recovered by comparing F::MULTIPLICATIVE_GENERATOR against small integers
+// Every field halo2 is used with has a single digit generator, so this is plenty
+const GENERATOR_SEARCH_LIMIT: u64 = 1024;
+
+// The constants of a field that reduction needs, which are worked out once per field
+struct FieldConstants {
+    modulus: BigUint,
+    signed_modulus: BigInt,
+    generator: Option<BigUint>,
+}
+
+/// A `TermField` bound to the modulus of the concrete prime field `F`
+///
+/// Constants are reduced mod p, so equality and inversion of constants are decidable and the `PrimeField`
+/// constants are those of `F`. Symbolic values behave exactly as they do in `TermField`.
+pub struct ModularTermField<F>(TermField, PhantomData<fn() -> F>);
+
+impl<F> Clone for ModularTermField<F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F> Copy for ModularTermField<F> {}
+
+impl<F: PrimeField> ModularTermField<F> {
+    const fn wrap(x: TermField) -> Self {
+        Self(x, PhantomData)
+    }
+
+    fn new(x: TermField) -> Self {
+        Self::wrap(Self::resolve(x))
+    }
+
+    /// The modulus of `F`, parsed from `F::MODULUS`
+    pub fn modulus() -> BigUint {
+        Self::constants().modulus.clone()
+    }
+
+    fn constants() -> &'static FieldConstants {
+        static CONSTANTS: OnceLock<Mutex<HashMap<TypeId, &'static FieldConstants>>> = OnceLock::new();
+        let constants = || {
+            CONSTANTS
+                .get_or_init(Default::default)
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+        };
+        if let Some(found) = constants().get(&TypeId::of::<F>()) {
+            return found;
+        }
+        // Worked out without holding the lock, as F may itself look up its constants
+        let modulus = F::MODULUS;
+        let parsed = match modulus.strip_prefix("0x") {
+            Some(hex) => BigUint::from_str_radix(hex, 16),
+            None => BigUint::from_str_radix(modulus, 10),
+        };
+        let modulus = parsed.unwrap_or_else(|_| panic!("Unable to parse the modulus {modulus}"));
+        let generator = (2..GENERATOR_SEARCH_LIMIT)
+            .find(|k| F::from(*k) == F::MULTIPLICATIVE_GENERATOR)
+            .map(BigUint::from);
+        // Leaked once per field type, of which a program has a handful
+        let computed = FieldConstants {
+            signed_modulus: modulus.clone().into(),
+            modulus,
+            generator,
+        };
+        constants()
+            .entry(TypeId::of::<F>())
+            .or_insert_with(|| Box::leak(Box::new(computed)))
+    }
+
+    pub fn create_symbol(name: &str) -> Self {
+        Self::wrap(TermField::create_symbol(name))
+    }
+
+    pub fn create_symbol_in(name: &str, domain: SymbolDomain) -> Self {
+        Self::wrap(TermField::create_symbol_in(name, domain))
+    }
+
+    pub fn create_symbol_vec(name: &str, len: usize) -> Vec<Self> {
+        TermField::create_symbol_vec(name, len).into_iter().map(Self::wrap).collect()
+    }
+
+    pub fn create_symbol_vec_in(name: &str, len: usize, domain: SymbolDomain) -> Vec<Self> {
+        TermField::create_symbol_vec_in(name, len, domain).into_iter().map(Self::wrap).collect()
+    }
+
+    pub fn apply(name: &str, args: &[Self]) -> Self {
+        let args: Vec<TermField> = args.iter().map(|arg| arg.0).collect();
+        Self::wrap(TermField::apply(name, &args))
+    }
+
+    /// The underlying term, with constants reduced
+    pub fn term(&self) -> TermField {
+        Self::resolve(self.0)
+    }
+
+    /// The value of this element in `0..p`, if it is a constant
+    pub fn constant(&self) -> Option<BigUint> {
+        let p = &Self::constants().signed_modulus;
+        let x = Self::resolve(self.0).constant()?;
+        Some(((x % p + p) % p).magnitude().clone())
+    }
+
+    // Constants are stored as their representative closest to zero, so that p - 1 prints as -1
+    fn reduce(x: BigInt) -> TermField {
+        let p = &Self::constants().signed_modulus;
+        let x = (x % p + p) % p;
+        if &x * 2 > *p {
+            TermField::from_bigint(x - p)
+        } else {
+            TermField::from_bigint(x)
+        }
+    }
+
+    // Replace constants by their reduced form, and builtins by their value in F where it can be found
+    fn resolve(x: TermField) -> TermField {
+        if let Some(value) = x.constant() {
+            return Self::reduce(value);
+        }
+        match x.builtin().and_then(Self::builtin_value) {
+            Some(value) => Self::reduce(value),
+            None => x,
+        }
+    }
+
+    fn builtin_value(builtin: Builtin) -> Option<BigInt> {
+        let p = &Self::constants().modulus;
+        let t = (p - 1u32) >> F::S;
+        let value = match builtin {
+            Builtin::TwoInv => (p + 1u32) / 2u32,
+            Builtin::S => F::S.into(),
+            Builtin::MultiplicativeGenerator => Self::generator()?.clone(),
+            Builtin::RootOfUnity => Self::generator()?.modpow(&t, p),
+            Builtin::RootOfUnityInv => Self::generator()?.modpow(&t, p).modpow(&(p - 2u32), p),
+            Builtin::Delta => Self::generator()?.modpow(&(BigUint::one() << F::S), p),
+        };
+        Some(value.into())
+    }
+
+    fn generator() -> Option<&'static BigUint> {
+        Self::constants().generator.as_ref()
+    }
+}
+
+impl<F: PrimeField> SymbolicField for ModularTermField<F> {
+    fn instance(column: usize, row: usize) -> Self {
+        Self::wrap(TermField::instance(column, row))
+    }
+
+    fn challenge(index: usize, phase: u8) -> Self {
+        Self::wrap(TermField::challenge(index, phase))
+    }
+
+    fn to_term(&self) -> Term {
+        self.term().to_term()
+    }
+
+    fn modulus() -> Option<BigUint> {
+        Some(Self::modulus())
+    }
+}
+
+impl<F: PrimeField> PartialEq for ModularTermField<F> {
+    fn eq(&self, other: &Self) -> bool {
+        let (lhs, rhs) = (self.term(), other.term());
+        match (lhs.constant(), rhs.constant()) {
+            // Both are reduced, so this is equality mod p
+            (Some(x), Some(y)) => x == y,
+            _ => lhs == rhs,
+        }
+    }
+}
+
+impl<F: PrimeField> Eq for ModularTermField<F> {}
+
+impl<F: PrimeField> Debug for ModularTermField<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.term())
+    }
+}
+
+// Renders as Lean
+impl<F: PrimeField> Display for ModularTermField<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.term())
+    }
+}
+
+impl<F: PrimeField> From<&str> for ModularTermField<F> {
+    fn from(s: &str) -> Self {
+        Self::new(TermField::from(s))
+    }
+}
+
+impl<F: PrimeField> From<String> for ModularTermField<F> {
+    fn from(value: String) -> Self {
+        Self::from(value.as_str())
+    }
+}
+
+impl<F: PrimeField> From<bool> for ModularTermField<F> {
+    fn from(value: bool) -> Self {
+        Self::wrap(TermField::from(value))
+    }
+}
+
+impl<F: PrimeField> From<u64> for ModularTermField<F> {
+    fn from(value: u64) -> Self {
+        Self::new(TermField::from(value))
+    }
+}
+
+impl<F: PrimeField> Default for ModularTermField<F> {
+    fn default() -> Self {
+        Self::ZERO
+    }
+}
+
+impl<F: PrimeField> ConditionallySelectable for ModularTermField<F> {
+    fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+        if choice.unwrap_u8() == 0 {
+            *a
+        } else {
+            *b
+        }
+    }
+}
+
+impl<F: PrimeField> ConstantTimeEq for ModularTermField<F> {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        Choice::from(u8::from(self.eq(other)))
+    }
+}
+
+impl<F: PrimeField> Neg for ModularTermField<F> {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self::new(-self.term())
+    }
+}
+
+impl<F: PrimeField> Add for ModularTermField<F> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.term() + rhs.term())
+    }
+}
+
+impl<F: PrimeField> Sub for ModularTermField<F> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(self.term() - rhs.term())
+    }
+}
+
+impl<F: PrimeField> Mul for ModularTermField<F> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::new(self.term() * rhs.term())
+    }
+}
+
+impl<F: PrimeField> Sum for ModularTermField<F> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, Self::add)
+    }
+}
+
+impl<F: PrimeField> Product for ModularTermField<F> {
+    fn product<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ONE, Self::mul)
+    }
+}
+
+impl<'a, F: PrimeField> Add<&'a ModularTermField<F>> for ModularTermField<F> {
+    type Output = Self;
+
+    fn add(self, rhs: &'a ModularTermField<F>) -> Self::Output {
+        self.add(*rhs)
+    }
+}
+
+impl<'a, F: PrimeField> Sub<&'a ModularTermField<F>> for ModularTermField<F> {
+    type Output = Self;
+
+    fn sub(self, rhs: &'a ModularTermField<F>) -> Self::Output {
+        self.sub(*rhs)
+    }
+}
+
+impl<'a, F: PrimeField> Mul<&'a ModularTermField<F>> for ModularTermField<F> {
+    type Output = Self;
+
+    fn mul(self, rhs: &'a ModularTermField<F>) -> Self::Output {
+        self.mul(*rhs)
+    }
+}
+
+impl<F: PrimeField> AddAssign for ModularTermField<F> {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl<F: PrimeField> SubAssign for ModularTermField<F> {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl<F: PrimeField> MulAssign for ModularTermField<F> {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+impl<'a, F: PrimeField> AddAssign<&'a ModularTermField<F>> for ModularTermField<F> {
+    fn add_assign(&mut self, rhs: &'a ModularTermField<F>) {
+        *self = *self + *rhs;
+    }
+}
+
+impl<'a, F: PrimeField> SubAssign<&'a ModularTermField<F>> for ModularTermField<F> {
+    fn sub_assign(&mut self, rhs: &'a ModularTermField<F>) {
+        *self = *self - *rhs;
+    }
+}
+
+impl<'a, F: PrimeField> MulAssign<&'a ModularTermField<F>> for ModularTermField<F> {
+    fn mul_assign(&mut self, rhs: &'a ModularTermField<F>) {
+        *self = *self * *rhs;
+    }
+}
+
+impl<'a, F: PrimeField> Sum<&'a ModularTermField<F>> for ModularTermField<F> {
+    fn sum<I: Iterator<Item = &'a ModularTermField<F>>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, Self::add)
+    }
+}
+
+impl<'a, F: PrimeField> Product<&'a ModularTermField<F>> for ModularTermField<F> {
+    fn product<I: Iterator<Item = &'a ModularTermField<F>>>(iter: I) -> Self {
+        iter.fold(Self::ONE, Self::mul)
+    }
+}
+
+impl<F: PrimeField> PartialOrd for ModularTermField<F> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Constants are ordered by their value in 0..p, anything else falls back to TermField
+impl<F: PrimeField> Ord for ModularTermField<F> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self.constant(), other.constant()) {
+            (Some(x), Some(y)) => x.cmp(&y),
+            _ => self.term().cmp(&other.term()),
+        }
+    }
+}
+
+impl<F: PrimeField> Field for ModularTermField<F> {
+    const ZERO: Self = Self::wrap(TermField::zero());
+    const ONE: Self = Self::wrap(TermField::one());
+
+    fn random(_rng: impl rand_core::RngCore) -> Self {
+        panic!("Random with a random number generator is not supported. Use create_symbol for an unknown value")
+    }
+
+    fn square(&self) -> Self {
+        *self * self
+    }
+
+    fn double(&self) -> Self {
+        *self + self
+    }
+
+    fn invert(&self) -> CtOption<Self> {
+        match self.constant() {
+            Some(x) if x.is_zero() => CtOption::new(Self::ZERO, Choice::from(0)),
+            Some(x) => {
+                let p = &Self::constants().modulus;
+                let inverse = x.modpow(&(p - 2u32), p);
+                CtOption::new(Self::new(TermField::from_bigint(inverse.into())), Choice::from(1))
+            },
+            None => self.term().invert().map(Self::wrap),
+        }
+    }
+
+    fn sqrt_ratio(_num: &Self, _div: &Self) -> (Choice, Self) {
+        panic!("Sqrt_ratio is not supported, because it is not possible to determine whether a TermField equals zero. Hence the Choice return cannot be determined")
+    }
+}
+
+impl<F: PrimeField> PrimeField for ModularTermField<F> {
+    type Repr = TermFieldBytes;
+
+    fn from_repr(repr: Self::Repr) -> CtOption<Self> {
+        TermField::from_repr(repr).map(Self::new)
+    }
+
+    fn to_repr(&self) -> Self::Repr {
+        self.term().to_repr()
+    }
+
+    fn is_odd(&self) -> Choice {
+        match self.constant() {
+            Some(x) => Choice::from(u8::from(x.bit(0))),
+            None => unimplemented!("Cannot deterministically decide whether a symbolic TermField is odd"),
+        }
+    }
+
+    const MODULUS: &'static str = F::MODULUS;
+
+    const NUM_BITS: u32 = F::NUM_BITS;
+
+    const CAPACITY: u32 = F::CAPACITY;
+
+    const TWO_INV: Self = Self::wrap(TermField::two_inv());
+
+    const MULTIPLICATIVE_GENERATOR: Self = Self::wrap(TermField::Expr(TermId::MULTIPLICATIVE_GENERATOR));
+
+    const S: u32 = F::S;
+
+    const ROOT_OF_UNITY: Self = Self::wrap(TermField::Expr(TermId::ROOT_OF_UNITY));
+
+    const ROOT_OF_UNITY_INV: Self = Self::wrap(TermField::Expr(TermId::ROOT_OF_UNITY_INV));
+
+    const DELTA: Self = Self::wrap(TermField::Expr(TermId::DELTA));
+}
+
+impl<F: PrimeField> FromUniformBytes<64> for ModularTermField<F> {
+    fn from_uniform_bytes(bytes: &[u8; 64]) -> Self {
+        Self::new(TermField::from_uniform_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use halo2curves::bn256::Fr;
+
+    use super::*;
+
+    type M = ModularTermField<Fr>;
+
+    fn p() -> BigUint {
+        BigUint::from_str_radix("30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001", 16).unwrap()
+    }
+
+    #[test]
+    fn test_modulus() {
+        assert_eq!(M::modulus(), p());
+        assert_eq!(<M as SymbolicField>::modulus(), Some(p()));
+    }
+
+    #[test]
+    fn test_reduction() {
+        // The representative closest to zero is kept, but constant() is always in 0..p
+        let minus_one = -M::ONE;
+        assert_eq!(minus_one.constant(), Some(p() - 1u32));
+        assert_eq!(minus_one.to_string(), "-1");
+        assert!(M::from(0) - M::ONE == minus_one);
+        assert!(minus_one + M::ONE == M::ZERO);
+
+        let max = M::from(u64::MAX);
+        assert_eq!((max * max).constant(), Some(BigUint::from(u64::MAX).pow(2) % p()));
+        // p - 1 + 2 wraps around to 1
+        assert!(minus_one + M::from(2) == M::ONE);
+        // Ordered by the value in 0..p, not by the representative
+        assert!(minus_one > M::from(3));
+    }
+
+    #[test]
+    fn test_invert() {
+        let three = M::from(3);
+        let inverse = three.invert().unwrap();
+        assert!(inverse * three == M::ONE);
+        assert!(bool::from(M::ZERO.invert().is_none()));
+        assert!((-M::ONE).invert().unwrap() == -M::ONE);
+    }
+
+    #[test]
+    fn test_builtins() {
+        assert_eq!(M::TWO_INV.constant(), Some((p() + 1u32) / 2u32));
+        assert!(M::TWO_INV * M::from(2) == M::ONE);
+        assert_eq!(M::MULTIPLICATIVE_GENERATOR.constant(), Some(BigUint::from(7u32)));
+        assert_eq!(M::S, 28);
+        // The root of unity has order exactly 2^S
+        let root = M::ROOT_OF_UNITY.constant().unwrap();
+        assert_eq!(root.modpow(&(BigUint::one() << 28), &p()), BigUint::one());
+        assert_ne!(root.modpow(&(BigUint::one() << 27), &p()), BigUint::one());
+        assert!(M::ROOT_OF_UNITY * M::ROOT_OF_UNITY_INV == M::ONE);
+    }
+
+    #[test]
+    fn test_symbolic() {
+        // Symbolic terms are untouched, but the constants in them are reduced
+        let x = M::create_symbol("x");
+        assert!(x + M::ZERO == x + M::ZERO);
+        assert_eq!(x.constant(), None);
+        assert!(M::from(2) * M::TWO_INV * x == M::ONE * x);
+    }
+}
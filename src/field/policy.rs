@@ -0,0 +1,328 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
+
+use super::assumption::{self, Assumption};
+
+#[cfg(all(feature = "repr-text", feature = "repr-number"))]
+compile_error!("features `halo2-extractor/repr-text` and `halo2-extractor/repr-number` are mutually exclusive");
+
+/// What to do when an operation needs a concrete answer about symbolic values
+/// that neither the terms themselves nor a sample witness can give
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Policy {
+    /// Panic, so that the circuit can't silently depend on the answer
+    #[default]
+    Panic,
+    /// Give a placeholder answer: not equal, `Less`, or an unchecked inverse
+    Assume,
+    /// Give the placeholder answer and record it in the output as an assumption
+    Record,
+}
+
+/// How `PrimeField::Repr` is interpreted by `from_repr` and `to_repr`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReprPolicy {
+    /// Both conversions panic
+    #[default]
+    Unsupported,
+    /// The repr holds the Lean text of the term
+    Text,
+    /// The repr holds a little endian number. Only `from_repr` is supported
+    Number,
+}
+
+/// How TermField answers questions about symbolic values during an extraction
+///
+/// The default comes from the deprecated unsafe-* and repr-* cargo features, each of which selects `Policy::Record`
+/// (or the matching `ReprPolicy`) for its operation. Use `install_policy` to choose a policy for the extractions on
+/// a thread.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExtractionPolicy {
+    pub equality: Policy,
+    pub ordering: Policy,
+    pub inversion: Policy,
+    pub repr: ReprPolicy,
+}
+
+impl ExtractionPolicy {
+    /// Panic whenever an answer isn't known
+    pub const fn strict() -> Self {
+        Self {
+            equality: Policy::Panic,
+            ordering: Policy::Panic,
+            inversion: Policy::Panic,
+            repr: ReprPolicy::Unsupported,
+        }
+    }
+
+    /// Record an assumption whenever an answer isn't known
+    pub const fn recording() -> Self {
+        Self {
+            equality: Policy::Record,
+            ordering: Policy::Record,
+            inversion: Policy::Record,
+            repr: ReprPolicy::Text,
+        }
+    }
+
+    fn get(&self, operation: Operation) -> Policy {
+        match operation {
+            Operation::Equality => self.equality,
+            Operation::Ordering => self.ordering,
+            Operation::Inversion => self.inversion,
+        }
+    }
+}
+
+const fn feature_policy(enabled: bool) -> Policy {
+    if enabled {
+        Policy::Record
+    } else {
+        Policy::Panic
+    }
+}
+
+impl Default for ExtractionPolicy {
+    fn default() -> Self {
+        Self {
+            equality: feature_policy(cfg!(feature = "unsafe-equality")),
+            ordering: feature_policy(cfg!(feature = "unsafe-ord")),
+            inversion: feature_policy(cfg!(feature = "unsafe-invert")),
+            repr: if cfg!(feature = "repr-text") {
+                ReprPolicy::Text
+            } else if cfg!(feature = "repr-number") {
+                ReprPolicy::Number
+            } else {
+                ReprPolicy::Unsupported
+            },
+        }
+    }
+}
+
+thread_local! {
+    // The policy is per thread, like the extraction that installs it, so that extractions on different threads
+    // (e.g. in parallel tests) never see each other's policy
+    static POLICY: Cell<Option<ExtractionPolicy>> = const { Cell::new(None) };
+    static COUNTS: Cell<Counts> = const { Cell::new(Counts::new()) };
+}
+
+/// The policy currently in effect on this thread
+pub fn current_policy() -> ExtractionPolicy {
+    POLICY.get().unwrap_or_default()
+}
+
+/// Restores the previous policy when dropped
+#[must_use = "the policy is removed as soon as the guard is dropped"]
+pub struct PolicyGuard {
+    previous: Option<ExtractionPolicy>,
+    // The guard restores the policy of the thread it was created on, so it must be dropped there
+    _thread: PhantomData<*const ()>,
+}
+
+impl Drop for PolicyGuard {
+    fn drop(&mut self) {
+        POLICY.set(self.previous);
+    }
+}
+
+/// Use `policy` for every TermField operation on this thread until the returned guard is dropped
+///
+/// Guards can be nested, and dropping one restores the policy that was in effect before it
+pub fn install_policy(policy: ExtractionPolicy) -> PolicyGuard {
+    PolicyGuard {
+        previous: POLICY.replace(Some(policy)),
+        _thread: PhantomData,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(super) enum Operation {
+    Equality,
+    Ordering,
+    Inversion,
+}
+
+#[derive(Clone, Copy)]
+pub(super) enum Outcome {
+    // Answered from the terms themselves
+    Decided,
+    // Answered by the sample witness
+    Shadowed,
+    // Answered by the policy
+    Assumed,
+}
+
+// How often each operation was answered each way, indexed by operation and then outcome
+#[derive(Clone, Copy)]
+struct Counts {
+    operations: [[usize; 3]; 3],
+    repr_conversions: usize,
+}
+
+impl Counts {
+    const fn new() -> Self {
+        Self {
+            operations: [[0; 3]; 3],
+            repr_conversions: 0,
+        }
+    }
+}
+
+pub(super) fn count(operation: Operation, outcome: Outcome) {
+    let mut counts = COUNTS.get();
+    counts.operations[operation as usize][outcome as usize] += 1;
+    COUNTS.set(counts);
+}
+
+pub(super) fn count_repr() {
+    let mut counts = COUNTS.get();
+    counts.repr_conversions += 1;
+    COUNTS.set(counts);
+}
+
+/// Apply the current policy to an operation whose answer isn't known
+///
+/// Panics with `message` under `Policy::Panic`, and records `assumption` under `Policy::Record`
+pub(super) fn assume(operation: Operation, message: impl FnOnce() -> String, assumption: impl FnOnce() -> Assumption) {
+    match current_policy().get(operation) {
+        Policy::Panic => panic!("{}", message()),
+        Policy::Assume => {},
+        Policy::Record => assumption::record(assumption()),
+    }
+    count(operation, Outcome::Assumed);
+}
+
+/// How often an operation was answered each way
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OperationCounts {
+    pub decided: usize,
+    pub shadowed: usize,
+    pub assumed: usize,
+}
+
+impl OperationCounts {
+    fn new(counts: &Counts, operation: Operation) -> Self {
+        let [decided, shadowed, assumed] = counts.operations[operation as usize];
+        Self {
+            decided,
+            shadowed,
+            assumed,
+        }
+    }
+}
+
+/// Which policies an extraction used, and how often
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PolicyReport {
+    pub policy: ExtractionPolicy,
+    pub equality: OperationCounts,
+    pub ordering: OperationCounts,
+    pub inversion: OperationCounts,
+    pub repr_conversions: usize,
+}
+
+/// The report for everything on this thread since the last call, under the current policy
+///
+/// Each extraction starts by discarding the report, so its own covers just the extraction
+pub fn take_policy_report() -> PolicyReport {
+    let counts = COUNTS.replace(Counts::new());
+    PolicyReport {
+        policy: current_policy(),
+        equality: OperationCounts::new(&counts, Operation::Equality),
+        ordering: OperationCounts::new(&counts, Operation::Ordering),
+        inversion: OperationCounts::new(&counts, Operation::Inversion),
+        repr_conversions: counts.repr_conversions,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::panic::catch_unwind;
+
+    use ff::{Field, PrimeField};
+
+    use crate::field::{install_sample_witness, take_assumptions, SampleWitness, TermField};
+
+    use super::*;
+
+    #[test]
+    fn test_panic_policy() {
+        let _guard = install_policy(ExtractionPolicy::strict());
+        let x = TermField::from("panic_x");
+        let y = TermField::from("panic_y");
+        assert!(catch_unwind(|| x == y).is_err());
+        assert!(catch_unwind(|| x < y).is_err());
+        assert!(catch_unwind(|| x.invert()).is_err());
+        assert!(catch_unwind(|| x.to_repr()).is_err());
+
+        // Questions the terms answer themselves never reach the policy
+        assert!(x == x);
+        assert!(TermField::from(2u64) < TermField::from(3u64));
+    }
+
+    #[test]
+    fn test_assume_policy() {
+        let _guard = install_policy(ExtractionPolicy {
+            equality: Policy::Assume,
+            ordering: Policy::Assume,
+            inversion: Policy::Assume,
+            repr: ReprPolicy::Unsupported,
+        });
+        let x = TermField::from("assume_x");
+        let y = TermField::from("assume_y");
+        assert!(x != y);
+        assert!(x < y);
+        assert!(bool::from(x.invert().is_some()));
+        assert!(take_assumptions().is_empty());
+    }
+
+    #[test]
+    fn test_nested_policies() {
+        assert_eq!(current_policy(), ExtractionPolicy::default());
+        let outer = install_policy(ExtractionPolicy::strict());
+        {
+            let _inner = install_policy(ExtractionPolicy::recording());
+            assert_eq!(current_policy(), ExtractionPolicy::recording());
+            // Other threads keep their own policy
+            assert_eq!(std::thread::spawn(current_policy).join().unwrap(), ExtractionPolicy::default());
+        }
+        assert_eq!(current_policy(), ExtractionPolicy::strict());
+        drop(outer);
+        assert_eq!(current_policy(), ExtractionPolicy::default());
+    }
+
+    #[test]
+    fn test_policy_report() {
+        let _guard = install_policy(ExtractionPolicy::recording());
+        let x = TermField::from("report_x");
+        let y = TermField::from("report_y");
+        take_policy_report();
+
+        assert!(x == x);
+        assert!(x != y);
+        assert!(x < y);
+        assert!(x < y);
+        assert!(bool::from(x.invert().is_some()));
+        TermField::from_repr(x.to_repr()).unwrap();
+        {
+            let _witness = install_sample_witness(SampleWitness::new(97u32));
+            assert!(bool::from(TermField::from(3u64).invert().is_some()));
+        }
+
+        let report = take_policy_report();
+        assert_eq!(report.policy, ExtractionPolicy::recording());
+        assert_eq!(report.equality, OperationCounts { decided: 1, shadowed: 0, assumed: 1 });
+        // The repeated pair is answered from the first assumption, but still counts as assumed
+        assert_eq!(report.ordering, OperationCounts { decided: 0, shadowed: 0, assumed: 2 });
+        assert_eq!(report.inversion, OperationCounts { decided: 0, shadowed: 1, assumed: 1 });
+        assert_eq!(report.repr_conversions, 2);
+
+        let report = take_policy_report();
+        assert_eq!(report.equality, OperationCounts::default());
+        assert_eq!(report.repr_conversions, 0);
+    }
+}
@@ -0,0 +1,256 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use num_bigint::{BigInt, BigUint};
+use num_traits::Zero;
+
+use super::{arena, Builtin, TermArena, TermId, TermNode};
+
+/// A function given to `SampleWitness::with_function` to evaluate an uninterpreted function such as field_xor
+pub type ShadowFunction = Box<dyn Fn(&[BigUint]) -> BigUint + Send + Sync>;
+
+/// Concrete values for the symbolic inputs of a circuit, used to shadow symbolic execution
+///
+/// While a sample witness is installed, every `TermField` also has a concrete value mod `modulus`, computed from
+/// the values given here. Equality, ordering and inversion use that value instead of requiring the unsafe-* features,
+/// so witness generation code takes the same branches it would for this witness. The terms themselves are still
+/// recorded symbolically.
+pub struct SampleWitness {
+    modulus: BigUint,
+    symbols: HashMap<String, BigUint>,
+    symbol_vecs: HashMap<String, Vec<BigUint>>,
+    instance: HashMap<(usize, usize), BigUint>,
+    challenges: HashMap<usize, BigUint>,
+    functions: HashMap<String, ShadowFunction>,
+}
+
+impl SampleWitness {
+    pub fn new(modulus: impl Into<BigUint>) -> Self {
+        Self {
+            modulus: modulus.into(),
+            symbols: HashMap::new(),
+            symbol_vecs: HashMap::new(),
+            instance: HashMap::new(),
+            challenges: HashMap::new(),
+            functions: HashMap::new(),
+        }
+    }
+
+    /// The value of the symbol created by `TermField::create_symbol(name)`
+    pub fn with_symbol(mut self, name: &str, value: impl Into<BigUint>) -> Self {
+        self.symbols.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// The values of the symbols created by `TermField::create_symbol_vec(name, _)`
+    pub fn with_symbol_vec(mut self, name: &str, values: impl IntoIterator<Item = impl Into<BigUint>>) -> Self {
+        self.symbol_vecs.insert(name.to_string(), values.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn with_instance(mut self, column: usize, row: usize, value: impl Into<BigUint>) -> Self {
+        self.instance.insert((column, row), value.into());
+        self
+    }
+
+    pub fn with_challenge(mut self, index: usize, value: impl Into<BigUint>) -> Self {
+        self.challenges.insert(index, value.into());
+        self
+    }
+
+    /// How to evaluate the uninterpreted function `name`, as created by `TermField::apply`
+    pub fn with_function(mut self, name: &str, function: impl Fn(&[BigUint]) -> BigUint + Send + Sync + 'static) -> Self {
+        self.functions.insert(name.to_string(), Box::new(function));
+        self
+    }
+
+    pub fn modulus(&self) -> &BigUint {
+        &self.modulus
+    }
+
+    fn reduce(&self, x: &BigInt) -> BigUint {
+        let p = BigInt::from(self.modulus.clone());
+        ((x % &p + &p) % &p).magnitude().clone()
+    }
+
+    fn invert(&self, x: &BigUint) -> BigUint {
+        // Like Lean's inv, the inverse of 0 is 0
+        if x.is_zero() {
+            x.clone()
+        } else {
+            x.modpow(&(&self.modulus - 2u32), &self.modulus)
+        }
+    }
+}
+
+struct Shadow {
+    witness: SampleWitness,
+    // Terms are hash-consed, so each id only ever needs evaluating once
+    values: HashMap<TermId, Option<BigUint>>,
+}
+
+thread_local! {
+    // The witness is per thread, like the extraction policy, so that extractions on different threads never see each
+    // other's witness
+    static SHADOW: RefCell<Option<Shadow>> = const { RefCell::new(None) };
+}
+
+/// Restores the previous sample witness when dropped
+#[must_use = "the sample witness is removed as soon as the guard is dropped"]
+pub struct ShadowGuard {
+    previous: Option<Shadow>,
+    // The guard restores the witness of the thread it was created on, so it must be dropped there
+    _thread: PhantomData<*const ()>,
+}
+
+impl Drop for ShadowGuard {
+    fn drop(&mut self) {
+        SHADOW.set(self.previous.take());
+    }
+}
+
+/// Install `witness` for every TermField operation on this thread until the returned guard is dropped
+///
+/// Guards can be nested, and dropping one restores the witness that was installed before it
+pub fn install_sample_witness(witness: SampleWitness) -> ShadowGuard {
+    ShadowGuard {
+        previous: SHADOW.replace(Some(Shadow {
+            witness,
+            values: HashMap::new(),
+        })),
+        _thread: PhantomData,
+    }
+}
+
+/// The value of the term at `id` under the installed sample witness
+///
+/// None if no witness is installed, or if the term depends on something the witness doesn't give a value for
+pub(super) fn value(id: TermId) -> Option<BigUint> {
+    SHADOW.with_borrow_mut(|shadow| evaluate(shadow.as_mut()?, &arena(), id))
+}
+
+/// The value of a constant under the installed sample witness, or None if no witness is installed
+pub(super) fn constant(x: &BigInt) -> Option<BigUint> {
+    SHADOW.with_borrow(|shadow| shadow.as_ref().map(|shadow| shadow.witness.reduce(x)))
+}
+
+fn operands(node: &TermNode) -> Vec<TermId> {
+    match node {
+        TermNode::Add(lhs, rhs) | TermNode::Mul(lhs, rhs) => vec![*lhs, *rhs],
+        TermNode::Neg(x) | TermNode::Inv(x) => vec![*x],
+        TermNode::Apply(_, args) => args.clone(),
+        _ => vec![],
+    }
+}
+
+// Terms built up over a long witness computation can be far deeper than the call stack, so the operands are
+// evaluated from an explicit stack, each before the node using them
+fn evaluate(shadow: &mut Shadow, arena: &TermArena, id: TermId) -> Option<BigUint> {
+    let mut stack = vec![id];
+    while let Some(&top) = stack.last() {
+        if shadow.values.contains_key(&top) {
+            stack.pop();
+            continue;
+        }
+        let node = arena.get(top);
+        let pending: Vec<_> = operands(node).into_iter().filter(|operand| !shadow.values.contains_key(operand)).collect();
+        if !pending.is_empty() {
+            stack.extend(pending);
+            continue;
+        }
+        stack.pop();
+        let value = evaluate_node(shadow, node);
+        shadow.values.insert(top, value);
+    }
+    shadow.values[&id].clone()
+}
+
+// The value of `node`, whose operands have all been evaluated already
+fn evaluate_node(shadow: &Shadow, node: &TermNode) -> Option<BigUint> {
+    let witness = &shadow.witness;
+    let p = &witness.modulus;
+    let operand = |id: &TermId| shadow.values[id].as_ref();
+    match node {
+        TermNode::Const(x) => Some(witness.reduce(x)),
+        TermNode::Symbol(name) => witness.symbols.get(name).map(|x| x % p),
+        TermNode::SymbolAt(name, index) => witness.symbol_vecs.get(name).and_then(|xs| xs.get(*index)).map(|x| x % p),
+        TermNode::Builtin(Builtin::TwoInv) => Some((p + 1u32) / 2u32),
+        TermNode::Builtin(_) => None,
+        TermNode::Add(lhs, rhs) => operand(lhs).zip(operand(rhs)).map(|(x, y)| (x + y) % p),
+        TermNode::Mul(lhs, rhs) => operand(lhs).zip(operand(rhs)).map(|(x, y)| (x * y) % p),
+        TermNode::Neg(x) => operand(x).map(|x| (p - x) % p),
+        TermNode::Inv(x) => operand(x).map(|x| witness.invert(x)),
+        TermNode::Instance { column, row } => witness.instance.get(&(*column, *row)).map(|x| x % p),
+        TermNode::Challenge { index, .. } => witness.challenges.get(index).map(|x| x % p),
+        TermNode::Apply(name, args) => args
+            .iter()
+            .map(|arg| operand(arg).cloned())
+            .collect::<Option<Vec<_>>>()
+            .and_then(|args| witness.functions.get(name).map(|function| function(&args) % p)),
+        TermNode::Raw(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ff::Field;
+
+    use crate::field::TermField;
+
+    use super::*;
+
+    #[test]
+    fn test_shadow_evaluation() {
+        let witness = SampleWitness::new(97u32)
+            .with_symbol("shadow_a", 5u32)
+            .with_symbol("shadow_b", 0u32)
+            .with_symbol_vec("shadow_v", [1u32, 200])
+            .with_instance(0, 3, 11u32)
+            .with_function("shadow_xor", |args| &args[0] ^ &args[1]);
+        let a = TermField::create_symbol("shadow_a");
+        let b = TermField::create_symbol("shadow_b");
+        let v = TermField::create_symbol_vec("shadow_v", 2);
+        let unknown = TermField::create_symbol("shadow_unknown");
+        let xor = TermField::apply("shadow_xor", &[a, TermField::from(3)]);
+
+        let _guard = install_sample_witness(witness);
+        assert_eq!((a * TermField::from(2)).shadow(), Some(10u32.into()));
+        assert_eq!((-a).shadow(), Some(92u32.into()));
+        assert_eq!((v[1] + TermField::instance(0, 3)).shadow(), Some(17u32.into()));
+        assert_eq!(xor.shadow(), Some(6u32.into()));
+        assert_eq!(TermField::two_inv().shadow(), Some(49u32.into()));
+        assert_eq!((a + unknown).shadow(), None);
+
+        // Comparisons follow the concrete values
+        assert!(a * TermField::from(2) == TermField::from(107));
+        assert!(a != b);
+        assert!(a > b && v[0] < a);
+        let inverse = a.invert().unwrap();
+        assert_eq!((inverse * a).shadow(), Some(1u32.into()));
+        assert!(bool::from(b.invert().is_none()));
+    }
+    #[test]
+    fn test_nested_witnesses() {
+        let a = TermField::create_symbol("shadow_nested");
+        assert_eq!(a.shadow(), None);
+        let outer = install_sample_witness(SampleWitness::new(97u32).with_symbol("shadow_nested", 5u32));
+        {
+            let _inner = install_sample_witness(SampleWitness::new(97u32).with_symbol("shadow_nested", 7u32));
+            assert_eq!(a.shadow(), Some(7u32.into()));
+            // Other threads don't see the witness
+            assert_eq!(std::thread::spawn(move || a.shadow()).join().unwrap(), None);
+        }
+        assert_eq!(a.shadow(), Some(5u32.into()));
+        drop(outer);
+        assert_eq!(a.shadow(), None);
+    }
+
+    #[test]
+    fn test_deep_term() {
+        let a = TermField::create_symbol("shadow_deep");
+        let sum = (0..200_000).fold(TermField::zero(), |sum, _| sum + a);
+        let _guard = install_sample_witness(SampleWitness::new(97u32).with_symbol("shadow_deep", 5u32));
+        assert_eq!(sum.shadow(), Some((200_000u32 * 5 % 97).into()));
+    }
+}
@@ -0,0 +1,158 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// A range a symbol is known to lie in, emitted as a hypothesis of `Circuit.isValid`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolDomain {
+    Boolean,
+    Byte,
+    /// Less than 2^bits
+    Bits(u32),
+}
+
+impl SymbolDomain {
+    /// The Lean Prop stating that `value` lies in this domain
+    pub fn hypothesis(&self, value: &str) -> String {
+        match self {
+            SymbolDomain::Boolean => format!("({value} = 0 ∨ {value} = 1)"),
+            SymbolDomain::Byte => format!("({value}).val < 256"),
+            SymbolDomain::Bits(bits) => format!("({value}).val < 2^{bits}"),
+        }
+    }
+}
+
+/// How a symbol appears in the Lean `Circuit` structure
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SymbolDecl {
+    pub name: String,
+    /// The number of elements, if the symbol was created with `create_symbol_vec`
+    pub len: Option<usize>,
+    pub domain: Option<SymbolDomain>,
+}
+
+impl SymbolDecl {
+    pub fn scalar(name: &str) -> Self {
+        SymbolDecl {
+            name: name.to_string(),
+            len: None,
+            domain: None,
+        }
+    }
+
+    /// The Lean type of the structure field
+    pub fn lean_type(&self) -> &'static str {
+        match self.len {
+            Some(_) => "ℕ → ZMod P",
+            None => "ZMod P",
+        }
+    }
+
+    /// The domain hypothesis for this symbol, as a field of the circuit `circuit`
+    pub fn domain_hypothesis(&self, circuit: &str) -> Option<String> {
+        let domain = self.domain?;
+        let field = format!("{circuit}.sym_{}", self.name);
+        Some(match self.len {
+            Some(len) => format!("(∀ i < {len}, {})", domain.hypothesis(&format!("{field} i"))),
+            None => domain.hypothesis(&field),
+        })
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    // In the order they were first created
+    symbols: Vec<SymbolDecl>,
+    index: HashMap<String, usize>,
+    // Names created more than once with a different length or domain
+    conflicts: Vec<String>,
+}
+
+thread_local! {
+    // Per thread, and emptied by each extraction, so that an extraction only sees the symbols created for it
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry::default());
+}
+
+pub(super) fn register(decl: SymbolDecl) {
+    REGISTRY.with_borrow_mut(|registry| match registry.index.get(&decl.name) {
+        Some(idx) => {
+            if registry.symbols[*idx] != decl && !registry.conflicts.contains(&decl.name) {
+                log::warn!("Symbol {} was created again with a different length or domain", decl.name);
+                registry.conflicts.push(decl.name);
+            }
+        },
+        None => {
+            let idx = registry.symbols.len();
+            registry.index.insert(decl.name.clone(), idx);
+            registry.symbols.push(decl);
+        },
+    })
+}
+
+/// Every symbol created on this thread since the last extraction, in the order they were first created
+///
+/// Each of these becomes a `sym_{name}` field of the Lean `Circuit` structure
+pub fn registered_symbols() -> Vec<SymbolDecl> {
+    REGISTRY.with_borrow(|registry| registry.symbols.clone())
+}
+
+/// The names of symbols that were created more than once with a different length or domain
+pub fn conflicting_symbols() -> Vec<String> {
+    REGISTRY.with_borrow(|registry| registry.conflicts.clone())
+}
+
+/// Forget every symbol created on this thread, which each extraction does once it is done with them
+pub fn clear_symbols() {
+    REGISTRY.take();
+}
+
+/// Check that `sym_{name}` is a valid Lean identifier
+pub fn validate_symbol_name(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() {
+        Err("symbol names can't be empty")
+    } else if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '\'') {
+        Ok(())
+    } else {
+        Err("symbol names may only contain ASCII letters, digits, underscores and apostrophes")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::field::TermField;
+
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        clear_symbols();
+        TermField::create_symbol("registry_b");
+        TermField::create_symbol("registry_a");
+        TermField::create_symbol("registry_b");
+        assert_eq!(registered_symbols(), [SymbolDecl::scalar("registry_b"), SymbolDecl::scalar("registry_a")]);
+        assert!(conflicting_symbols().is_empty());
+
+        // Creating a symbol again is only a conflict if its length or domain changed, and each is reported once
+        TermField::create_symbol_vec("registry_a", 2);
+        TermField::create_symbol_in("registry_a", SymbolDomain::Boolean);
+        assert_eq!(conflicting_symbols(), ["registry_a"]);
+        assert_eq!(registered_symbols()[1], SymbolDecl::scalar("registry_a"));
+
+        clear_symbols();
+        assert!(registered_symbols().is_empty());
+        assert!(conflicting_symbols().is_empty());
+    }
+
+    #[test]
+    fn test_symbol_names() {
+        for name in ["a", "x_1", "x'", "_", "Preimage2"] {
+            assert_eq!(validate_symbol_name(name), Ok(()));
+        }
+        assert!(validate_symbol_name("").is_err());
+        for name in ["a-b", "a b", "a.b", "α", "x₁", "é"] {
+            assert!(validate_symbol_name(name).is_err(), "{name} should be rejected");
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use num_bigint::{BigInt, Sign};
+use num_traits::{Signed, Zero};
+use serde::{Deserialize, Serialize};
+
+/// Index of a node in the global term arena
+///
+/// Nodes are hash-consed, so two ids are equal exactly when the terms they refer to are structurally identical
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TermId(u32);
+
+impl TermId {
+    // The arena is seeded with the builtin constants at these ids, so that they can be used in consts
+    pub const TWO_INV: TermId = TermId(0);
+    pub const MULTIPLICATIVE_GENERATOR: TermId = TermId(1);
+    pub const S: TermId = TermId(2);
+    pub const ROOT_OF_UNITY: TermId = TermId(3);
+    pub const ROOT_OF_UNITY_INV: TermId = TermId(4);
+    pub const DELTA: TermId = TermId(5);
+}
+
+/// The constants a `PrimeField` has to provide but which we can only refer to symbolically
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Builtin {
+    TwoInv,
+    MultiplicativeGenerator,
+    S,
+    RootOfUnity,
+    RootOfUnityInv,
+    Delta,
+}
+
+const BUILTINS: [Builtin; 6] = [
+    Builtin::TwoInv,
+    Builtin::MultiplicativeGenerator,
+    Builtin::S,
+    Builtin::RootOfUnity,
+    Builtin::RootOfUnityInv,
+    Builtin::Delta,
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TermNode {
+    // Any constant. Those that fit in an i64 are kept inline in TermField and only interned when they become an
+    // operand of a larger term, so a TermField::Expr holding a Const is always larger
+    Const(BigInt),
+    Symbol(String),
+    // An element of a symbol vector
+    SymbolAt(String, usize),
+    Builtin(Builtin),
+    Add(TermId, TermId),
+    Mul(TermId, TermId),
+    Neg(TermId),
+    Inv(TermId),
+    Instance { column: usize, row: usize },
+    Challenge { index: usize, phase: u8 },
+    // An uninterpreted function applied to some arguments, e.g. field_xor
+    Apply(String, Vec<TermId>),
+    // Text passed in through From<&str> that isn't a number
+    Raw(String),
+}
+
+pub struct TermArena {
+    nodes: Vec<TermNode>,
+    ids: HashMap<TermNode, TermId>,
+}
+
+impl TermArena {
+    fn new() -> Self {
+        let mut arena = TermArena {
+            nodes: vec![],
+            ids: HashMap::new(),
+        };
+        for builtin in BUILTINS {
+            arena.intern(TermNode::Builtin(builtin));
+        }
+        arena
+    }
+
+    pub fn intern(&mut self, node: TermNode) -> TermId {
+        if let Some(id) = self.ids.get(&node) {
+            return *id;
+        }
+        let id = TermId(u32::try_from(self.nodes.len()).expect("Term arena is full"));
+        self.nodes.push(node.clone());
+        self.ids.insert(node, id);
+        id
+    }
+
+    pub fn get(&self, id: TermId) -> &TermNode {
+        &self.nodes[id.0 as usize]
+    }
+
+    /// Build an owned tree for the term at `id`
+    pub fn to_term(&self, id: TermId) -> Term {
+        match self.get(id) {
+            TermNode::Const(x) => Term::Const(x.clone()),
+            TermNode::Symbol(name) => Term::Symbol(name.clone()),
+            TermNode::SymbolAt(name, index) => Term::SymbolAt(name.clone(), *index),
+            TermNode::Builtin(builtin) => Term::Builtin(*builtin),
+            TermNode::Add(lhs, rhs) => Term::Add(Box::new(self.to_term(*lhs)), Box::new(self.to_term(*rhs))),
+            TermNode::Mul(lhs, rhs) => Term::Mul(Box::new(self.to_term(*lhs)), Box::new(self.to_term(*rhs))),
+            TermNode::Neg(x) => Term::Neg(Box::new(self.to_term(*x))),
+            TermNode::Inv(x) => Term::Inv(Box::new(self.to_term(*x))),
+            TermNode::Instance { column, row } => Term::Instance { column: *column, row: *row },
+            TermNode::Challenge { index, phase } => Term::Challenge { index: *index, phase: *phase },
+            TermNode::Apply(name, args) => Term::Apply(name.clone(), args.iter().map(|arg| self.to_term(*arg)).collect()),
+            TermNode::Raw(text) => Term::Raw(text.clone()),
+        }
+    }
+}
+
+/// Lock the global term arena
+///
+/// The arena is shared between threads because circuits are free to compute witnesses in parallel
+pub fn arena() -> MutexGuard<'static, TermArena> {
+    static ARENA: OnceLock<Mutex<TermArena>> = OnceLock::new();
+    ARENA
+        .get_or_init(|| Mutex::new(TermArena::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// An owned, serializable term tree, produced from a `TermField` when it is handed to a backend
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Term {
+    Const(#[serde(with = "decimal")] BigInt),
+    Symbol(String),
+    SymbolAt(String, usize),
+    Builtin(Builtin),
+    Add(Box<Term>, Box<Term>),
+    Mul(Box<Term>, Box<Term>),
+    Neg(Box<Term>),
+    Inv(Box<Term>),
+    Instance { column: usize, row: usize },
+    Challenge { index: usize, phase: u8 },
+    Apply(String, Vec<Term>),
+    Raw(String),
+}
+
+impl Term {
+    pub fn is_zero(&self) -> bool {
+        matches!(self, Term::Const(x) if x.is_zero())
+    }
+}
+
+// Constants are serialized as decimal strings, which any JSON consumer can read without losing precision
+mod decimal {
+    use num_bigint::BigInt;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &BigInt, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigInt, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
+// Write the absolute value of a constant
+// Anything wider than 64 bits is written as a power of two or in hex, which is much shorter than decimal
+fn write_magnitude(f: &mut std::fmt::Formatter<'_>, x: &BigInt) -> std::fmt::Result {
+    let x = x.magnitude();
+    if x.bits() <= 64 {
+        write!(f, "{x}")
+    } else if ((x - 1u32) & x).is_zero() {
+        write!(f, "2^{}", x.bits() - 1)
+    } else {
+        write!(f, "0x{x:x}")
+    }
+}
+
+// Renders as Lean
+impl Display for Term {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Term::Const(x) => {
+                if x.sign() == Sign::Minus {
+                    write!(f, "-")?;
+                }
+                write_magnitude(f, x)
+            },
+            Term::Symbol(name) => write!(f, "c.1.sym_{name}"),
+            Term::SymbolAt(name, index) => write!(f, "c.1.sym_{name} {index}"),
+            Term::Builtin(Builtin::TwoInv) => write!(f, "(2: ZMod P).inv"),
+            Term::Builtin(Builtin::MultiplicativeGenerator) => write!(f, "c.mult_gen"),
+            Term::Builtin(Builtin::S) => write!(f, "c.S"),
+            Term::Builtin(Builtin::RootOfUnity) => write!(f, "c.root_of_unity"),
+            Term::Builtin(Builtin::RootOfUnityInv) => write!(f, "c.root_of_unity.inv"),
+            Term::Builtin(Builtin::Delta) => write!(f, "c.delta"),
+            Term::Add(lhs, rhs) => match rhs.as_ref() {
+                Term::Neg(rhs) => write!(f, "({lhs}) - ({rhs})"),
+                Term::Const(x) if x.is_negative() => {
+                    write!(f, "({lhs}) - (")?;
+                    write_magnitude(f, x)?;
+                    write!(f, ")")
+                },
+                rhs => write!(f, "({lhs}) + ({rhs})"),
+            },
+            Term::Mul(lhs, rhs) => write!(f, "({lhs}) * ({rhs})"),
+            Term::Neg(x) => write!(f, "-({x})"),
+            Term::Inv(x) => write!(f, "(({x}: ZMod P).inv)"),
+            Term::Instance { column, row } => write!(f, "instance_to_field (c.1.Instance {column} {row})"),
+            Term::Challenge { index, phase } => write!(f, "c.get_challenge {index} {phase}"),
+            Term::Apply(name, args) => {
+                write!(f, "({name}")?;
+                for arg in args {
+                    write!(f, " ({arg})")?;
+                }
+                write!(f, ")")
+            },
+            Term::Raw(text) => write!(f, "{text}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::field::TermField;
+
+    use super::*;
+
+    fn id(x: TermField) -> TermId {
+        match x {
+            TermField::Expr(id) => id,
+            TermField::Val(x) => panic!("{x} is an inline constant"),
+        }
+    }
+
+    #[test]
+    fn test_hash_consing() {
+        let arena_len = || arena().nodes.len();
+        assert_eq!(arena().get(TermId::TWO_INV), &TermNode::Builtin(Builtin::TwoInv));
+        assert_eq!(arena().get(TermId::DELTA), &TermNode::Builtin(Builtin::Delta));
+
+        let build = || {
+            let a = TermField::from("hash_consing_a");
+            let b = TermField::from("hash_consing_b");
+            (a * b + a) * TermField::from("hash_consing_c")
+        };
+        let first = id(build());
+        let len = arena_len();
+        assert_eq!(id(build()), first);
+        assert_eq!(arena_len(), len);
+
+        // Operand order is part of the structure
+        let a = TermField::from("hash_consing_a");
+        let b = TermField::from("hash_consing_b");
+        let (product, negated) = (id(a * b), id(-a));
+        assert_ne!(product, id(b * a));
+        assert_eq!(arena().get(product), &TermNode::Mul(id(a), id(b)));
+        assert_eq!(arena().to_term(negated), Term::Neg(Box::new(Term::Raw("hash_consing_a".to_string()))));
+    }
+    #[test]
+    fn test_big_constants() {
+        // Past 64 bits constants are folded exactly rather than wrapping or becoming symbolic
+        let max = TermField::from(u64::MAX);
+        let square = BigInt::from(u64::MAX) * BigInt::from(u64::MAX);
+        assert_eq!((max * max).constant(), Some(square.clone()));
+        assert_eq!((max * max - max * max).constant(), Some(BigInt::zero()));
+        assert_eq!((-(max * max) + TermField::from(1u64)).constant(), Some(BigInt::from(1) - square));
+        assert_eq!((-TermField::Val(i64::MIN)).constant(), Some(-BigInt::from(i64::MIN)));
+        assert!(matches!(TermField::from(u64::MAX) - TermField::from(u64::MAX), TermField::Val(0)));
+    }
+
+    #[test]
+    fn test_constant_rendering() {
+        let pow2 = |k: usize| BigInt::from(1) << k;
+        let constant = |x: BigInt| Term::Const(x).to_string();
+        assert_eq!(constant(BigInt::from(u64::MAX)), "18446744073709551615");
+        assert_eq!(constant(pow2(64)), "2^64");
+        assert_eq!(constant(-pow2(255)), "-2^255");
+        assert_eq!(constant(pow2(64) + 1), "0x10000000000000001");
+        assert_eq!(constant(-(BigInt::from(0xabc) * pow2(64))), "-0xabc0000000000000000");
+
+        let x = Box::new(Term::Raw("x".to_string()));
+        assert_eq!(Term::Add(x.clone(), Box::new(Term::Const(-pow2(70)))).to_string(), "(x) - (2^70)");
+        assert_eq!(Term::Mul(x, Box::new(Term::Const(pow2(70)))).to_string(), "(x) * (2^70)");
+    }
+}
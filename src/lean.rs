@@ -0,0 +1,1871 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
+
+use itertools::Itertools;
+use num_bigint::BigInt;
+use num_traits::{One, Zero};
+
+use crate::field::Term;
+use crate::model::{Cell, CircuitModel, ColumnAnnotations, ColumnKind, Gate, ModelExpr, RegionEvent};
+use crate::utils::{equivalence_classes, find_period, find_polynomial, get_group_annotations, group_values};
+
+mod ast;
+mod evaluator;
+mod halva;
+mod modules;
+mod proofs;
+mod sharing;
+mod unrolled;
+
+pub use ast::{mangle, BinOp, Binder, Def, DefKind, Expr, Item};
+pub use halva::{print_halva, write_halva, HALVA_MODULE, HALVA_VERSION};
+use halva::halva;
+pub use modules::{module_path, write_modules, write_modules_with};
+pub use proofs::{print_proofs, proofs};
+pub(crate) use modules::Module;
+use evaluator::Evaluator;
+use sharing::Sharing;
+use unrolled::unrolled_gates;
+
+const GROUPING_SIZE: usize = 10;
+
+/// How gates are stated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GateMode {
+    /// `∀ row : ℕ, poly(row) = 0`, reading selectors and fixed columns through the circuit
+    #[default]
+    Quantified,
+    /// One proposition for each row a constraint isn't trivially true on, with the selector and fixed values
+    /// substituted, followed by the quantified form for the rows after the extracted ones
+    Unrolled,
+}
+
+/// Write the full Lean rendering of `model` to `out`
+pub fn print_circuit(out: &mut impl Write, model: &CircuitModel) -> io::Result<()> {
+    print_circuit_with(out, model, GateMode::default())
+}
+
+/// Write the full Lean rendering of `model` to `out`, stating gates as `mode` says
+pub fn print_circuit_with(out: &mut impl Write, model: &CircuitModel, mode: GateMode) -> io::Result<()> {
+    print_preamble(out, model)?;
+    write_items(out, regions(model))?;
+    print_grouping_props(out, model, mode)?;
+    print_postamble(out, model)
+}
+
+/// Write each item in turn
+pub fn write_items(out: &mut impl Write, items: impl IntoIterator<Item = Item>) -> io::Result<()> {
+    for item in items {
+        write!(out, "{item}")?;
+    }
+    Ok(())
+}
+
+fn zmod() -> Expr {
+    Expr::raw("ZMod P")
+}
+
+fn nat_type() -> Expr {
+    Expr::raw("ℕ")
+}
+
+// The `(c: ValidCircuit P P_Prime)` parameter every generated definition takes
+fn circuit_param() -> Vec<Binder> {
+    vec![Binder::new(&["c"], Expr::raw("ValidCircuit P P_Prime"))]
+}
+
+fn circuit_def(name: impl Into<String>, ty: Expr, body: Expr) -> Item {
+    Def::new(name, circuit_param(), Some(ty), body).into()
+}
+
+fn prop_def(name: impl Into<String>, body: Expr) -> Item {
+    circuit_def(name, Expr::raw("Prop"), body)
+}
+
+fn c() -> Expr {
+    Expr::ident("c")
+}
+
+fn row() -> Expr {
+    Expr::ident("row")
+}
+
+fn bin(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+    Expr::binary(op, lhs, rhs)
+}
+
+fn term(value: &Term) -> Expr {
+    Expr::raw(value)
+}
+
+fn regions(model: &CircuitModel) -> Vec<Item> {
+    model
+        .regions
+        .iter()
+        .flat_map(|event| match event {
+            RegionEvent::Entered(name) => vec![Item::Blank, Item::Comment(format!("Entered region: {name}"))],
+            RegionEvent::Exited(name) => vec![Item::Comment(format!("Exited region: {name}"))],
+        })
+        .collect()
+}
+
+fn cell(cell: &Cell) -> Expr {
+    cell_at(cell.kind, cell.column, Expr::nat(cell.row))
+}
+
+fn cell_at(kind: ColumnKind, column: usize, row: Expr) -> Expr {
+    let getter = match kind {
+        ColumnKind::Advice => "c.get_advice",
+        ColumnKind::Fixed => "c.get_fixed",
+        ColumnKind::Instance => "c.get_instance",
+    };
+    Expr::app(getter, [Expr::nat(column), row])
+}
+
+// Hands out identifiers derived from names, adding `_2`, `_3`, ... to repeats in the order they are seen
+#[derive(Default)]
+struct Namer {
+    used: HashSet<String>,
+}
+
+impl Namer {
+    fn name(&mut self, base: String) -> String {
+        let mut name = base.clone();
+        let mut suffix = 1;
+        while self.used.contains(&name) {
+            suffix += 1;
+            name = format!("{base}_{suffix}");
+        }
+        self.used.insert(name.clone());
+        name
+    }
+}
+
+// The mangled name, or `unnamed` for an empty one
+fn name_part(name: &str) -> String {
+    if name.is_empty() {
+        "unnamed".to_string()
+    } else {
+        mangle(name)
+    }
+}
+
+// FNV-1a followed by the murmur3 finalizer, so that similar names spread across the leading digits
+// Unlike the std hashers this is fixed across platforms and releases
+fn stable_hash(name: &str) -> u64 {
+    let mut hash = name.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3));
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+/// `final_name`, the conjunction of the props called `members`, through intermediate groups of at most `group_size`
+///
+/// A group is named after the leading hex digits of the hashes of its members, so inserting or removing a prop
+/// only changes the groups containing it and the names of the others are unaffected
+pub fn prop_groups(final_name: &str, members: Vec<String>, group_size: usize) -> Vec<Item> {
+    assert!(group_size > 1);
+    let members = members.into_iter().map(|name| (stable_hash(&name), name)).collect_vec();
+    let mut items = vec![];
+    let conjuncts = hash_groups(final_name, "", members, group_size, &mut items);
+    items.push(prop_def(final_name, Expr::and(conjuncts.into_iter().map(|name| Expr::app(name, [c()])))));
+    items
+}
+
+// The names to conjoin in place of `members`, pushing the definitions of any groups onto `items`
+fn hash_groups(final_name: &str, path: &str, members: Vec<(u64, String)>, group_size: usize, items: &mut Vec<Item>) -> Vec<String> {
+    // Once every digit is used the remaining members share a hash, so can't be split further
+    if members.len() <= group_size || path.len() == 16 {
+        return members.into_iter().map(|(_, name)| name).collect();
+    }
+    let mut buckets: BTreeMap<u64, Vec<(u64, String)>> = BTreeMap::new();
+    for (hash, name) in members {
+        let digit = (hash >> (60 - 4 * path.len())) & 0xf;
+        buckets.entry(digit).or_default().push((hash, name));
+    }
+    buckets
+        .into_iter()
+        .map(|(digit, bucket)| {
+            let path = format!("{path}{digit:x}");
+            let conjuncts = hash_groups(final_name, &path, bucket, group_size, items);
+            let name = format!("{final_name}_{path}");
+            items.push(prop_def(&name, Expr::and(conjuncts.into_iter().map(|name| Expr::app(name, [c()])))));
+            name
+        })
+        .collect()
+}
+
+fn cell_name(cell: &Cell) -> String {
+    let kind = match cell.kind {
+        ColumnKind::Advice => "advice",
+        ColumnKind::Fixed => "fixed",
+        ColumnKind::Instance => "instance",
+    };
+    format!("{kind}{}_{}", cell.column, cell.row)
+}
+
+// The definitions for one kind of constraint. Each unit is kept together when the output is split into modules,
+// and the summary, which refers to the units, comes after all of them
+struct Section {
+    units: Vec<Vec<Item>>,
+    summary: Vec<Item>,
+}
+
+impl Section {
+    fn into_items(self) -> impl Iterator<Item = Item> {
+        self.units.into_iter().flatten().chain(self.summary)
+    }
+}
+
+// The projection taking the `idx`th of `len` conjuncts out of a proof of their conjunction, such as `.2.1`
+fn conjunct_projection(idx: usize, len: usize) -> String {
+    ".2".repeat(idx) + if idx + 1 == len { "" } else { ".1" }
+}
+
+// A class of copied cells, which are all equal to the representative
+//
+// The representative is an instance cell if there is one, then a fixed cell, so that proofs rewrite towards
+// the values they know
+struct CopyClass {
+    representative: Cell,
+    others: Vec<Cell>,
+}
+
+impl CopyClass {
+    fn new(class: Vec<Cell>) -> Self {
+        let representative = *class
+            .iter()
+            .min_by_key(|cell| (Reverse(cell.kind), cell.column, cell.row))
+            .expect("Equivalence classes aren't empty");
+        let others = class.into_iter().filter(|&cell| cell != representative).collect();
+        CopyClass { representative, others }
+    }
+
+    fn cells(&self) -> impl Iterator<Item = &Cell> {
+        std::iter::once(&self.representative).chain(&self.others)
+    }
+
+    fn base(&self) -> usize {
+        self.cells().map(|cell| cell.row).min().expect("Classes have a representative")
+    }
+
+    // The class moved up to start at row 0, representative first, which every repetition of a loop shares
+    fn shape(&self) -> Vec<(ColumnKind, usize, usize)> {
+        let base = self.base();
+        self.cells().map(|cell| (cell.kind, cell.column, cell.row - base)).collect()
+    }
+}
+
+// Fewer repetitions of a class than this are stated one by one
+const MIN_LOOP_LENGTH: usize = 4;
+
+// Runs of classes with the same shape whose rows advance by the same step from one to the next, such as those of a
+// region assigned in a loop, as the indices of the classes and the step. Every class is in exactly one run, most of
+// them alone with a step of 0, and the runs are in the order of their first class
+fn copy_loops(classes: &[CopyClass]) -> Vec<(Vec<usize>, usize)> {
+    let mut shapes: BTreeMap<_, Vec<usize>> = BTreeMap::new();
+    for (idx, class) in classes.iter().enumerate() {
+        shapes.entry(class.shape()).or_default().push(idx);
+    }
+    let mut loops = vec![];
+    for mut members in shapes.into_values() {
+        // Classes are disjoint, so no two of the same shape have the same base
+        members.sort_by_key(|&idx| classes[idx].base());
+        let mut rest = members.as_slice();
+        while let [first, second, ..] = rest {
+            let step = classes[*second].base() - classes[*first].base();
+            let len = 1 + rest
+                .windows(2)
+                .take_while(|pair| classes[pair[1]].base() - classes[pair[0]].base() == step)
+                .count();
+            if len >= MIN_LOOP_LENGTH {
+                loops.push((rest[..len].to_vec(), step));
+                rest = &rest[len..];
+            } else {
+                loops.push((vec![*first], 0));
+                rest = &rest[1..];
+            }
+        }
+        loops.extend(rest.iter().map(|&idx| (vec![idx], 0)));
+    }
+    loops.sort_by_key(|(members, _)| members.iter().min().copied());
+    loops
+}
+
+// `step * i + start`, the row of the `i`th repetition of a loop
+fn loop_row(start: usize, step: usize) -> Expr {
+    let i = Expr::ident("i");
+    let scaled = if step == 1 { i } else { bin(BinOp::Mul, Expr::nat(step), i) };
+    match start {
+        0 => scaled,
+        start => bin(BinOp::Add, scaled, Expr::nat(start)),
+    }
+}
+
+// Copied cells are stated a class at a time, as `cell = representative` for every other cell of the class.
+// A class repeated at least MIN_LOOP_LENGTH times is stated once, quantified over the repetitions, as
+// `copy_loop_{first representative}`. Every copy is still defined as `copy_{left}_{right}`, which follows from its
+// class by `copy_{left}_{right}_of_class`. The copies of a loop are defined once for each position in the class,
+// quantified over the repetitions and named after the first
+fn copy_constraints(model: &CircuitModel) -> Section {
+    let mut namer = Namer::default();
+    let mut units = vec![];
+    let mut members = vec![];
+    let classes = equivalence_classes(model.copies.iter().map(|copy| (copy.left, copy.right)))
+        .into_iter()
+        .map(CopyClass::new)
+        .collect_vec();
+    // The unit and definition of each class stated on its own
+    let mut singles = HashMap::new();
+    // The unit, definition, first class and step of the loop each class is in, if any
+    let mut looped = HashMap::new();
+    for (loop_classes, step) in copy_loops(&classes) {
+        if let [idx] = loop_classes[..] {
+            let class = &classes[idx];
+            let name = namer.name(format!("copy_class_{}", cell_name(&class.representative)));
+            let body = Expr::and(class.others.iter().map(|other| bin(BinOp::Eq, cell(other), cell(&class.representative))));
+            singles.insert(idx, (units.len(), name.clone()));
+            units.push(vec![prop_def(&name, body)]);
+            members.push(name);
+            continue;
+        }
+
+        let first = &classes[loop_classes[0]];
+        let name = namer.name(format!("copy_loop_{}", cell_name(&first.representative)));
+        let base = first.base();
+        let shape = first.shape();
+        let at = |&(kind, column, offset): &(ColumnKind, usize, usize)| cell_at(kind, column, loop_row(base + offset, step));
+        let body = Expr::and(shape[1..].iter().map(|other| bin(BinOp::Eq, at(other), at(&shape[0]))));
+        let i = Expr::ident("i");
+        let prop = Expr::forall(
+            vec![Binder::new(&["i"], nat_type())],
+            bin(BinOp::Implies, bin(BinOp::Lt, i, Expr::nat(loop_classes.len())), body),
+        );
+        for idx in &loop_classes {
+            looped.insert(*idx, (units.len(), name.clone(), loop_classes[0], step, loop_classes.len()));
+        }
+        units.push(vec![prop_def(&name, prop)]);
+        members.push(name);
+    }
+
+    // Each cell's class, and its position among the class's conjuncts unless it is the representative
+    let mut positions = HashMap::new();
+    for (class_idx, class) in classes.iter().enumerate() {
+        positions.insert(class.representative, (class_idx, None));
+        for (idx, other) in class.others.iter().enumerate() {
+            positions.insert(*other, (class_idx, Some(idx)));
+        }
+    }
+    // The positions of the copies of each loop that are already defined
+    let mut loop_copies = HashSet::new();
+    for copy in &model.copies {
+        let (class_idx, left) = positions[&copy.left];
+        let (_, right) = positions[&copy.right];
+        let len = classes[class_idx].others.len();
+        // The proof of the copy from `h`, a proof of its class
+        let proof = |h: &str| {
+            let fact = |position: Option<usize>| position.map(|idx| format!("{h}{}", conjunct_projection(idx, len)));
+            match (fact(left), fact(right)) {
+                (Some(left), Some(right)) => format!("{left}.trans {right}.symm"),
+                (Some(left), None) => left,
+                (None, Some(right)) => format!("{right}.symm"),
+                (None, None) => "rfl".to_string(),
+            }
+        };
+
+        if let Some((unit, class, first, step, count)) = looped.get(&class_idx) {
+            // A copy in a loop is stated for every repetition at once, so that its size doesn't grow with their number
+            if !loop_copies.insert((*first, left, right)) {
+                continue;
+            }
+            let first = &classes[*first];
+            let (base, shape) = (first.base(), first.shape());
+            let cell_of = |position: Option<usize>| shape[position.map_or(0, |idx| idx + 1)];
+            let first_cell = |position| {
+                let (kind, column, offset) = cell_of(position);
+                Cell { kind, column, row: base + offset }
+            };
+            let name = namer.name(format!("copy_{}_{}", cell_name(&first_cell(left)), cell_name(&first_cell(right))));
+            let at = |(kind, column, offset): (ColumnKind, usize, usize)| cell_at(kind, column, loop_row(base + offset, *step));
+            let i = Expr::ident("i");
+            let prop = Expr::forall(
+                vec![Binder::new(&["i"], nat_type())],
+                bin(BinOp::Implies, bin(BinOp::Lt, i, Expr::nat(*count)), bin(BinOp::Eq, at(cell_of(left)), at(cell_of(right)))),
+            );
+            let lemma = Def::new(
+                namer.name(format!("{name}_of_class")),
+                [circuit_param(), vec![Binder::new(&["h"], Expr::app(class, [c()]))]].concat(),
+                Some(Expr::app(&name, [c()])),
+                Expr::raw(format!("by unfold {name}; unfold {class} at h; intro i hi; exact {}", proof("(h i hi)"))),
+            )
+            .theorem();
+            units[*unit].push(prop_def(&name, prop));
+            units[*unit].push(lemma.into());
+            continue;
+        }
+
+        let (unit, class) = &singles[&class_idx];
+        let name = namer.name(format!("copy_{}_{}", cell_name(&copy.left), cell_name(&copy.right)));
+        let lemma = Def::new(
+            namer.name(format!("{name}_of_class")),
+            [circuit_param(), vec![Binder::new(&["h"], Expr::app(class, [c()]))]].concat(),
+            Some(Expr::app(&name, [c()])),
+            Expr::raw(format!("by unfold {name}; unfold {class} at h; exact {}", proof("h"))),
+        )
+        .theorem();
+        units[*unit].push(prop_def(&name, bin(BinOp::Eq, cell(&copy.left), cell(&copy.right))));
+        units[*unit].push(lemma.into());
+    }
+
+    Section {
+        units,
+        summary: prop_groups("all_copy_constraints", members, GROUPING_SIZE),
+    }
+}
+
+// Branches are tried in order, giving `otherwise` if none match
+fn if_chain(branches: Vec<(Expr, Expr, Option<String>)>, otherwise: Expr) -> Expr {
+    branches
+        .into_iter()
+        .rev()
+        .fold(otherwise, |otherwise, (condition, value, comment)| {
+            let branch = Expr::ite(condition, value, otherwise);
+            match comment {
+                Some(comment) => branch.commented(comment),
+                None => branch,
+            }
+        })
+}
+
+// The condition for a run of rows, where `end` is inclusive
+fn row_range(start: usize, end: Option<usize>) -> Expr {
+    match end {
+        Some(end) => Expr::and([
+            bin(BinOp::Ge, row(), Expr::nat(start)),
+            bin(BinOp::Le, row(), Expr::nat(end)),
+        ]),
+        None => bin(BinOp::Eq, row(), Expr::nat(start)),
+    }
+}
+
+// The rows an evaluation lemma covers
+enum Rows {
+    At(usize),
+    // Inclusive
+    Between(usize, usize),
+    // Every later row
+    From(usize),
+    // The rows a fixed column is filled to, up to `c.usable_rows`
+    Fill(usize),
+}
+
+impl Rows {
+    // `group_values` gives the end of a run only if it covers more than one row
+    fn run(start: usize, end: Option<usize>) -> Self {
+        match end {
+            Some(end) if end > start => Rows::Between(start, end),
+            _ => Rows::At(start),
+        }
+    }
+}
+
+// A `@[simp]` lemma evaluating `{func} c row` on `rows`, see `evaluation_theorem`
+fn evaluation_lemma(func: &str, unfold: &[String], rows: Rows, value: Expr) -> Item {
+    evaluation_theorem(func, unfold, rows, value).with_attribute("simp").into()
+}
+
+// The name, parameters and left hand side of a theorem evaluating `{func} c row` on `rows`
+fn evaluation_statement(func: &str, rows: Rows) -> (String, Vec<Binder>, Expr) {
+    let row_param = || Binder::new(&["row"], nat_type());
+    let bound = |name: &str, condition: Expr| Binder::new(&[name], condition);
+    let at = |row: Expr| Expr::app(func, [c(), row]);
+    match rows {
+        Rows::At(row) => (format!("{func}_row_{row}"), vec![], at(Expr::nat(row))),
+        Rows::Between(start, end) => {
+            let mut params = vec![row_param()];
+            if start > 0 {
+                params.push(bound("h_start", bin(BinOp::Le, Expr::nat(start), row())));
+            }
+            params.push(bound("h_end", bin(BinOp::Le, row(), Expr::nat(end))));
+            (format!("{func}_rows_{start}_to_{end}"), params, at(row()))
+        },
+        Rows::From(start) => (
+            format!("{func}_rows_from_{start}"),
+            vec![row_param(), bound("h_start", bin(BinOp::Le, Expr::nat(start), row()))],
+            at(row()),
+        ),
+        Rows::Fill(start) => (
+            format!("{func}_fill"),
+            vec![
+                row_param(),
+                bound("h_start", bin(BinOp::Le, Expr::nat(start), row())),
+                bound("h_end", bin(BinOp::Lt, row(), Expr::ident("c.usable_rows"))),
+            ],
+            at(row()),
+        ),
+    }
+}
+
+// A theorem evaluating `{func} c row` on `rows`, proved by unfolding `unfold` and splitting the `if`s
+fn evaluation_theorem(func: &str, unfold: &[String], rows: Rows, value: Expr) -> Def {
+    let (name, params, lhs) = evaluation_statement(func, rows);
+    let proof = format!("by simp only [{}] <;> split_ifs <;> first | rfl | omega", unfold.join(", "));
+    Def::new(name, [circuit_param(), params].concat(), Some(bin(BinOp::Eq, lhs, value)), Expr::raw(proof)).theorem()
+}
+
+// `{summary} c {col} row = {func} c row`, so the evaluation lemmas of `func` apply to `summary`
+fn column_lemma(summary: &str, func: &str, col: usize) -> Item {
+    let params = [circuit_param(), vec![Binder::new(&["row"], nat_type())]].concat();
+    let statement = bin(BinOp::Eq, Expr::app(summary, [c(), Expr::nat(col), row()]), Expr::app(func, [c(), row()]));
+    Def::new(format!("{func}_eq"), params, Some(statement), Expr::raw("rfl"))
+        .theorem()
+        .with_attribute("simp")
+        .into()
+}
+
+// The maximal runs of consecutive rows, with inclusive ends
+fn row_runs(rows: impl IntoIterator<Item = usize>) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = vec![];
+    for row in rows {
+        match runs.last_mut() {
+            // We have found a row that connects to the current run
+            Some((_, end)) if *end + 1 == row => *end = row,
+            _ => runs.push((row, row)),
+        }
+    }
+    runs
+}
+
+// Whether a column with `runs` runs of values is worth replacing by a closed form whose pattern has `pattern_runs`
+fn worth_closed_form(runs: usize, pattern_runs: usize) -> bool {
+    runs > GROUPING_SIZE && 2 * pattern_runs <= runs
+}
+
+// `row - start`, the index of `row` into a block of rows starting at `start`
+fn block_index(start: usize) -> Expr {
+    if start == 0 {
+        row()
+    } else {
+        bin(BinOp::Sub, row(), Expr::nat(start))
+    }
+}
+
+// `(row - start) % period`, the offset of `row` into the repeating pattern
+fn pattern_offset(start: usize, period: usize) -> Expr {
+    bin(BinOp::Mod, block_index(start), Expr::nat(period))
+}
+
+// Closed forms are checked against more rows than this with `native_decide` rather than `decide`, which would be
+// too slow in the kernel
+const DECIDE_MAX_ROWS: usize = 64;
+
+// `{func}_data : List {ty}`, the values extracted on the rows a closed form covers, which it is checked against
+fn data_def(func: &str, ty: &str, values: impl IntoIterator<Item = Expr>) -> Item {
+    Def::new(format!("{func}_data"), vec![], Some(Expr::raw(format!("List {ty}"))), Expr::list(values)).into()
+}
+
+// `{func}_data.getD {index} 0`
+fn data_at(func: &str, index: Expr) -> Expr {
+    Expr::app(format!("{func}_data.getD"), [index, Expr::nat(0)])
+}
+
+// `{name} : ∀ i : ℕ, i < rows → {statement}`, a statement about the data of `rows` rows decided by the kernel
+fn data_check(name: &str, rows: usize, statement: Expr) -> Item {
+    let i = Expr::ident("i");
+    let prop = Expr::forall(vec![Binder::new(&["i"], nat_type())], bin(BinOp::Implies, bin(BinOp::Lt, i, Expr::nat(rows)), statement));
+    let tactic = if rows <= DECIDE_MAX_ROWS { "decide" } else { "native_decide" };
+    Def::new(name, vec![], Some(prop), Expr::raw(format!("by {tactic}"))).theorem().into()
+}
+
+// `{func}_eq_data`, stating that `func` agrees with `{func}_data` over `start..=end`
+fn data_theorem(func: &str, start: usize, end: usize, ty: &str, proof: String) -> Item {
+    let (_, params, lhs) = evaluation_statement(func, Rows::Between(start, end));
+    let value = Expr::raw(format!("(({} : {ty}) : ZMod P)", data_at(func, block_index(start))));
+    Def::new(format!("{func}_eq_data"), [circuit_param(), params].concat(), Some(bin(BinOp::Eq, lhs, value)), Expr::raw(proof))
+        .theorem()
+        .into()
+}
+
+// The data of a column repeating `{func}_period` over `start..=end`, the kernel's check that it repeats, and proofs that
+// the pattern and then the column agree with it
+fn periodic_data(func: &str, Periodic { start, end, period }: &Periodic, ty: &str, values: Vec<Expr>) -> Vec<Item> {
+    let (start, end, period) = (*start, *end, *period);
+    let pattern = format!("{func}_period");
+    let i = Expr::ident("i");
+    let repeats = bin(
+        BinOp::Eq,
+        data_at(func, bin(BinOp::Mod, i.clone(), Expr::nat(period))),
+        data_at(func, i),
+    );
+
+    // The pattern's own evaluation lemmas give its value at each offset
+    let (_, params, lhs) = evaluation_statement(&pattern, Rows::Between(0, period - 1));
+    let value = Expr::raw(format!("(({} : {ty}) : ZMod P)", data_at(func, row())));
+    let pattern_agrees = Def::new(
+        format!("{pattern}_eq_data"),
+        [circuit_param(), params].concat(),
+        Some(bin(BinOp::Eq, lhs, value)),
+        Expr::raw(format!("by interval_cases row <;> simp [{func}_data]")),
+    );
+
+    let by_omega = || Expr::raw("(by omega)");
+    let repeat = Expr::app(format!("{func}_data_periodic"), [block_index(start), by_omega()]);
+    let offset = Expr::app(format!("{pattern}_eq_data"), [c(), pattern_offset(start, period), by_omega()]);
+    let proof = format!("by rw [← {repeat}, ← {offset}]; simp only [{func}] <;> split_ifs <;> first | rfl | omega");
+    vec![
+        data_def(func, ty, values),
+        data_check(&format!("{func}_data_periodic"), end - start + 1, repeats),
+        pattern_agrees.theorem().into(),
+        data_theorem(func, start, end, ty, proof),
+    ]
+}
+
+// Periodic columns are emitted as a pattern over `0..period`, repeated over `start..=end`
+struct Periodic {
+    start: usize,
+    end: usize,
+    period: usize,
+}
+
+// `λ row => ...` enabling the selector on `runs` and giving the values of `folds` on their rows, adding a lemma for
+// each run, each fold and each gap up to `end` to `lemmas`
+//
+// A selector with more than GROUPING_SIZE runs, folds and gaps has them nested in definitions as `grouped_branches`
+// does, which are added to `items`
+fn selector_chain(
+    func: &str,
+    runs: &[(usize, usize)],
+    folds: Vec<(Expr, usize, usize)>,
+    end: Option<usize>,
+    items: &mut Vec<Item>,
+    lemmas: &mut Vec<Item>,
+) -> Expr {
+    let mut enabled = runs.iter().map(|&(start, end)| (Expr::nat(1), start, end)).chain(folds).collect_vec();
+    enabled.sort_by_key(|&(_, start, _)| start);
+    // The runs, the folds and the gaps before them as (value, start, end)
+    let mut segments = vec![];
+    let mut next = 0;
+    for (value, start, end) in enabled {
+        if start > next {
+            segments.push((Expr::nat(0), next, start - 1));
+        }
+        segments.push((value, start, end));
+        next = end + 1;
+    }
+    let trailing = match end {
+        Some(end) if end >= next => Some(Rows::run(next, Some(end))),
+        Some(_) => None,
+        None => Some(Rows::From(next)),
+    };
+
+    if segments.len() > GROUPING_SIZE {
+        let segments = segments.into_iter().map(|(value, start, end)| (value, start, (end > start).then_some(end))).collect_vec();
+        let mut groups = vec![];
+        let branches = grouped_branches(func, &segments, None, &Expr::nat(0), items, &mut groups);
+        for (value, start, end) in segments {
+            lemmas.push(evaluation_lemma(func, &unfold_for(func, &groups, start), Rows::run(start, end), value));
+        }
+        if let Some(rows) = trailing {
+            lemmas.push(evaluation_lemma(func, &[func.to_string()], rows, Expr::nat(0)));
+        }
+        return Expr::lambda(&["row"], if_chain(branches, Expr::nat(0)));
+    }
+
+    let unfold = [func.to_string()];
+    let branches = segments
+        .iter()
+        .map(|(value, _, end)| (bin(BinOp::Lt, row(), Expr::nat(end+1)), value.clone(), None))
+        .collect_vec();
+    for (value, start, end) in segments {
+        lemmas.push(evaluation_lemma(func, &unfold, Rows::run(start, Some(end)), value));
+    }
+    if let Some(rows) = trailing {
+        lemmas.push(evaluation_lemma(func, &unfold, rows, Expr::nat(0)));
+    }
+    Expr::lambda(&["row"], if_chain(branches, Expr::nat(0)))
+}
+
+// A selector enabled in a repeating pattern, such as every 300th row
+fn periodic_selector(row_set: &BTreeMap<usize, String>, runs: usize) -> Option<Periodic> {
+    let (&start, _) = row_set.first_key_value()?;
+    let (&end, _) = row_set.last_key_value()?;
+    let enabled = (start..=end).map(|row| row_set.contains_key(&row)).collect_vec();
+    let period = find_period(&enabled);
+    let pattern_runs = row_runs((0..period).filter(|&offset| enabled[offset])).len();
+    (2 * period <= enabled.len() && worth_closed_form(runs, pattern_runs)).then_some(Periodic { start, end, period })
+}
+
+// A region entered at least MIN_LOOP_LENGTH times, `step` rows apart, that enables the same selectors at the same
+// offsets from its first enabled row each time, such as a region assigned in a loop
+struct RegionLoop {
+    name: String,
+    base: usize,
+    step: usize,
+    count: usize,
+    // (column, offset) of each selector enabled, sorted
+    shape: Vec<(usize, usize)>,
+}
+
+impl RegionLoop {
+    // The first and last rows on which the loop enables selector `col`, with the offsets from the first row it enables
+    // it on in each repetition. None if the loop doesn't enable `col`, or another region enables it on those rows too
+    fn fold(&self, col: usize, row_set: &BTreeMap<usize, String>) -> Option<(usize, usize, Vec<usize>)> {
+        let offsets = self.shape.iter().filter(|&&(column, _)| column == col).map(|&(_, offset)| offset).collect_vec();
+        let (&first, &last) = offsets.first().zip(offsets.last())?;
+        let start = self.base + first;
+        let end = self.base + self.step * (self.count - 1) + last;
+        let offsets = offsets.iter().map(|offset| offset - first).collect_vec();
+        (row_set.range(start..=end).count() == offsets.len() * self.count).then_some((start, end, offsets))
+    }
+}
+
+// The loops among the regions entered more than once with the same name, in the order of their first rows
+fn region_loops(model: &CircuitModel) -> Vec<RegionLoop> {
+    // The first enabled row and the shape of each repetition of each region
+    let mut repetitions: BTreeMap<&str, Vec<(usize, Vec<(usize, usize)>)>> = BTreeMap::new();
+    for region in &model.region_selectors {
+        let Some(base) = region.enabled.iter().map(|&(_, row)| row).min() else {
+            continue;
+        };
+        let shape = region.enabled.iter().map(|&(column, row)| (column, row - base)).sorted().dedup().collect_vec();
+        repetitions.entry(&region.name).or_default().push((base, shape));
+    }
+
+    let mut loops = vec![];
+    for (name, mut repetitions) in repetitions {
+        repetitions.sort_by_key(|&(base, _)| base);
+        let mut rest = repetitions.as_slice();
+        while let [(base, shape), (next, _), ..] = rest {
+            let step = next - base;
+            let len = 1 + rest
+                .windows(2)
+                .take_while(|pair| pair[1].0 - pair[0].0 == step && pair[1].1 == *shape)
+                .count();
+            // Repetitions that overlap can't be told apart
+            let span = shape.iter().map(|&(_, offset)| offset).max().unwrap_or(0);
+            if len >= MIN_LOOP_LENGTH && span < step {
+                loops.push(RegionLoop {
+                    name: name.to_string(),
+                    base: *base,
+                    step,
+                    count: len,
+                    shape: shape.clone(),
+                });
+                rest = &rest[len..];
+            } else {
+                rest = &rest[1..];
+            }
+        }
+    }
+    loops.sort_by_key(|region_loop| region_loop.base);
+    loops
+}
+
+// Selectors enabled by a region loop are folded into a pattern `{func}_loop_{start}` over one repetition, with a
+// lemma `{func}_loop_{start}_at_{offset}` for each row it enables, quantified over the repetitions
+fn selectors(model: &CircuitModel) -> Section {
+    let loops = region_loops(model);
+    let mut units = vec![];
+    for (col, row_set) in &model.selectors {
+        let func = format!("selector_func_col_{col}");
+        let mut items = vec![];
+        let mut lemmas = vec![];
+        let runs = row_runs(row_set.keys().copied());
+        let body = if runs.is_empty() {
+            Expr::lambda(&["_"], Expr::nat(0))
+        } else if let Some(periodic) = periodic_selector(row_set, runs.len()) {
+            let Periodic { start, end, period } = periodic;
+            let pattern = format!("{func}_period");
+            let pattern_runs = row_runs((start..start + period).filter(|row| row_set.contains_key(row)).map(|row| row - start));
+            let pattern_body = selector_chain(&pattern, &pattern_runs, vec![], Some(period - 1), &mut items, &mut lemmas);
+            items.push(circuit_def(&pattern, Expr::raw("ℕ → ZMod P"), pattern_body));
+
+            let repeated = Expr::app(&pattern, [c(), pattern_offset(start, period)]);
+            let unfold = [func.clone()];
+            if start > 0 {
+                lemmas.push(evaluation_lemma(&func, &unfold, Rows::run(0, Some(start - 1)), Expr::nat(0)));
+            }
+            lemmas.push(evaluation_lemma(&func, &unfold, Rows::run(start, Some(end)), repeated.clone()));
+            lemmas.push(evaluation_lemma(&func, &unfold, Rows::From(end + 1), Expr::nat(0)));
+            let values = (start..=end).map(|row| Expr::nat(row_set.contains_key(&row).into())).collect();
+            lemmas.extend(periodic_data(&func, &periodic, "ℕ", values));
+            Expr::lambda(&["row"], if_chain(vec![(row_range(start, Some(end)), repeated, None)], Expr::nat(0)))
+        } else {
+            let mut folds = vec![];
+            let mut loop_lemmas = vec![];
+            for region_loop in &loops {
+                let Some((start, end, offsets)) = region_loop.fold(*col, row_set) else {
+                    continue;
+                };
+                let RegionLoop { name, step, count, .. } = region_loop;
+                let pattern = format!("{func}_loop_{start}");
+                let pattern_body = selector_chain(&pattern, &row_runs(offsets.iter().copied()), vec![], Some(step - 1), &mut items, &mut lemmas);
+                items.push(circuit_def(&pattern, Expr::raw("ℕ → ZMod P"), pattern_body));
+                folds.push((Expr::app(&pattern, [c(), pattern_offset(start, *step)]), start, end));
+
+                loop_lemmas.push(Item::Comment(format!("Region {name} repeated {count} times, every {step} rows")));
+                for offset in offsets {
+                    loop_lemmas.push(loop_lemma(&func, start, end, *step, *count, offset));
+                }
+            }
+            let unfolded = row_set.keys().copied().filter(|row| !folds.iter().any(|(_, start, end)| (start..=end).contains(&row)));
+            let body = selector_chain(&func, &row_runs(unfolded), folds, None, &mut items, &mut lemmas);
+            lemmas.extend(loop_lemmas);
+            body
+        };
+        items.push(circuit_def(&func, Expr::raw("ℕ → ZMod P"), body));
+        items.extend(lemmas);
+        units.push(items);
+    }
+
+    let mut arms = model
+        .selectors
+        .keys()
+        .map(|col| (Expr::nat(*col), Expr::app(format!("selector_func_col_{col}"), [c(), row()])))
+        .collect_vec();
+    arms.push((Expr::ident("_"), Expr::nat(0)));
+    let body = Expr::lambda(&["col", "row"], Expr::match_on(Expr::ident("col"), arms));
+    let mut summary = vec![circuit_def("selector_func", Expr::raw("ℕ → ℕ → ZMod P"), body)];
+    summary.extend(model.selectors.keys().map(|col| column_lemma("selector_func", &format!("selector_func_col_{col}"), *col)));
+    Section { units, summary }
+}
+
+// `{func}_loop_{start}_at_{offset} : ∀ i : ℕ, i < count → {func} c (step * i + start + offset) = 1`, for a selector
+// folded over `start..=end`
+fn loop_lemma(func: &str, start: usize, end: usize, step: usize, count: usize, offset: usize) -> Item {
+    let enabled = loop_row(start + offset, step);
+    let statement = bin(BinOp::Eq, Expr::app(func, [c(), enabled.clone()]), Expr::nat(1));
+    let i = Expr::ident("i");
+    let prop = Expr::forall(vec![Binder::new(&["i"], nat_type())], bin(BinOp::Implies, bin(BinOp::Lt, i, Expr::nat(count)), statement));
+
+    // The fold's own lemma gives the pattern at the offset, which the pattern's lemmas evaluate
+    let by_omega = Expr::raw("(by omega)");
+    let mut args = vec![c(), enabled.clone()];
+    if start > 0 {
+        args.push(by_omega.clone());
+    }
+    args.push(by_omega);
+    let fold = Expr::app(format!("{func}_rows_{start}_to_{end}"), args);
+    let shifted = if start == 0 { enabled } else { bin(BinOp::Sub, enabled, Expr::nat(start)) };
+    let pattern_offset = bin(BinOp::Eq, bin(BinOp::Mod, shifted, Expr::nat(step)), Expr::nat(offset));
+    let proof = format!("by intro i hi; rw [{fold}, show {pattern_offset} by omega]; simp");
+    Def::new(format!("{func}_loop_{start}_at_{offset}"), circuit_param(), Some(prop), Expr::raw(proof))
+        .theorem()
+        .with_attribute("simp")
+        .into()
+}
+
+fn fixed_unassigned(col: Expr) -> Expr {
+    Expr::app("c.1.FixedUnassigned", [col, row()])
+}
+
+// Fixed columns of constants whose values follow a pattern, over a block of rows that are all assigned
+enum FixedClosedForm {
+    Periodic(Periodic),
+    // The value at `row` is `Σ coefficients[i] * (row - start)^i`
+    Polynomial { start: usize, end: usize, coefficients: Vec<BigInt> },
+}
+
+const MAX_POLYNOMIAL_DEGREE: usize = 3;
+
+fn fixed_closed_form(row_set: &BTreeMap<usize, Term>, runs: usize) -> Option<FixedClosedForm> {
+    let (&start, _) = row_set.first_key_value()?;
+    let (&end, _) = row_set.last_key_value()?;
+    if end - start + 1 != row_set.len() {
+        return None;
+    }
+    // The closed form is checked against the values by the kernel, which can only decide equality of constants
+    let constants = row_set
+        .values()
+        .map(|value| match value {
+            Term::Const(value) => Some(value.clone()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let period = find_period(&constants);
+    if 2 * period <= constants.len() {
+        let pattern_runs = group_values(&row_set.values().take(period).enumerate().collect()).len();
+        if worth_closed_form(runs, pattern_runs) {
+            return Some(FixedClosedForm::Periodic(Periodic { start, end, period }));
+        }
+    }
+
+    let coefficients = find_polynomial(&constants, MAX_POLYNOMIAL_DEGREE)?;
+    (coefficients.len() > 1 && worth_closed_form(runs, 1)).then_some(FixedClosedForm::Polynomial { start, end, coefficients })
+}
+
+// `Σ coefficients[i] * (row - start)^i` in `ZMod P`
+fn polynomial(start: usize, coefficients: &[BigInt]) -> Expr {
+    let offset = if start == 0 {
+        Expr::raw("(row : ZMod P)")
+    } else {
+        Expr::raw(format!("((row - {start} : ℕ) : ZMod P)"))
+    };
+    polynomial_in(offset, coefficients)
+}
+
+// `Σ coefficients[i] * offset^i`
+fn polynomial_in(offset: Expr, coefficients: &[BigInt]) -> Expr {
+    coefficients
+        .iter()
+        .enumerate()
+        .filter(|(_, coefficient)| !coefficient.is_zero())
+        .map(|(degree, coefficient)| {
+            let power = match degree {
+                0 => return term(&Term::Const(coefficient.clone())),
+                1 => offset.clone(),
+                _ => bin(BinOp::Pow, offset.clone(), Expr::nat(degree)),
+            };
+            if coefficient.is_one() {
+                power
+            } else {
+                bin(BinOp::Mul, term(&Term::Const(coefficient.clone())), power)
+            }
+        })
+        .reduce(|sum, monomial| bin(BinOp::Add, sum, monomial))
+        .unwrap_or_else(|| Expr::nat(0))
+}
+
+// Columns with more runs than this are emitted as a literal rather than nested `if`s
+const LITERAL_MIN_RUNS: usize = GROUPING_SIZE * GROUPING_SIZE;
+
+// The value of a column with too many runs for nested `if`s, looked up in `{func}_values`
+//
+// A contiguous block of rows is an `Array` indexed from its first row, and anything else a `List` of `(row, value)`.
+// Returns the rows covered and the lookup, adding the literal to `items`
+fn fixed_literal(func: &str, row_set: &BTreeMap<usize, Term>, unassigned: &Expr, items: &mut Vec<Item>) -> (usize, usize, Expr) {
+    let (&start, _) = row_set.first_key_value().expect("A column with runs has rows");
+    let (&end, _) = row_set.last_key_value().expect("A column with runs has rows");
+    let name = format!("{func}_values");
+    let values = Expr::app(&name, [c()]);
+    let (ty, literal, lookup) = if end - start + 1 == row_set.len() {
+        let index = block_index(start);
+        (
+            "Array (ZMod P)",
+            Expr::array(row_set.values().map(term)),
+            Expr::app("Array.getD", [values, index, unassigned.clone()]),
+        )
+    } else {
+        (
+            "List (ℕ × ZMod P)",
+            Expr::list(row_set.iter().map(|(row, value)| Expr::tuple([Expr::nat(*row), term(value)]))),
+            Expr::app("Option.getD", [Expr::app("List.lookup", [row(), values]), unassigned.clone()]),
+        )
+    };
+    items.push(circuit_def(&name, Expr::raw(ty), literal));
+    (start, end, lookup)
+}
+
+// Branches choosing between the values of `runs`, in which every GROUPING_SIZE runs are nested in a definition
+// `{func}_{start}_to_{end}` until there are at most GROUPING_SIZE branches. The nested definitions are added to `items`
+// and their names and the rows they cover to `groups`
+fn grouped_branches(
+    func: &str,
+    runs: &[(Expr, usize, Option<usize>)],
+    annotations: Option<&ColumnAnnotations>,
+    otherwise: &Expr,
+    items: &mut Vec<Item>,
+    groups: &mut Vec<(String, usize, usize)>,
+) -> Vec<(Expr, Expr, Option<String>)> {
+    // (value, start, end, annotations already printed)
+    let mut entries = runs
+        .iter()
+        .map(|(a,b,c)| (a.clone(),*b,*c,false))
+        .collect_vec();
+
+    assert!(GROUPING_SIZE > 1);
+
+    while entries.len() > GROUPING_SIZE {
+        let mut new_entries = vec![];
+
+        while entries.len() > GROUPING_SIZE {
+            let start = entries[0].1;
+            let end = entries[GROUPING_SIZE-1].2.unwrap_or(entries[GROUPING_SIZE-1].1);
+            let name = format!("{func}_{start}_to_{end}");
+
+            let branches = entries
+                .drain(..GROUPING_SIZE)
+                .map(|(value, start, end, annotated)| {
+                    let annotation = match (annotated, annotations) {
+                        (false, Some(annotations)) => get_group_annotations(&annotations.rows, start, end.unwrap_or(start)),
+                        _ => None,
+                    };
+                    (row_range(start, end), value, annotation)
+                })
+                .collect_vec();
+            let body = Expr::lambda(&["row"], if_chain(branches, otherwise.clone()));
+            items.push(circuit_def(&name, Expr::raw("ℕ → ZMod P"), body));
+            groups.push((name.clone(), start, end));
+
+            new_entries.push((Expr::app(name, [c(), row()]), start, Some(end), true));
+        }
+
+        for new_entry in new_entries.into_iter().rev() {
+            entries.insert(0, new_entry);
+        }
+    }
+
+    entries
+        .into_iter()
+        .map(|(value, start, end, _)| (row_range(start, end), value, None))
+        .collect_vec()
+}
+
+// The definitions to unfold to evaluate `func` at `row`: `func` itself and the groups from `grouped_branches` that
+// contain `row`. Leaving the other groups folded keeps each proof to a few branches however many runs there are
+fn unfold_for(func: &str, groups: &[(String, usize, usize)], row: usize) -> Vec<String> {
+    let containing = groups.iter().filter(|(_, start, end)| (*start..=*end).contains(&row));
+    std::iter::once(func.to_string()).chain(containing.map(|(name, ..)| name.clone())).collect()
+}
+
+fn fixed(model: &CircuitModel) -> Section {
+    let mut units = vec![];
+    for (col, row_set) in &model.fixed {
+        let func = format!("fixed_func_col_{col}");
+        let unassigned = fixed_unassigned(Expr::nat(*col));
+        let mut items = vec![];
+        let mut lemmas = vec![];
+        let runs = group_values(row_set);
+        // The definitions to unfold to evaluate the column away from its runs
+        let unfold = [func.clone()];
+
+        let mut branches = match fixed_closed_form(row_set, runs.len()) {
+            Some(FixedClosedForm::Periodic(periodic)) => {
+                let Periodic { start, end, period } = periodic;
+                let pattern = format!("{func}_period");
+                let pattern_values = row_set.values().take(period).cloned().enumerate().collect();
+                let pattern_runs = group_values(&pattern_values);
+                let mut pattern_groups = vec![];
+                let pattern_values = pattern_runs.iter().map(|(value, start, end)| (term(value), *start, *end)).collect_vec();
+                let mut pattern_branches =
+                    grouped_branches(&pattern, &pattern_values, None, &unassigned, &mut items, &mut pattern_groups);
+                // Every offset is in the pattern, so the last run needs no condition
+                let (_, last, _) = pattern_branches.pop().expect("A periodic column has at least one run");
+                let pattern_body = Expr::lambda(&["row"], if_chain(pattern_branches, last));
+                items.push(circuit_def(&pattern, Expr::raw("ℕ → ZMod P"), pattern_body));
+                for (value, start, end) in &pattern_runs {
+                    let unfold = unfold_for(&pattern, &pattern_groups, *start);
+                    items.push(evaluation_lemma(&pattern, &unfold, Rows::run(*start, *end), term(value)));
+                }
+
+                let repeated = Expr::app(&pattern, [c(), pattern_offset(start, period)]);
+                lemmas.push(evaluation_lemma(&func, &unfold, Rows::run(start, Some(end)), repeated.clone()));
+                lemmas.extend(periodic_data(&func, &periodic, "ℤ", row_set.values().map(term).collect()));
+                vec![(row_range(start, Some(end)), repeated, None)]
+            },
+            Some(FixedClosedForm::Polynomial { start, end, coefficients }) => {
+                let value = polynomial(start, &coefficients);
+                lemmas.push(evaluation_lemma(&func, &unfold, Rows::run(start, Some(end)), value.clone()));
+                // The polynomial over ℤ, whose cast is the column's, is checked against the data
+                let i = Expr::ident("i");
+                let agrees = bin(BinOp::Eq, polynomial_in(Expr::raw("(i : ℤ)"), &coefficients), data_at(&func, i));
+                let agreement = Expr::app(format!("{func}_data_agrees"), [block_index(start), Expr::raw("(by omega)")]);
+                let proof = format!(
+                    "by rw [← {agreement}]; simp only [{func}] <;> split_ifs <;> first | omega | (push_cast <;> ring)"
+                );
+                lemmas.push(data_def(&func, "ℤ", row_set.values().map(term)));
+                lemmas.push(data_check(&format!("{func}_data_agrees"), end - start + 1, agrees));
+                lemmas.push(data_theorem(&func, start, end, "ℤ", proof));
+                vec![(row_range(start, Some(end)), value, None)]
+            },
+            None if runs.len() > LITERAL_MIN_RUNS => {
+                let (start, end, lookup) = fixed_literal(&func, row_set, &unassigned, &mut items);
+                // A lemma per run would unfold the literal each time, so the lookup is left to `rfl` or `decide`
+                let lemma = evaluation_theorem(&func, &unfold, Rows::run(start, Some(end)), lookup.clone());
+                lemmas.push(lemma.into());
+                vec![(row_range(start, Some(end)), lookup, None)]
+            },
+            None => {
+                let annotations = model.fixed_annotations.get(col);
+                let mut groups = vec![];
+                let values = runs.iter().map(|(value, start, end)| (term(value), *start, *end)).collect_vec();
+                let branches = grouped_branches(&func, &values, annotations, &unassigned, &mut items, &mut groups);
+                for (value, start, end) in &runs {
+                    let unfold = unfold_for(&func, &groups, *start);
+                    lemmas.push(evaluation_lemma(&func, &unfold, Rows::run(*start, *end), term(value)));
+                }
+                branches
+            },
+        };
+
+        if let Some(fill) = model.fixed_fill.get(col) {
+            let condition = Expr::and([
+                bin(BinOp::Ge, row(), Expr::nat(fill.row)),
+                bin(BinOp::Lt, row(), Expr::ident("c.usable_rows")),
+            ]);
+            branches.push((condition, term(&fill.value), None));
+            // The fill only holds where no assignment comes after it
+            if row_set.last_key_value().map_or(true, |(&last, _)| last < fill.row) {
+                lemmas.push(evaluation_lemma(&func, &unfold, Rows::Fill(fill.row), term(&fill.value)));
+            }
+        }
+        let body = Expr::lambda(&["row"], if_chain(branches, unassigned));
+        items.push(circuit_def(&func, Expr::raw("ℕ → ZMod P"), body));
+        items.extend(lemmas);
+        units.push(items);
+    }
+
+    let mut arms = model
+        .fixed
+        .keys()
+        .map(|col| {
+            let value = Expr::app(format!("fixed_func_col_{col}"), [c(), row()]);
+            match model.fixed_annotations.get(col).and_then(|annotations| annotations.column.as_ref()) {
+                Some(annotation) => (Expr::nat(*col), value.commented(annotation)),
+                None => (Expr::nat(*col), value),
+            }
+        })
+        .collect_vec();
+    arms.push((Expr::ident("_"), fixed_unassigned(Expr::ident("col"))));
+    let body = Expr::lambda(&["col", "row"], Expr::match_on(Expr::ident("col"), arms));
+    let mut summary = vec![circuit_def("fixed_func", Expr::raw("ℕ → ℕ → ZMod P"), body)];
+    summary.extend(model.fixed.keys().map(|col| column_lemma("fixed_func", &format!("fixed_func_col_{col}"), *col)));
+    Section { units, summary }
+}
+
+fn advice_phase(model: &CircuitModel) -> Item {
+    let mut arms = model
+        .advice_phases
+        .iter()
+        .enumerate()
+        .filter(|(_, phase)| **phase != 0)
+        .map(|(col, phase)| (Expr::nat(col), Expr::nat(*phase as usize)))
+        .collect_vec();
+    arms.push((Expr::ident("_"), Expr::nat(0)));
+    let body = Expr::lambda(&["col"], Expr::match_on(Expr::ident("col"), arms));
+    circuit_def("advice_phase", Expr::raw("ℕ → ℕ"), body)
+}
+
+fn column_annotations(kind: &str, annotations: &BTreeMap<usize, ColumnAnnotations>) -> Vec<Item> {
+    let mut items = vec![Item::Comment(format!("{kind} column annotations:"))];
+    if annotations.is_empty() {
+        items.push(Item::Comment("None".to_string()));
+    }
+    for (col, annotations) in annotations {
+        items.push(Item::Comment(format!("{kind} Column {col}")));
+        if let Some(column_annotation) = &annotations.column {
+            items.push(Item::Comment(column_annotation.clone()));
+        }
+        if let Some((start, _)) = annotations.rows.first_key_value() {
+            if let Some ((end, _)) = annotations.rows.last_key_value() {
+                if let Some(comments) = get_group_annotations(&annotations.rows, *start, *end) {
+                    items.push(Item::Comment(comments));
+                }
+            }
+        }
+    }
+    items
+}
+
+// The identifiers of a gate and of each of its constraints, or `None` for a constraint that is trivially true
+struct GateNames {
+    gate: String,
+    constraints: Vec<Option<String>>,
+}
+
+impl GateNames {
+    // Whether the gate has a definition, which it only does if some constraint isn't trivially true
+    fn is_defined(&self) -> bool {
+        self.constraints.iter().any(Option::is_some)
+    }
+}
+
+// Each gate gets a definition `gate_{gate name}`, the conjunction of `gate_{gate name}_{constraint name}` for each of its
+// constraints. Constraints without a name are numbered within their gate
+// Shared by the definitions and the proof skeleton, so that both use the same identifiers
+fn gate_names(model: &CircuitModel, namer: &mut Namer) -> Vec<GateNames> {
+    model
+        .gates
+        .iter()
+        .map(|gate| {
+            let gate_name = namer.name(format!("gate_{}", name_part(&gate.name)));
+            let constraints = gate
+                .constraints
+                .iter()
+                .enumerate()
+                .map(|(poly_idx, constraint)| {
+                    if constraint.polynomial.is_zero() {
+                        return None;
+                    }
+                    let part = if constraint.name.is_empty() {
+                        poly_idx.to_string()
+                    } else {
+                        mangle(&constraint.name)
+                    };
+                    Some(namer.name(format!("{gate_name}_{part}")))
+                })
+                .collect();
+            GateNames { gate: gate_name, constraints }
+        })
+        .collect()
+}
+
+// How a gate is described in comments
+fn gate_description(gate_idx: usize, gate: &Gate) -> String {
+    format!("Gate number {} name: {:?}", gate_idx+1, gate.name)
+}
+
+// How a constraint is described in comments
+fn constraint_description(gate_idx: usize, gate: &Gate, poly_idx: usize) -> String {
+    let constraint = &gate.constraints[poly_idx];
+    format!("{} part {}/{} {}", gate_description(gate_idx, gate), poly_idx+1, gate.constraints.len(), constraint.name)
+}
+
+fn gates(model: &CircuitModel, mode: GateMode) -> Section {
+    if mode == GateMode::Unrolled {
+        return unrolled_gates(model);
+    }
+    let mut units = vec![];
+    let mut members = vec![];
+    let mut namer = Namer::default();
+    let names = gate_names(model, &mut namer);
+    for (gate_idx, (gate, names)) in model.gates.iter().zip(names).enumerate() {
+        let polynomials = gate.constraints.iter().map(|constraint| &constraint.polynomial);
+        let sharing = Sharing::new(polynomials, |idx| namer.name(format!("{}_shared_{idx}", names.gate)));
+        let mut items = sharing.helpers();
+        // Each gate can contain many polynomials, so we need an inner iteration
+        for (poly_idx, name) in names.constraints.iter().enumerate() {
+            let description = constraint_description(gate_idx, gate, poly_idx);
+            match name {
+                None => items.push(Item::Comment(format!("{description} is trivially true"))),
+                Some(name) => {
+                    let polynomial = sharing.polynomial(poly_idx);
+                    let prop = Expr::forall(
+                        vec![Binder::new(&["row"], nat_type())],
+                        bin(BinOp::Eq, polynomial, Expr::nat(0)),
+                    );
+                    items.push(prop_def(name, prop.commented(description)));
+                },
+            }
+        }
+        if names.is_defined() {
+            let conjuncts = names.constraints.iter().flatten().map(|name| Expr::app(name, [c()]));
+            items.push(prop_def(&names.gate, Expr::and(conjuncts)));
+            members.push(names.gate);
+        }
+        units.push(items);
+    }
+
+    Section {
+        units,
+        summary: prop_groups("all_gates", members, GROUPING_SIZE),
+    }
+}
+
+// The distinct rows of a lookup table whose every usable row is known from the model, or None if any is unknown
+//
+// The rows after the extracted ones all hold the fill of the fixed columns, so they only add one more row. That row
+// is only there if `c.usable_rows` is larger than the extracted rows, so it has to be one of the extracted rows for
+// membership to mean the same as the row search
+fn known_table(model: &CircuitModel, table: &[ModelExpr]) -> Option<Vec<Expr>> {
+    let tuple = |at: Option<usize>| {
+        let mut evaluator = Evaluator::new(model).usable();
+        let values = table.iter().map(|expr| evaluator.evaluate(expr, at)).collect_vec();
+        let mut values = values.iter().map(|value| evaluator.to_lean(&value.to_term())).collect_vec();
+        let row = if values.len() == 1 { values.remove(0) } else { Expr::tuple(values) };
+        evaluator.is_known().then_some(row)
+    };
+    let mut seen = HashSet::new();
+    let mut rows = vec![];
+    for at in 0..model.usable_rows {
+        let row = tuple(Some(at))?;
+        if seen.insert(row.to_string()) {
+            rows.push(row);
+        }
+    }
+    seen.contains(&tuple(None)?.to_string()).then_some(rows)
+}
+
+fn lookups(model: &CircuitModel) -> Section {
+    let mut namer = Namer::default();
+    let lookup_row = Expr::ident("lookup_row");
+    let mut units = vec![];
+    let mut members = vec![];
+    for (idx, lookup) in model.lookups.iter().enumerate() {
+        let name = namer.name(format!("lookup_{}", name_part(&lookup.name)));
+        let mut items = vec![];
+        let lhs = Expr::tuple(lookup.inputs.iter().map(|expr| expression_to_lean(expr, &row())));
+        let matched = match known_table(model, &lookup.table) {
+            Some(rows) => {
+                let table = namer.name(format!("{name}_table"));
+                let ty = vec!["ZMod P"; lookup.table.len()].join(" × ");
+                items.push(circuit_def(&table, Expr::raw(format!("List ({ty})")), Expr::list(rows)));
+                bin(BinOp::Mem, lhs, Expr::app(&table, [c()]))
+            },
+            None => {
+                let rhs = Expr::tuple(lookup.table.iter().map(|expr| expression_to_lean(expr, &lookup_row)));
+                Expr::exists(
+                    vec![Binder::new(&["lookup_row"], nat_type())],
+                    Expr::and([
+                        bin(BinOp::Lt, lookup_row.clone(), Expr::ident("c.usable_rows")),
+                        bin(BinOp::Eq, lhs, rhs),
+                    ]),
+                )
+            },
+        };
+        let prop = Expr::forall(
+            vec![Binder::new(&["row"], nat_type())],
+            bin(BinOp::Implies, bin(BinOp::Lt, row(), Expr::ident("c.usable_rows")), matched),
+        );
+        items.push(prop_def(&name, prop.commented(format!("Lookup number {} name: {:?}", idx+1, lookup.name))));
+        units.push(items);
+        members.push(name);
+    }
+
+    Section {
+        units,
+        summary: prop_groups("all_lookups", members, GROUPING_SIZE),
+    }
+}
+
+fn shuffles(model: &CircuitModel) -> Section {
+    let mut namer = Namer::default();
+    let mut units = vec![];
+    let mut shuffle_names = vec![];
+    let shuffled_row = Expr::app("shuffle", [row()]);
+    for shuffle in &model.shuffles {
+        let name = namer.name(format!("shuffle_{}", name_part(&shuffle.name)));
+        shuffle_names.push(name.clone());
+        let lhs = Expr::tuple(shuffle.inputs.iter().map(|expr| expression_to_lean(expr, &row())));
+        let rhs = Expr::tuple(shuffle.shuffles.iter().map(|expr| expression_to_lean(expr, &shuffled_row)));
+        let body = Expr::exists(
+            vec![Binder::untyped(&["shuffle"])],
+            Expr::and([
+                Expr::app("is_shuffle", [c(), Expr::ident("shuffle")]),
+                Expr::forall(
+                    vec![Binder::new(&["row"], nat_type())],
+                    bin(BinOp::Implies, bin(BinOp::Lt, row(), Expr::ident("c.usable_rows")), bin(BinOp::Eq, lhs, rhs)),
+                ),
+            ]),
+        );
+        let def = Def::new(name, circuit_param(), Some(Expr::raw("Prop")), body).with_doc(format!("Shuffle {:?}", shuffle.name));
+        units.push(vec![def.into()]);
+    }
+
+    let all_shuffles_body = Expr::and(shuffle_names.into_iter().map(|name| Expr::app(name, [c()])));
+    Section {
+        units,
+        summary: vec![prop_def("all_shuffles", all_shuffles_body)],
+    }
+}
+
+pub fn print_grouping_props(out: &mut impl Write, model: &CircuitModel, mode: GateMode) -> io::Result<()> {
+    write_items(out, [Item::Blank, Item::Blank])?;
+    write_items(out, copy_constraints(model).into_items())?;
+    write_items(out, selectors(model).into_items())?;
+    write_items(out, fixed(model).into_items())?;
+    write_items(out, [advice_phase(model)])?;
+    write_items(out, column_annotations("Advice", &model.advice_annotations))?;
+    write_items(out, column_annotations("Instance", &model.instance_annotations))?;
+    write_items(out, gates(model, mode).into_items())?;
+    write_items(out, lookups(model).into_items())?;
+    write_items(out, shuffles(model).into_items())
+}
+
+// The circuit's own `Circuit` adds its symbols to the fields of `Halva.Circuit`
+fn circuit_structure(model: &CircuitModel) -> Item {
+    let fields = model
+        .symbols
+        .iter()
+        .map(|symbol| (format!("sym_{}", symbol.name), Expr::raw(symbol.lean_type())))
+        .collect();
+    Item::Structure {
+        name: "Circuit".to_string(),
+        params: vec![
+            Binder::new(&["P"], nat_type()),
+            Binder::new(&["P_Prime"], Expr::raw("Nat.Prime P")),
+        ],
+        extends: Some(Expr::raw(format!("{HALVA_MODULE}.Circuit P P_Prime"))),
+        fields,
+    }
+}
+
+fn circuit_is_valid(model: &CircuitModel) -> Item {
+    let mut conjuncts = vec![Expr::app("c.toCircuit.isValid", [Expr::nat(model.num_advice_columns)])];
+    conjuncts.extend(model.symbols.iter().filter_map(|symbol| symbol.domain_hypothesis("c")).map(Expr::raw));
+    Def::new(
+        "Circuit.isValid",
+        vec![Binder::new(&["c"], Expr::raw("Circuit P P_Prime"))],
+        Some(Expr::raw("Prop")),
+        Expr::and(conjuncts),
+    )
+    .into()
+}
+
+// The accessors of the `ValidCircuit` namespace, which unfold to those of `Halva.Circuit`
+fn valid_circuit_accessors() -> Vec<Item> {
+    let accessor = |name: &str, ty: Option<Expr>| -> Item {
+        Def::new(name, circuit_param(), ty, Expr::ident(format!("c.1.toCircuit.{name}"))).abbrev().into()
+    };
+    let mut items = ["get_advice", "get_fixed", "get_instance", "get_selector", "get_challenge"]
+        .map(|name| accessor(name, Some(Expr::raw("ℕ → ℕ → ZMod P"))))
+        .to_vec();
+    items.extend(["k", "n", "usable_rows", "S", "T"].map(|name| accessor(name, Some(nat_type()))));
+    items.extend(["mult_gen", "root_of_unity", "delta"].map(|name| accessor(name, Some(zmod()))));
+    items
+}
+
+// The `variable` command declaring whichever of `P` and `P_Prime` the definitions are generic over
+pub(crate) fn variables(model: &CircuitModel) -> Item {
+    let p_prime = Binder::implicit(&["P_Prime"], Expr::raw("Nat.Prime P"));
+    match model.modulus {
+        Some(_) => Item::Variable(vec![p_prime]),
+        None => Item::Variable(vec![Binder::implicit(&["P"], nat_type()), p_prime]),
+    }
+}
+
+// Only `Halva` imports these directly
+const MATHLIB_IMPORTS: [&str; 4] = [
+    "Mathlib.Data.Nat.Prime.Defs",
+    "Mathlib.Data.Nat.Prime.Basic",
+    "Mathlib.Data.ZMod.Defs",
+    "Mathlib.Data.ZMod.Basic",
+];
+
+/// Write the start of the single file output, up to the definitions of the circuit
+///
+/// A single file has nothing to import `Halva` from, so the library is written inline, ahead of the circuit
+pub fn print_preamble(out: &mut impl Write, model: &CircuitModel) -> io::Result<()> {
+    // The library already turns off the unused variables linter for the rest of the file
+    let mut items = halva();
+    items.extend([Item::Blank, Item::Namespace(model.namespace.clone()), Item::Blank]);
+    items.extend(preamble(model));
+    write_items(out, items)
+}
+
+// The definitions every other part of the output depends on, other than those shared through `Halva`
+fn preamble(model: &CircuitModel) -> Vec<Item> {
+    let mut items = vec![circuit_structure(model)];
+
+    if let Some(modulus) = &model.modulus {
+        items.push(Def::new("P", vec![], Some(nat_type()), Expr::raw(modulus)).abbrev().into());
+    }
+    items.push(variables(model));
+    items.push(circuit_is_valid(model));
+
+    items.push(
+        Def::new(
+            "ValidCircuit",
+            vec![Binder::new(&["P"], nat_type()), Binder::new(&["P_Prime"], Expr::raw("Nat.Prime P"))],
+            Some(Expr::raw("Type")),
+            Expr::raw("{c: Circuit P P_Prime // c.isValid}"),
+        )
+        .abbrev()
+        .into(),
+    );
+    items.push(Item::Namespace("ValidCircuit".to_string()));
+    items.extend(valid_circuit_accessors());
+    items.push(Item::End("ValidCircuit".to_string()));
+
+    items.push(
+        Def::new(
+            "is_shuffle",
+            [circuit_param(), vec![Binder::new(&["shuffle"], Expr::raw("ℕ → ℕ"))]].concat(),
+            Some(Expr::raw("Prop")),
+            Expr::app("c.1.toCircuit.is_shuffle", [Expr::ident("shuffle")]),
+        )
+        .abbrev()
+        .into(),
+    );
+
+    items.push(prop_def(
+        "sufficient_rows",
+        Expr::app("c.1.toCircuit.sufficient_rows", [Expr::nat(model.minimum_rows)]).commented("cs.minimum_rows"),
+    ));
+
+    items.push(Item::Comment("End preamble".to_string()));
+    items
+}
+
+// The extracted constraints are only faithful to the circuit if these hold, so proofs should take them as a hypothesis
+fn extraction_assumptions(model: &CircuitModel) -> Item {
+    let conjuncts = model
+        .assumptions
+        .iter()
+        .map(|assumption| Expr::raw(assumption).commented(format!("assumed by {}", assumption.source())));
+    prop_def("extraction_assumptions", Expr::and(conjuncts))
+}
+
+pub fn print_assumptions(out: &mut impl Write, model: &CircuitModel) -> io::Result<()> {
+    write_items(out, [extraction_assumptions(model)])
+}
+
+pub fn print_postamble(out: &mut impl Write, model: &CircuitModel) -> io::Result<()> {
+    print_assumptions(out, model)?;
+    write_items(out, [meets_constraints(model), Item::End(model.namespace.clone())])
+}
+
+// The conjuncts of `meets_constraints`, each with a name for the lemma extracting it
+fn meets_constraints_conjuncts(model: &CircuitModel) -> Vec<(&'static str, Expr)> {
+    let applied = |name: &str| Expr::app(name, [c()]);
+    let instance_unassigned = Expr::forall(
+        vec![Binder::new(&["col", "row"], nat_type())],
+        bin(
+            BinOp::Implies,
+            Expr::and([
+                bin(BinOp::Lt, row(), Expr::ident("c.n")),
+                bin(BinOp::Ge, row(), Expr::ident("c.usable_rows")),
+            ]),
+            bin(
+                BinOp::Eq,
+                Expr::app("c.1.Instance", [Expr::ident("col"), row()]),
+                Expr::app("c.1.InstanceUnassigned", [Expr::ident("col"), row()]),
+            ),
+        ),
+    );
+    vec![
+        ("sufficient_rows", applied("sufficient_rows")),
+        ("num_blinding_factors", bin(BinOp::Eq, Expr::ident("c.1.num_blinding_factors"), Expr::nat(model.blinding_factors))),
+        ("selector", bin(BinOp::Eq, Expr::ident("c.1.Selector"), applied("selector_func"))),
+        ("fixed", bin(BinOp::Eq, Expr::ident("c.1.Fixed"), applied("fixed_func"))),
+        ("advice_phase", bin(BinOp::Eq, Expr::ident("c.1.AdvicePhase"), applied("advice_phase"))),
+        ("usable_rows", bin(BinOp::Ge, Expr::ident("c.usable_rows"), Expr::nat(model.usable_rows))),
+        ("all_gates", applied("all_gates")),
+        ("all_copy_constraints", applied("all_copy_constraints")),
+        ("all_lookups", applied("all_lookups")),
+        ("all_shuffles", applied("all_shuffles")),
+        ("instance_unassigned", instance_unassigned),
+    ]
+}
+
+fn meets_constraints(model: &CircuitModel) -> Item {
+    let conjuncts = meets_constraints_conjuncts(model).into_iter().map(|(_, conjunct)| conjunct);
+    prop_def("meets_constraints", Expr::and(conjuncts))
+}
+
+/// The value of `expr` at the row `row`
+pub fn expression_to_lean(expr: &ModelExpr, row: &Expr) -> Expr {
+    let query = |getter: &str, column: usize, rotation: i32| {
+        let n = Expr::ident("c.n");
+        let row = if rotation == 0 {
+            row.clone()
+        } else if rotation > 0 {
+            bin(BinOp::Mod, bin(BinOp::Add, row.clone(), Expr::nat(rotation as usize)), n)
+        } else {
+            let offset = bin(BinOp::Mod, Expr::nat(rotation.unsigned_abs() as usize), n.clone());
+            bin(BinOp::Mod, bin(BinOp::Sub, bin(BinOp::Add, row.clone(), n.clone()), offset), n)
+        };
+        Expr::app(getter, [Expr::nat(column), row])
+    };
+
+    match expr {
+        ModelExpr::Constant(value) => term(value),
+        ModelExpr::Selector(selector) => Expr::app("c.get_selector", [Expr::nat(*selector), row.clone()]),
+        ModelExpr::Fixed { column, rotation } => query("c.get_fixed", *column, *rotation),
+        ModelExpr::Advice { column, rotation } => query("c.get_advice", *column, *rotation),
+        ModelExpr::Instance { column, rotation } => query("c.get_instance", *column, *rotation),
+        ModelExpr::Challenge { index, phase } => {
+            Expr::app("c.get_challenge", [Expr::nat(*index), Expr::nat(*phase as usize)])
+        },
+        ModelExpr::Negated(expression) => Expr::negate(expression_to_lean(expression, row)),
+        ModelExpr::Sum(lhs, rhs) => bin(BinOp::Add, expression_to_lean(lhs, row), expression_to_lean(rhs, row)),
+        ModelExpr::Product(lhs, rhs) => bin(BinOp::Mul, expression_to_lean(lhs, row), expression_to_lean(rhs, row)),
+        ModelExpr::Scaled(expression, factor) => bin(BinOp::Mul, term(factor), expression_to_lean(expression, row)),
+    }
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use crate::field::take_policy_report;
+    use crate::model::{Constraint, CopyConstraint, FixedFill, Lookup, RegionSelectors, MODEL_VERSION};
+
+    use super::*;
+
+    pub(super) fn constant(value: i64) -> Term {
+        Term::Const(value.into())
+    }
+
+    pub(super) fn advice(column: usize, rotation: i32) -> ModelExpr {
+        ModelExpr::Advice { column, rotation }
+    }
+
+    pub(super) fn cell(kind: ColumnKind, column: usize, row: usize) -> Cell {
+        Cell { kind, column, row }
+    }
+
+    // A circuit with no gates, fixed values or copies, for each test to fill in
+    pub(crate) fn sample_model() -> CircuitModel {
+        CircuitModel {
+            version: MODEL_VERSION,
+            namespace: "Test".to_string(),
+            modulus: None,
+            symbols: vec![],
+            num_advice_columns: 3,
+            num_fixed_columns: 2,
+            num_instance_columns: 1,
+            num_selectors: 2,
+            advice_phases: vec![0, 0, 0],
+            minimum_rows: 6,
+            blinding_factors: 5,
+            usable_rows: 8,
+            regions: vec![],
+            region_selectors: vec![],
+            copies: vec![],
+            selectors: BTreeMap::new(),
+            fixed: BTreeMap::new(),
+            fixed_fill: BTreeMap::new(),
+            advice_annotations: BTreeMap::new(),
+            fixed_annotations: BTreeMap::new(),
+            instance_annotations: BTreeMap::new(),
+            gates: vec![],
+            lookups: vec![],
+            shuffles: vec![],
+            assumptions: vec![],
+            policy_report: take_policy_report(),
+        }
+    }
+
+    pub(super) fn printed(model: &CircuitModel, mode: GateMode) -> String {
+        let mut out = vec![];
+        print_circuit_with(&mut out, model, mode).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    // The text from the line starting with `start` up to the line starting with `end`
+    pub(super) fn between<'a>(text: &'a str, start: &str, end: &str) -> &'a str {
+        let from = text.find(&format!("\n{start}")).unwrap() + 1;
+        let to = from + text[from..].find(&format!("\n{end}")).unwrap() + 1;
+        &text[from..to]
+    }
+
+    pub(super) fn enabled(rows: impl IntoIterator<Item = usize>) -> BTreeMap<usize, String> {
+        rows.into_iter().map(|row| (row, String::new())).collect()
+    }
+
+    pub(super) fn column(rows: impl IntoIterator<Item = usize>, value: impl Fn(usize) -> i64) -> BTreeMap<usize, Term> {
+        rows.into_iter().map(|row| (row, constant(value(row)))).collect()
+    }
+
+    #[test]
+    fn test_gate_names() {
+        let gate = |name: &str| Gate {
+            name: name.to_string(),
+            constraints: vec![
+                Constraint { name: String::new(), polynomial: advice(0, 0) },
+                Constraint { name: "sum".to_string(), polynomial: ModelExpr::Sum(Box::new(advice(0, 0)), Box::new(advice(1, 0))) },
+            ],
+        };
+        let mut model = sample_model();
+        model.gates = (0..40).map(|i| gate(&format!("g{i}"))).collect();
+        let before = printed(&model, GateMode::Quantified);
+        model.gates.insert(0, gate("inserted"));
+        let after = printed(&model, GateMode::Quantified);
+
+        // The definitions whose name starts with `prefix`, each up to the next definition
+        let definitions = |text: &str, prefix: &str| -> HashSet<String> {
+            text.split("\ndef ").filter(|item| item.starts_with(prefix)).map(str::to_string).collect()
+        };
+        let names = |text: &str| -> HashSet<String> {
+            definitions(text, "gate_").iter().map(|item| item.split(' ').next().unwrap().to_string()).collect()
+        };
+        let added = names(&after).difference(&names(&before)).cloned().sorted().collect_vec();
+        assert_eq!(added, ["gate_inserted", "gate_inserted_0", "gate_inserted_sum"]);
+        assert!(names(&before).is_subset(&names(&after)));
+
+        // Only the group the new gate hashes into is redefined
+        let groups_before = definitions(&before, "all_gates_");
+        let groups_after = definitions(&after, "all_gates_");
+        assert!(groups_before.len() > 1);
+        let changed = groups_before.difference(&groups_after).collect_vec();
+        assert!(changed.len() <= 1, "{changed:?}");
+        assert_eq!(groups_after.iter().filter(|group| group.contains("gate_inserted c")).count(), 1);
+    }
+
+    #[test]
+    fn test_closed_forms() {
+        let mut model = sample_model();
+        model.fixed.insert(0, column(0..16, |row| [0, 0, 1, 5][row % 4]));
+        model.fixed.insert(1, column(3..15, |row| 2 * row as i64 + 5));
+        model.selectors.insert(1, enabled((2..36).filter(|row| (row - 2) % 3 == 0)));
+        let text = printed(&model, GateMode::Quantified);
+
+        let expected = r#"def selector_func_col_1_data : List ℕ :=
+  [1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 0,
+    0, 1]
+theorem selector_func_col_1_data_periodic : ∀ i : ℕ, i < 34 → selector_func_col_1_data.getD (i % 3) 0 = selector_func_col_1_data.getD i 0 := by decide
+theorem selector_func_col_1_period_eq_data (c : ValidCircuit P P_Prime) (row : ℕ) (h_end : row ≤ 2) : selector_func_col_1_period c row = ((selector_func_col_1_data.getD row 0 : ℕ) : ZMod P) := by interval_cases row <;> simp [selector_func_col_1_data]
+theorem selector_func_col_1_eq_data (c : ValidCircuit P P_Prime) (row : ℕ) (h_start : 2 ≤ row) (h_end : row ≤ 35) : selector_func_col_1 c row = ((selector_func_col_1_data.getD (row - 2) 0 : ℕ) : ZMod P) := by rw [← selector_func_col_1_data_periodic (row - 2) (by omega), ← selector_func_col_1_period_eq_data c ((row - 2) % 3) (by omega)]; simp only [selector_func_col_1] <;> split_ifs <;> first | rfl | omega
+"#;
+        assert_eq!(between(&text, "def selector_func_col_1_data", "def selector_func "), expected);
+        let expected = r#"def fixed_func_col_0_data : List ℤ := [0, 0, 1, 5, 0, 0, 1, 5, 0, 0, 1, 5, 0, 0, 1, 5]
+theorem fixed_func_col_0_data_periodic : ∀ i : ℕ, i < 16 → fixed_func_col_0_data.getD (i % 4) 0 = fixed_func_col_0_data.getD i 0 := by decide
+theorem fixed_func_col_0_period_eq_data (c : ValidCircuit P P_Prime) (row : ℕ) (h_end : row ≤ 3) : fixed_func_col_0_period c row = ((fixed_func_col_0_data.getD row 0 : ℤ) : ZMod P) := by interval_cases row <;> simp [fixed_func_col_0_data]
+theorem fixed_func_col_0_eq_data (c : ValidCircuit P P_Prime) (row : ℕ) (h_end : row ≤ 15) : fixed_func_col_0 c row = ((fixed_func_col_0_data.getD row 0 : ℤ) : ZMod P) := by rw [← fixed_func_col_0_data_periodic row (by omega), ← fixed_func_col_0_period_eq_data c (row % 4) (by omega)]; simp only [fixed_func_col_0] <;> split_ifs <;> first | rfl | omega
+"#;
+        assert_eq!(between(&text, "def fixed_func_col_0_data", "def fixed_func_col_1 "), expected);
+        let expected = r#"def fixed_func_col_1 (c : ValidCircuit P P_Prime) : ℕ → ZMod P :=
+  λ row =>
+  if row ≥ 3 ∧ row ≤ 14 then 11 + 2 * ((row - 3 : ℕ) : ZMod P)
+  else c.1.FixedUnassigned 1 row
+@[simp]
+theorem fixed_func_col_1_rows_3_to_14 (c : ValidCircuit P P_Prime) (row : ℕ) (h_start : 3 ≤ row) (h_end : row ≤ 14) : fixed_func_col_1 c row = 11 + 2 * ((row - 3 : ℕ) : ZMod P) := by simp only [fixed_func_col_1] <;> split_ifs <;> first | rfl | omega
+def fixed_func_col_1_data : List ℤ := [11, 13, 15, 17, 19, 21, 23, 25, 27, 29, 31, 33]
+theorem fixed_func_col_1_data_agrees : ∀ i : ℕ, i < 12 → 11 + 2 * (i : ℤ) = fixed_func_col_1_data.getD i 0 := by decide
+theorem fixed_func_col_1_eq_data (c : ValidCircuit P P_Prime) (row : ℕ) (h_start : 3 ≤ row) (h_end : row ≤ 14) : fixed_func_col_1 c row = ((fixed_func_col_1_data.getD (row - 3) 0 : ℤ) : ZMod P) := by rw [← fixed_func_col_1_data_agrees (row - 3) (by omega)]; simp only [fixed_func_col_1] <;> split_ifs <;> first | omega | (push_cast <;> ring)
+"#;
+        assert_eq!(between(&text, "def fixed_func_col_1 ", "def fixed_func "), expected);
+    }
+
+    #[test]
+    fn test_literals() {
+        let mut model = sample_model();
+        // Pseudo-random values, so that no closed form applies
+        let value = |row: usize| ((row * row * 31 + row * 17) % 1009) as i64;
+        model.fixed.insert(0, column(0..110, value));
+        model.fixed.insert(1, column((0..165).filter(|row| row % 3 != 0), value));
+        let text = printed(&model, GateMode::Quantified);
+
+        let expected = r#"def fixed_func_col_0_values (c : ValidCircuit P P_Prime) : Array (ZMod P) :=
+  #[0, 48, 158, 330, 564, 860, 209, 629, 102, 646, 243, 911, 632, 415, 260, 167, 136, 167, 260, 415,
+    632, 911, 243, 646, 102, 629, 209, 860, 564, 330, 158, 48, 0, 14, 90, 228, 428, 690, 5, 391,
+    839, 340, 912, 537, 224, 982, 793, 666, 601, 598, 657, 778, 961, 197, 504, 873, 295, 788, 334,
+    951, 621, 353, 147, 3, 930, 910, 952, 47, 213, 441, 731, 74, 488, 964, 493, 84, 746, 461, 238,
+    77, 987, 950, 975, 53, 202, 413, 686, 12, 409, 868, 380, 963, 599, 297, 57, 888, 772, 718, 726,
+    796, 928, 113, 369, 687, 58, 500, 1004, 561, 180, 870]
+def fixed_func_col_0 (c : ValidCircuit P P_Prime) : ℕ → ZMod P :=
+  λ row =>
+  if row ≥ 0 ∧ row ≤ 109 then Array.getD (fixed_func_col_0_values c) row (c.1.FixedUnassigned 0 row)
+  else c.1.FixedUnassigned 0 row
+theorem fixed_func_col_0_rows_0_to_109 (c : ValidCircuit P P_Prime) (row : ℕ) (h_end : row ≤ 109) : fixed_func_col_0 c row = Array.getD (fixed_func_col_0_values c) row (c.1.FixedUnassigned 0 row) := by simp only [fixed_func_col_0] <;> split_ifs <;> first | rfl | omega
+"#;
+        assert_eq!(between(&text, "def fixed_func_col_0_values", "def fixed_func_col_1_values"), expected);
+        // Sparse columns are a list of the assigned rows
+        assert!(text.contains("def fixed_func_col_1_values (c : ValidCircuit P P_Prime) : List (ℕ × ZMod P) :=\n  [(1, 48), (2, 158), (4, 564),"));
+        assert!(text.contains("then Option.getD (List.lookup row (fixed_func_col_1_values c)) (c.1.FixedUnassigned 1 row)\n"));
+    }
+
+    #[test]
+    fn test_copy_classes() {
+        let mut model = sample_model();
+        let advice = |column, row| cell(ColumnKind::Advice, column, row);
+        let instance = |row| cell(ColumnKind::Instance, 0, row);
+        model.copies = vec![
+            CopyConstraint { left: advice(0, 1), right: advice(1, 0) },
+            CopyConstraint { left: advice(1, 0), right: instance(2) },
+            CopyConstraint { left: advice(2, 5), right: advice(0, 1) },
+            CopyConstraint { left: advice(2, 7), right: advice(0, 8) },
+        ];
+        let text = printed(&model, GateMode::Quantified);
+
+        let expected = r#"def copy_class_instance0_2 (c : ValidCircuit P P_Prime) : Prop :=
+  c.get_advice 0 1 = c.get_instance 0 2 ∧
+  c.get_advice 1 0 = c.get_instance 0 2 ∧
+  c.get_advice 2 5 = c.get_instance 0 2
+def copy_advice0_1_advice1_0 (c : ValidCircuit P P_Prime) : Prop := c.get_advice 0 1 = c.get_advice 1 0
+theorem copy_advice0_1_advice1_0_of_class (c : ValidCircuit P P_Prime) (h : copy_class_instance0_2 c) : copy_advice0_1_advice1_0 c := by unfold copy_advice0_1_advice1_0; unfold copy_class_instance0_2 at h; exact h.1.trans h.2.1.symm
+def copy_advice1_0_instance0_2 (c : ValidCircuit P P_Prime) : Prop := c.get_advice 1 0 = c.get_instance 0 2
+theorem copy_advice1_0_instance0_2_of_class (c : ValidCircuit P P_Prime) (h : copy_class_instance0_2 c) : copy_advice1_0_instance0_2 c := by unfold copy_advice1_0_instance0_2; unfold copy_class_instance0_2 at h; exact h.2.1
+def copy_advice2_5_advice0_1 (c : ValidCircuit P P_Prime) : Prop := c.get_advice 2 5 = c.get_advice 0 1
+theorem copy_advice2_5_advice0_1_of_class (c : ValidCircuit P P_Prime) (h : copy_class_instance0_2 c) : copy_advice2_5_advice0_1 c := by unfold copy_advice2_5_advice0_1; unfold copy_class_instance0_2 at h; exact h.2.2.trans h.1.symm
+def copy_class_advice0_8 (c : ValidCircuit P P_Prime) : Prop := c.get_advice 2 7 = c.get_advice 0 8
+def copy_advice2_7_advice0_8 (c : ValidCircuit P P_Prime) : Prop := c.get_advice 2 7 = c.get_advice 0 8
+theorem copy_advice2_7_advice0_8_of_class (c : ValidCircuit P P_Prime) (h : copy_class_advice0_8 c) : copy_advice2_7_advice0_8 c := by unfold copy_advice2_7_advice0_8; unfold copy_class_advice0_8 at h; exact h
+"#;
+        assert_eq!(between(&text, "def copy_class", "def all_copy_constraints"), expected);
+    }
+
+    #[test]
+    fn test_copy_loops() {
+        let mut model = sample_model();
+        let advice = |column, row| cell(ColumnKind::Advice, column, row);
+        let instance = |row| cell(ColumnKind::Instance, 0, row);
+        // A value passed diagonally through three columns, which repeats from the second row to the fifth
+        let mut copies = vec![CopyConstraint { left: advice(0, 0), right: instance(0) }];
+        for i in 1..6 {
+            copies.push(CopyConstraint { left: advice(0, i), right: advice(1, i - 1) });
+            copies.push(CopyConstraint { left: advice(1, i), right: advice(2, i - 1) });
+        }
+        copies.push(CopyConstraint { left: advice(2, 5), right: instance(1) });
+        model.copies = copies;
+        let text = printed(&model, GateMode::Quantified);
+
+        let expected = r#"def copy_loop_advice0_2 (c : ValidCircuit P P_Prime) : Prop :=
+  ∀ i : ℕ, i < 4 → c.get_advice 1 (i + 1) = c.get_advice 0 (i + 2) ∧ c.get_advice 2 i = c.get_advice 0 (i + 2)
+def copy_advice1_1_advice2_0 (c : ValidCircuit P P_Prime) : Prop :=
+  ∀ i : ℕ, i < 4 → c.get_advice 1 (i + 1) = c.get_advice 2 i
+theorem copy_advice1_1_advice2_0_of_class (c : ValidCircuit P P_Prime) (h : copy_loop_advice0_2 c) : copy_advice1_1_advice2_0 c := by unfold copy_advice1_1_advice2_0; unfold copy_loop_advice0_2 at h; intro i hi; exact (h i hi).1.trans (h i hi).2.symm
+def copy_advice0_2_advice1_1 (c : ValidCircuit P P_Prime) : Prop :=
+  ∀ i : ℕ, i < 4 → c.get_advice 0 (i + 2) = c.get_advice 1 (i + 1)
+theorem copy_advice0_2_advice1_1_of_class (c : ValidCircuit P P_Prime) (h : copy_loop_advice0_2 c) : copy_advice0_2_advice1_1 c := by unfold copy_advice0_2_advice1_1; unfold copy_loop_advice0_2 at h; intro i hi; exact (h i hi).1.symm
+"#;
+        assert_eq!(between(&text, "def copy_loop_advice0_2", "def copy_class_advice1_5"), expected);
+    }
+
+    #[test]
+    fn test_region_loops() {
+        let mut model = sample_model();
+        let mut regions = vec![RegionSelectors { name: "init".into(), enabled: vec![(1, 0), (1, 2)] }];
+        for k in 0..6 {
+            regions.push(RegionSelectors { name: "step".into(), enabled: vec![(1, 5 + 3 * k), (1, 6 + 3 * k), (0, 5 + 3 * k)] });
+        }
+        for region in &regions {
+            for &(selector, row) in &region.enabled {
+                model.selectors.entry(selector).or_default().insert(row, String::new());
+            }
+        }
+        model.region_selectors = regions;
+        let text = printed(&model, GateMode::Quantified);
+
+        let expected = r#"def selector_func_col_0_loop_5 (c : ValidCircuit P P_Prime) : ℕ → ZMod P :=
+  λ row =>
+  if row < 1 then 1
+  else 0
+def selector_func_col_0 (c : ValidCircuit P P_Prime) : ℕ → ZMod P :=
+  λ row =>
+  if row < 5 then 0
+  else if row < 21 then selector_func_col_0_loop_5 c ((row - 5) % 3)
+  else 0
+@[simp]
+theorem selector_func_col_0_loop_5_row_0 (c : ValidCircuit P P_Prime) : selector_func_col_0_loop_5 c 0 = 1 := by simp only [selector_func_col_0_loop_5] <;> split_ifs <;> first | rfl | omega
+@[simp]
+theorem selector_func_col_0_loop_5_rows_1_to_2 (c : ValidCircuit P P_Prime) (row : ℕ) (h_start : 1 ≤ row) (h_end : row ≤ 2) : selector_func_col_0_loop_5 c row = 0 := by simp only [selector_func_col_0_loop_5] <;> split_ifs <;> first | rfl | omega
+@[simp]
+theorem selector_func_col_0_rows_0_to_4 (c : ValidCircuit P P_Prime) (row : ℕ) (h_end : row ≤ 4) : selector_func_col_0 c row = 0 := by simp only [selector_func_col_0] <;> split_ifs <;> first | rfl | omega
+@[simp]
+theorem selector_func_col_0_rows_5_to_20 (c : ValidCircuit P P_Prime) (row : ℕ) (h_start : 5 ≤ row) (h_end : row ≤ 20) : selector_func_col_0 c row = selector_func_col_0_loop_5 c ((row - 5) % 3) := by simp only [selector_func_col_0] <;> split_ifs <;> first | rfl | omega
+@[simp]
+theorem selector_func_col_0_rows_from_21 (c : ValidCircuit P P_Prime) (row : ℕ) (h_start : 21 ≤ row) : selector_func_col_0 c row = 0 := by simp only [selector_func_col_0] <;> split_ifs <;> first | rfl | omega
+-- Region step repeated 6 times, every 3 rows
+@[simp]
+theorem selector_func_col_0_loop_5_at_0 (c : ValidCircuit P P_Prime) : ∀ i : ℕ, i < 6 → selector_func_col_0 c (3 * i + 5) = 1 := by intro i hi; rw [selector_func_col_0_rows_5_to_20 c (3 * i + 5) (by omega) (by omega), show (3 * i + 5 - 5) % 3 = 0 by omega]; simp
+"#;
+        assert_eq!(between(&text, "def selector_func_col_0_loop_5", "def selector_func_col_1_loop_5"), expected);
+        assert!(text.contains("theorem selector_func_col_1_loop_5_at_1 (c : ValidCircuit P P_Prime) : ∀ i : ℕ, i < 6 → selector_func_col_1 c (3 * i + 6) = 1"));
+    }
+
+    #[test]
+    fn test_known_tables() {
+        let mut model = sample_model();
+        model.fixed.insert(0, column(0..6, |row| (row % 4) as i64));
+        model.fixed.insert(1, column(0..6, |row| (row * row % 4) as i64));
+        let fill = || FixedFill { row: 6, value: constant(0) };
+        model.fixed_fill = [(0, fill()), (1, fill())].into_iter().collect();
+        let table = vec![
+            ModelExpr::Fixed { column: 0, rotation: 0 },
+            ModelExpr::Scaled(Box::new(ModelExpr::Fixed { column: 1, rotation: 0 }), constant(2)),
+        ];
+        model.lookups = vec![
+            Lookup { name: "known".into(), inputs: vec![advice(0, 0), advice(1, 0)], table },
+            Lookup { name: "dynamic".into(), inputs: vec![advice(0, 0)], table: vec![advice(1, 0)] },
+        ];
+        let text = printed(&model, GateMode::Quantified);
+
+        let expected = r#"def lookup_known_table (c : ValidCircuit P P_Prime) : List (ZMod P × ZMod P) := [(0, 0), (1, 2), (2, 0), (3, 2)]
+def lookup_known (c : ValidCircuit P P_Prime) : Prop :=
+  -- Lookup number 1 name: "known"
+  ∀ row : ℕ, row < c.usable_rows → (c.get_advice 0 row, c.get_advice 1 row) ∈ lookup_known_table c
+def lookup_dynamic (c : ValidCircuit P P_Prime) : Prop :=
+  -- Lookup number 2 name: "dynamic"
+  ∀ row : ℕ, row < c.usable_rows → (∃ lookup_row : ℕ, lookup_row < c.usable_rows ∧ (c.get_advice 0 row) = (c.get_advice 1 lookup_row))
+"#;
+        assert_eq!(between(&text, "def lookup_known_table", "def all_lookups"), expected);
+
+        // Without the fill the rows after the extracted ones are unknown
+        model.fixed_fill.clear();
+        assert!(!printed(&model, GateMode::Quantified).contains("lookup_known_table"));
+    }
+}
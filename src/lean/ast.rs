@@ -0,0 +1,770 @@
+use std::fmt::{self, Display};
+
+use itertools::Itertools;
+
+// Binding powers of the Lean notation we emit, following Lean's own notation declarations
+// Binders, `if` and `match` extend as far to the right as possible, so they bind loosest
+const LEAD: u32 = 0;
+const IFF: u32 = 20;
+const IMPLIES: u32 = 25;
+const OR: u32 = 30;
+const AND: u32 = 35;
+const CMP: u32 = 50;
+const ADD: u32 = 65;
+const MUL: u32 = 70;
+const POW: u32 = 75;
+const NEG: u32 = 75;
+const APP: u32 = 1024;
+// Atoms and anything parenthesised
+const ARG: u32 = 1025;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Mod,
+    Pow,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Ge,
+    Mem,
+    Or,
+    Implies,
+    Iff,
+}
+
+enum Assoc {
+    Left,
+    Right,
+    None,
+}
+
+impl BinOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Mod => "%",
+            BinOp::Pow => "^",
+            BinOp::Eq => "=",
+            BinOp::Ne => "≠",
+            BinOp::Lt => "<",
+            BinOp::Le => "≤",
+            BinOp::Ge => "≥",
+            BinOp::Mem => "∈",
+            BinOp::Or => "∨",
+            BinOp::Implies => "→",
+            BinOp::Iff => "↔",
+        }
+    }
+
+    // The precedence of the operator, and the precedence required of its left and right operands
+    fn binding(self) -> (u32, u32, u32) {
+        let (prec, assoc) = match self {
+            BinOp::Add | BinOp::Sub => (ADD, Assoc::Left),
+            BinOp::Mul | BinOp::Mod => (MUL, Assoc::Left),
+            BinOp::Pow => (POW, Assoc::Right),
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Ge | BinOp::Mem => (CMP, Assoc::None),
+            BinOp::Or => (OR, Assoc::Right),
+            BinOp::Implies => (IMPLIES, Assoc::Right),
+            BinOp::Iff => (IFF, Assoc::None),
+        };
+        match assoc {
+            Assoc::Left => (prec, prec, prec + 1),
+            Assoc::Right => (prec, prec + 1, prec),
+            Assoc::None => (prec, prec + 1, prec + 1),
+        }
+    }
+}
+
+/// A binder of a definition, quantifier or `variable` command
+#[derive(Clone, Debug)]
+pub struct Binder {
+    pub names: Vec<String>,
+    pub ty: Option<Expr>,
+    pub implicit: bool,
+}
+
+impl Binder {
+    pub fn new(names: &[&str], ty: Expr) -> Self {
+        Binder {
+            names: names.iter().map(|name| name.to_string()).collect(),
+            ty: Some(ty),
+            implicit: false,
+        }
+    }
+
+    pub fn untyped(names: &[&str]) -> Self {
+        Binder {
+            names: names.iter().map(|name| name.to_string()).collect(),
+            ty: None,
+            implicit: false,
+        }
+    }
+
+    pub fn implicit(names: &[&str], ty: Expr) -> Self {
+        Binder {
+            implicit: true,
+            ..Binder::new(names, ty)
+        }
+    }
+
+    // Written without brackets, as the only binder of a quantifier
+    fn write_bare(&self, out: &mut String) {
+        out.push_str(&self.names.join(" "));
+        if let Some(ty) = &self.ty {
+            out.push_str(" : ");
+            ty.write_inline_at(LEAD, out);
+        }
+    }
+
+    fn write_bracketed(&self, out: &mut String) {
+        if self.ty.is_none() && !self.implicit {
+            return self.write_bare(out);
+        }
+        let (open, close) = if self.implicit { ("{", "}") } else { ("(", ")") };
+        out.push_str(open);
+        self.write_bare(out);
+        out.push_str(close);
+    }
+}
+
+fn write_quantifier(symbol: &str, binders: &[Binder], out: &mut String) {
+    out.push_str(symbol);
+    out.push(' ');
+    match binders {
+        [binder] => binder.write_bare(out),
+        _ => {
+            for (idx, binder) in binders.iter().enumerate() {
+                if idx > 0 {
+                    out.push(' ');
+                }
+                binder.write_bracketed(out);
+            }
+        },
+    }
+    out.push_str(", ");
+}
+
+/// A Lean term
+#[derive(Clone, Debug)]
+pub enum Expr {
+    /// Lean text rendered elsewhere, such as a `Term`. It is parenthesised unless it is a single token or already
+    /// parenthesised
+    Raw(String),
+    Ident(String),
+    Nat(usize),
+    App(Box<Expr>, Vec<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+    /// A conjunction, `True` if empty
+    And(Vec<Expr>),
+    Tuple(Vec<Expr>),
+    /// `#[a, b, ...]`, wrapped over several lines if long
+    Array(Vec<Expr>),
+    /// `[a, b, ...]`, wrapped over several lines if long
+    List(Vec<Expr>),
+    Lambda(Vec<String>, Box<Expr>),
+    Forall(Vec<Binder>, Box<Expr>),
+    Exists(Vec<Binder>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    Match(Box<Expr>, Vec<(Expr, Expr)>),
+    /// An expression preceded by a comment
+    Commented(String, Box<Expr>),
+}
+
+impl Expr {
+    pub fn raw(text: impl Display) -> Self {
+        Expr::Raw(text.to_string())
+    }
+
+    pub fn ident(name: impl Into<String>) -> Self {
+        Expr::Ident(name.into())
+    }
+
+    pub fn nat(value: usize) -> Self {
+        Expr::Nat(value)
+    }
+
+    pub fn app(function: impl Into<String>, args: impl IntoIterator<Item = Expr>) -> Self {
+        Expr::App(Box::new(Expr::ident(function)), args.into_iter().collect())
+    }
+
+    pub fn binary(op: BinOp, lhs: Expr, rhs: Expr) -> Self {
+        Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn negate(value: Expr) -> Self {
+        Expr::Neg(Box::new(value))
+    }
+
+    pub fn and(conjuncts: impl IntoIterator<Item = Expr>) -> Self {
+        Expr::And(conjuncts.into_iter().collect())
+    }
+
+    pub fn tuple(items: impl IntoIterator<Item = Expr>) -> Self {
+        Expr::Tuple(items.into_iter().collect())
+    }
+
+    pub fn array(items: impl IntoIterator<Item = Expr>) -> Self {
+        Expr::Array(items.into_iter().collect())
+    }
+
+    pub fn list(items: impl IntoIterator<Item = Expr>) -> Self {
+        Expr::List(items.into_iter().collect())
+    }
+
+    pub fn lambda(params: &[&str], body: Expr) -> Self {
+        Expr::Lambda(params.iter().map(|param| param.to_string()).collect(), Box::new(body))
+    }
+
+    pub fn forall(binders: Vec<Binder>, body: Expr) -> Self {
+        Expr::Forall(binders, Box::new(body))
+    }
+
+    pub fn exists(binders: Vec<Binder>, body: Expr) -> Self {
+        Expr::Exists(binders, Box::new(body))
+    }
+
+    pub fn ite(condition: Expr, then: Expr, otherwise: Expr) -> Self {
+        Expr::If(Box::new(condition), Box::new(then), Box::new(otherwise))
+    }
+
+    pub fn match_on(scrutinee: Expr, arms: Vec<(Expr, Expr)>) -> Self {
+        Expr::Match(Box::new(scrutinee), arms)
+    }
+
+    pub fn commented(self, comment: impl Into<String>) -> Self {
+        Expr::Commented(comment.into(), Box::new(self))
+    }
+
+    fn prec(&self) -> u32 {
+        match self {
+            Expr::Raw(text) if is_token(text) || is_parenthesised(text) => ARG,
+            Expr::Raw(_) => LEAD,
+            Expr::Ident(_) | Expr::Nat(_) | Expr::Tuple(_) | Expr::Array(_) | Expr::List(_) => ARG,
+            Expr::App(function, args) if args.is_empty() => function.prec(),
+            Expr::App(..) => APP,
+            Expr::Binary(op, ..) => op.binding().0,
+            Expr::Neg(_) => NEG,
+            Expr::And(conjuncts) => match conjuncts.as_slice() {
+                [] => ARG,
+                [conjunct] => conjunct.prec(),
+                _ => AND,
+            },
+            Expr::Lambda(..) | Expr::Forall(..) | Expr::Exists(..) | Expr::If(..) | Expr::Match(..) => LEAD,
+            Expr::Commented(_, inner) => inner.prec(),
+        }
+    }
+
+    // Whether this reads better spread over several lines
+    fn is_block(&self) -> bool {
+        match self {
+            Expr::And(conjuncts) => match conjuncts.as_slice() {
+                [conjunct] => conjunct.is_block(),
+                conjuncts => conjuncts.len() > 1,
+            },
+            Expr::Lambda(..) | Expr::Forall(..) | Expr::Exists(..) | Expr::If(..) | Expr::Match(..) | Expr::Commented(..) => true,
+            Expr::Array(items) | Expr::List(items) => self.to_string().len() > LINE_WIDTH && items.len() > 1,
+            _ => false,
+        }
+    }
+
+    fn write_inline_at(&self, prec: u32, out: &mut String) {
+        if self.prec() < prec {
+            out.push('(');
+            self.write_inline(out);
+            out.push(')');
+        } else {
+            self.write_inline(out);
+        }
+    }
+
+    fn write_inline(&self, out: &mut String) {
+        match self {
+            Expr::Raw(text) | Expr::Ident(text) => out.push_str(text),
+            Expr::Nat(value) => out.push_str(&value.to_string()),
+            Expr::App(function, args) => {
+                function.write_inline_at(APP, out);
+                for arg in args {
+                    out.push(' ');
+                    arg.write_inline_at(ARG, out);
+                }
+            },
+            Expr::Binary(op, lhs, rhs) => {
+                let (_, lhs_prec, rhs_prec) = op.binding();
+                lhs.write_inline_at(lhs_prec, out);
+                out.push(' ');
+                out.push_str(op.symbol());
+                out.push(' ');
+                rhs.write_inline_at(rhs_prec, out);
+            },
+            Expr::Neg(value) => {
+                out.push('-');
+                value.write_inline_at(NEG + 1, out);
+            },
+            Expr::And(conjuncts) => match conjuncts.as_slice() {
+                [] => out.push_str("True"),
+                [conjunct] => conjunct.write_inline(out),
+                _ => {
+                    for (idx, conjunct) in conjuncts.iter().enumerate() {
+                        if idx > 0 {
+                            out.push_str(" ∧ ");
+                        }
+                        conjunct.write_inline_at(AND + 1, out);
+                    }
+                },
+            },
+            Expr::Tuple(items) => {
+                out.push('(');
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        out.push_str(", ");
+                    }
+                    item.write_inline_at(LEAD, out);
+                }
+                out.push(')');
+            },
+            Expr::Array(items) => write_elements("#[", items, None, out),
+            Expr::List(items) => write_elements("[", items, None, out),
+            Expr::Lambda(params, body) => {
+                out.push_str(&format!("λ {} => ", params.join(" ")));
+                body.write_inline_at(LEAD, out);
+            },
+            Expr::Forall(binders, body) => {
+                write_quantifier("∀", binders, out);
+                body.write_inline_at(LEAD, out);
+            },
+            Expr::Exists(binders, body) => {
+                write_quantifier("∃", binders, out);
+                body.write_inline_at(LEAD, out);
+            },
+            Expr::If(condition, then, otherwise) => {
+                out.push_str("if ");
+                condition.write_inline_at(LEAD, out);
+                out.push_str(" then ");
+                then.write_inline_at(LEAD + 1, out);
+                out.push_str(" else ");
+                otherwise.write_inline_at(LEAD, out);
+            },
+            Expr::Match(scrutinee, arms) => {
+                out.push_str("match ");
+                scrutinee.write_inline_at(LEAD, out);
+                out.push_str(" with");
+                for (pattern, body) in arms {
+                    out.push_str(" | ");
+                    pattern.write_inline_at(LEAD, out);
+                    out.push_str(" => ");
+                    // A nested match would swallow the remaining arms
+                    body.write_inline_at(LEAD + 1, out);
+                }
+            },
+            Expr::Commented(comment, inner) => {
+                out.push_str(&format!("/- {} -/ ", escape_block_comment(comment)));
+                inner.write_inline(out);
+            },
+        }
+    }
+
+    fn write_block_at(&self, prec: u32, indent: usize, out: &mut String) {
+        if self.prec() < prec {
+            self.write_inline_at(prec, out);
+        } else {
+            self.write_block(indent, out);
+        }
+    }
+
+    // Continuation lines are indented by `indent`. The caller has already indented the first line
+    fn write_block(&self, indent: usize, out: &mut String) {
+        let newline = |indent: usize, out: &mut String| {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+        };
+        match self {
+            Expr::And(conjuncts) if conjuncts.len() == 1 => conjuncts[0].write_block(indent, out),
+            Expr::And(conjuncts) if conjuncts.len() > 1 => {
+                for (idx, conjunct) in conjuncts.iter().enumerate() {
+                    if idx > 0 {
+                        out.push_str(" ∧");
+                        newline(indent, out);
+                    }
+                    conjunct.write_block_at(AND + 1, indent, out);
+                }
+            },
+            Expr::If(condition, then, otherwise) => {
+                out.push_str("if ");
+                condition.write_inline_at(LEAD, out);
+                out.push_str(" then ");
+                then.write_block_at(LEAD + 1, indent, out);
+                newline(indent, out);
+                out.push_str("else ");
+                otherwise.write_block_at(LEAD, indent, out);
+            },
+            Expr::Lambda(params, body) => {
+                out.push_str(&format!("λ {} =>", params.join(" ")));
+                if body.is_block() && !matches!(**body, Expr::Match(..)) {
+                    newline(indent, out);
+                } else {
+                    out.push(' ');
+                }
+                body.write_block_at(LEAD, indent, out);
+            },
+            Expr::Forall(binders, body) => {
+                write_quantifier("∀", binders, out);
+                body.write_block_at(LEAD, indent, out);
+            },
+            Expr::Exists(binders, body) => {
+                write_quantifier("∃", binders, out);
+                body.write_block_at(LEAD, indent, out);
+            },
+            Expr::Match(scrutinee, arms) => {
+                out.push_str("match ");
+                scrutinee.write_inline_at(LEAD, out);
+                out.push_str(" with");
+                for (pattern, body) in arms {
+                    newline(indent + 2, out);
+                    let body = match body {
+                        Expr::Commented(comment, inner) => {
+                            write_line_comment(comment, indent + 2, out);
+                            inner
+                        },
+                        _ => body,
+                    };
+                    out.push_str("| ");
+                    pattern.write_inline_at(LEAD, out);
+                    out.push_str(" => ");
+                    body.write_block_at(LEAD + 1, indent + 4, out);
+                }
+            },
+            Expr::Commented(comment, inner) => {
+                write_line_comment(comment, indent, out);
+                inner.write_block(indent, out);
+            },
+            Expr::Array(items) => write_elements("#[", items, Some(indent + 2), out),
+            Expr::List(items) => write_elements("[", items, Some(indent + 2), out),
+            _ => self.write_inline(out),
+        }
+    }
+}
+
+// Renders on a single line
+impl Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write_inline(&mut out);
+        f.write_str(&out)
+    }
+}
+
+// Literals longer than this are wrapped
+const LINE_WIDTH: usize = 100;
+
+// `{open}a, b, ...]`, starting a new line indented by `wrap` whenever the current one would pass LINE_WIDTH
+fn write_elements(open: &str, items: &[Expr], wrap: Option<usize>, out: &mut String) {
+    out.push_str(open);
+    for (idx, item) in items.iter().enumerate() {
+        let text = item.to_string();
+        if idx > 0 {
+            out.push(',');
+            let line = out.len() - out.rfind('\n').map_or(0, |newline| newline + 1);
+            match wrap {
+                Some(indent) if line + text.len() + 2 > LINE_WIDTH => {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                },
+                _ => out.push(' '),
+            }
+        }
+        out.push_str(&text);
+    }
+    out.push(']');
+}
+
+fn is_token(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.' || c == '\'')
+}
+
+// Whether the parenthesis opening `text` is closed by its last character
+fn is_parenthesised(text: &str) -> bool {
+    if !text.starts_with('(') {
+        return false;
+    }
+    let mut depth = 0;
+    for (idx, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {},
+        }
+        if depth == 0 {
+            return idx + c.len_utf8() == text.len();
+        }
+    }
+    false
+}
+
+// Writes one `--` line per line of `comment`, each followed by a newline indented by `indent`
+fn write_line_comment(comment: &str, indent: usize, out: &mut String) {
+    for line in comment_lines(comment) {
+        out.push_str(&format!("{line}\n{}", " ".repeat(indent)));
+    }
+}
+
+// A newline would end a `--` comment early, so each line gets its own
+fn comment_lines(comment: &str) -> impl Iterator<Item = String> + '_ {
+    comment.split(['\n', '\r']).map(|line| match line.trim_end() {
+        "" => "--".to_string(),
+        line => format!("-- {line}"),
+    })
+}
+
+// Block comments nest in Lean, so both delimiters have to be broken up
+fn escape_block_comment(comment: &str) -> String {
+    comment.replace("-/", "- /").replace("/-", "/ -")
+}
+
+/// Turn arbitrary text, such as a gate or shuffle name, into part of a Lean identifier
+///
+/// ASCII letters and digits are kept, underscores are doubled and any other character, whitespace included,
+/// becomes `_uXXXXXX_`, its code point as six hex digits, so distinct texts never mangle to the same name
+pub fn mangle(text: &str) -> String {
+    let mut mangled = String::new();
+    for c in text.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => mangled.push(c),
+            '_' => mangled.push_str("__"),
+            c => mangled.push_str(&format!("_u{:06x}_", c as u32)),
+        }
+    }
+    mangled
+}
+
+/// The command a `Def` is introduced with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefKind {
+    Def,
+    Abbrev,
+    Theorem,
+}
+
+/// A `def`, an `abbrev` or a `theorem`, whose type is then its statement and whose body its proof
+#[derive(Clone, Debug)]
+pub struct Def {
+    pub doc: Option<String>,
+    /// Attributes such as `simp`
+    pub attributes: Vec<String>,
+    pub kind: DefKind,
+    pub name: String,
+    pub params: Vec<Binder>,
+    pub ty: Option<Expr>,
+    pub body: Expr,
+}
+
+impl Def {
+    pub fn new(name: impl Into<String>, params: Vec<Binder>, ty: Option<Expr>, body: Expr) -> Self {
+        Def {
+            doc: None,
+            attributes: vec![],
+            kind: DefKind::Def,
+            name: name.into(),
+            params,
+            ty,
+            body,
+        }
+    }
+
+    pub fn abbrev(self) -> Self {
+        Def { kind: DefKind::Abbrev, ..self }
+    }
+
+    pub fn theorem(self) -> Self {
+        Def { kind: DefKind::Theorem, ..self }
+    }
+
+    pub fn with_attribute(mut self, attribute: &str) -> Self {
+        self.attributes.push(attribute.to_string());
+        self
+    }
+
+    pub fn with_doc(self, doc: impl Into<String>) -> Self {
+        Def {
+            doc: Some(doc.into()),
+            ..self
+        }
+    }
+}
+
+/// A top level Lean command
+#[derive(Clone, Debug)]
+pub enum Item {
+    Import(String),
+    SetOption(String, String),
+    Namespace(String),
+    End(String),
+    Variable(Vec<Binder>),
+    Structure {
+        name: String,
+        params: Vec<Binder>,
+        extends: Option<Expr>,
+        fields: Vec<(String, Expr)>,
+    },
+    Def(Def),
+    Comment(String),
+    Blank,
+}
+
+impl From<Def> for Item {
+    fn from(def: Def) -> Self {
+        Item::Def(def)
+    }
+}
+
+// Renders the command followed by a newline
+impl Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        match self {
+            Item::Import(module) => out.push_str(&format!("import {module}")),
+            Item::SetOption(option, value) => out.push_str(&format!("set_option {option} {value}")),
+            Item::Namespace(name) => out.push_str(&format!("namespace {name}")),
+            Item::End(name) => out.push_str(&format!("end {name}")),
+            Item::Variable(binders) => {
+                out.push_str("variable");
+                for binder in binders {
+                    out.push(' ');
+                    binder.write_bracketed(&mut out);
+                }
+            },
+            Item::Structure { name, params, extends, fields } => {
+                out.push_str(&format!("structure {name}"));
+                for param in params {
+                    out.push(' ');
+                    param.write_bracketed(&mut out);
+                }
+                if let Some(parent) = extends {
+                    out.push_str(" extends ");
+                    parent.write_inline_at(LEAD, &mut out);
+                }
+                out.push_str(" where");
+                for (field, ty) in fields {
+                    out.push_str(&format!("\n  {field} : "));
+                    ty.write_inline_at(LEAD, &mut out);
+                }
+            },
+            Item::Def(def) => {
+                if let Some(doc) = &def.doc {
+                    out.push_str(&format!("/-- {} -/\n", escape_block_comment(doc)));
+                }
+                if !def.attributes.is_empty() {
+                    out.push_str(&format!("@[{}]\n", def.attributes.join(", ")));
+                }
+                out.push_str(match def.kind {
+                    DefKind::Def => "def ",
+                    DefKind::Abbrev => "abbrev ",
+                    DefKind::Theorem => "theorem ",
+                });
+                out.push_str(&def.name);
+                for param in &def.params {
+                    out.push(' ');
+                    param.write_bracketed(&mut out);
+                }
+                if let Some(ty) = &def.ty {
+                    out.push_str(" : ");
+                    ty.write_inline_at(LEAD, &mut out);
+                }
+                out.push_str(" :=");
+                if def.body.is_block() {
+                    out.push_str("\n  ");
+                    def.body.write_block(2, &mut out);
+                } else {
+                    out.push(' ');
+                    def.body.write_inline(&mut out);
+                }
+            },
+            Item::Comment(comment) => {
+                out.push_str(&comment_lines(comment).join("\n"));
+            },
+            Item::Blank => {},
+        }
+        writeln!(f, "{out}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn x() -> Expr {
+        Expr::ident("x")
+    }
+
+    fn y() -> Expr {
+        Expr::ident("y")
+    }
+
+    #[test]
+    fn test_precedence() {
+        let bin = Expr::binary;
+        let sum = || bin(BinOp::Add, x(), y());
+        let difference = || bin(BinOp::Sub, x(), y());
+
+        // Left associative operators only need parentheses on the right
+        assert_eq!(bin(BinOp::Sub, difference(), Expr::nat(1)).to_string(), "x - y - 1");
+        assert_eq!(bin(BinOp::Sub, Expr::nat(1), difference()).to_string(), "1 - (x - y)");
+        assert_eq!(bin(BinOp::Mul, sum(), bin(BinOp::Mul, x(), y())).to_string(), "(x + y) * (x * y)");
+        assert_eq!(bin(BinOp::Add, bin(BinOp::Mul, x(), y()), Expr::nat(1)).to_string(), "x * y + 1");
+        assert_eq!(bin(BinOp::Mod, difference(), Expr::nat(3)).to_string(), "(x - y) % 3");
+
+        // Right associative ones only on the left
+        let power = || bin(BinOp::Pow, x(), y());
+        assert_eq!(bin(BinOp::Pow, Expr::nat(2), power()).to_string(), "2 ^ x ^ y");
+        assert_eq!(bin(BinOp::Pow, power(), Expr::nat(2)).to_string(), "(x ^ y) ^ 2");
+        let implies = bin(BinOp::Implies, bin(BinOp::Lt, x(), y()), bin(BinOp::Implies, x(), y()));
+        assert_eq!(implies.to_string(), "x < y → x → y");
+
+        // Comparisons don't associate at all
+        assert_eq!(bin(BinOp::Eq, bin(BinOp::Eq, x(), y()), x()).to_string(), "(x = y) = x");
+        assert_eq!(Expr::and([bin(BinOp::Le, x(), y()), bin(BinOp::Or, x(), y())]).to_string(), "x ≤ y ∧ (x ∨ y)");
+        assert_eq!(Expr::and([]).to_string(), "True");
+
+        assert_eq!(Expr::negate(sum()).to_string(), "-(x + y)");
+        assert_eq!(bin(BinOp::Add, x(), Expr::negate(y())).to_string(), "x + -y");
+    }
+
+    #[test]
+    fn test_parenthesised_arguments() {
+        let application = Expr::app("f", [Expr::app("g", [x()]), Expr::app("h", []), Expr::nat(2), Expr::tuple([x(), y()])]);
+        assert_eq!(application.to_string(), "f (g x) h 2 (x, y)");
+
+        // Raw text is parenthesised unless it is a single token or already parenthesised
+        let raw = Expr::app("f", [Expr::raw("x + 1"), Expr::raw("(y + 1)"), Expr::raw("c.usable_rows")]);
+        assert_eq!(raw.to_string(), "f (x + 1) (y + 1) c.usable_rows");
+
+        let lambda = Expr::app("f", [Expr::lambda(&["row"], Expr::ite(x(), y(), Expr::nat(0)))]);
+        assert_eq!(lambda.to_string(), "f (λ row => if x then y else 0)");
+        let nested = Expr::ite(x(), Expr::ite(y(), x(), y()), Expr::ite(y(), x(), y()));
+        assert_eq!(nested.to_string(), "if x then (if y then x else y) else if y then x else y");
+
+        let commented = Expr::app("f", [x()]).commented("a -/ b");
+        assert_eq!(commented.to_string(), "/- a - / b -/ f x");
+    }
+
+    #[test]
+    fn test_mangle() {
+        assert_eq!(mangle("Fib gate"), "Fib_u000020_gate");
+        assert_eq!(mangle("is_zero"), "is__zero");
+        assert_eq!(mangle("a-b"), "a_u00002d_b");
+        assert_eq!(mangle("λ"), "_u0003bb_");
+        assert_eq!(mangle("🦀"), "_u01f980_");
+        // Escapes have a fixed width and are delimited, so different names never collide
+        assert_ne!(mangle("\u{12}3"), mangle("\u{123}"));
+        assert_ne!(mangle("_u000041_"), mangle("A"));
+        assert_ne!(mangle("a b"), mangle("a_b"));
+        assert_ne!(mangle(" u00002d "), mangle("-"));
+        assert_eq!(mangle("a\tb\n"), "a_u000009_b_u00000a_");
+    }
+}
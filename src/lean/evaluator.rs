@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use num_traits::{Signed, Zero};
+
+use crate::field::{Term, TermField};
+use crate::model::{CircuitModel, ModelExpr};
+
+use super::{bin, expression_to_lean, row, term, BinOp, Expr};
+
+pub(super) fn is_zero(value: &TermField) -> bool {
+    value.constant().is_some_and(|value| value.is_zero())
+}
+
+// Folds a polynomial at a known row, substituting the selector and fixed values of the model. Anything unknown,
+// such as an advice cell, is folded as an opaque term standing for its Lean
+pub(super) struct Evaluator<'a> {
+    model: &'a CircuitModel,
+    // The Lean each opaque term stands for, keyed by its text
+    leaves: HashMap<String, Expr>,
+    // Whether the rows after the extracted ones are below `c.usable_rows`, where fixed columns hold their fill
+    usable: bool,
+}
+
+impl<'a> Evaluator<'a> {
+    pub(super) fn new(model: &'a CircuitModel) -> Self {
+        Evaluator {
+            model,
+            leaves: HashMap::new(),
+            usable: false,
+        }
+    }
+
+    // Evaluate the rows after the extracted ones as usable rows, so that a fixed column read there is its fill
+    pub(super) fn usable(self) -> Self {
+        Evaluator { usable: true, ..self }
+    }
+
+    // Whether everything evaluated so far was known, with no opaque terms
+    pub(super) fn is_known(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    fn leaf(&mut self, expr: Expr) -> TermField {
+        let text = expr.to_string();
+        let value = TermField::from(text.as_str());
+        self.leaves.insert(text, expr);
+        value
+    }
+
+    // The row a query at `rotation` reads, if it is one of the extracted rows. Those are below `c.n`, so it needs
+    // no reduction modulo `c.n`
+    fn query_row(&self, at: Option<usize>, rotation: i32) -> Option<usize> {
+        let row = at? as i64 + i64::from(rotation);
+        (0..self.model.usable_rows as i64).contains(&row).then_some(row as usize)
+    }
+
+    // The value of a fixed cell, as `fixed_func` gives it
+    fn fixed(&mut self, column: usize, row: usize) -> TermField {
+        let assigned = self.model.fixed.get(&column).and_then(|rows| rows.get(&row));
+        let fill = self.model.fixed_fill.get(&column).filter(|fill| fill.row <= row).map(|fill| &fill.value);
+        match assigned.or(fill) {
+            Some(value) => value.into(),
+            None => self.leaf(Expr::app("c.1.FixedUnassigned", [Expr::nat(column), Expr::nat(row)])),
+        }
+    }
+
+    // The value of a fixed column at every usable row after the extracted ones
+    fn fixed_later(&mut self, column: usize) -> TermField {
+        let fill = self.model.fixed_fill.get(&column).filter(|fill| fill.row <= self.model.usable_rows);
+        match fill {
+            Some(fill) => (&fill.value).into(),
+            None => self.leaf(expression_to_lean(&ModelExpr::Fixed { column, rotation: 0 }, &row())),
+        }
+    }
+
+    fn cell(&mut self, getter: &str, column: usize, row: usize) -> TermField {
+        self.leaf(Expr::app(getter, [Expr::nat(column), Expr::nat(row)]))
+    }
+
+    // `expr` at `at`, or at any row after the extracted ones if `at` is None
+    pub(super) fn evaluate(&mut self, expr: &ModelExpr, at: Option<usize>) -> TermField {
+        match (expr, self.query_row(at, expr_rotation(expr))) {
+            (ModelExpr::Constant(value), _) => value.into(),
+            (ModelExpr::Selector(selector), _) => {
+                let enabled = at.is_some_and(|at| self.model.selectors.get(selector).is_some_and(|rows| rows.contains_key(&at)));
+                TermField::from(u64::from(enabled))
+            },
+            (ModelExpr::Fixed { column, .. }, Some(row)) => self.fixed(*column, row),
+            (ModelExpr::Fixed { column, rotation: 0 }, None) if at.is_none() && self.usable => self.fixed_later(*column),
+            (ModelExpr::Advice { column, .. }, Some(row)) => self.cell("c.get_advice", *column, row),
+            (ModelExpr::Instance { column, .. }, Some(row)) => self.cell("c.get_instance", *column, row),
+            (ModelExpr::Negated(inner), _) => -self.evaluate(inner, at),
+            (ModelExpr::Sum(lhs, rhs), _) => self.evaluate(lhs, at) + self.evaluate(rhs, at),
+            (ModelExpr::Product(lhs, rhs), _) => {
+                // A disabled selector makes the whole product vanish, which folding alone doesn't see
+                let lhs = self.evaluate(lhs, at);
+                if is_zero(&lhs) {
+                    return lhs;
+                }
+                let rhs = self.evaluate(rhs, at);
+                if is_zero(&rhs) {
+                    return rhs;
+                }
+                lhs * rhs
+            },
+            (ModelExpr::Scaled(inner, factor), _) => {
+                let factor = TermField::from(factor);
+                if is_zero(&factor) {
+                    return factor;
+                }
+                let inner = self.evaluate(inner, at);
+                if is_zero(&inner) {
+                    return inner;
+                }
+                factor * inner
+            },
+            // Challenges, and queries that wrap around or read past the extracted rows
+            (query, _) => {
+                let row = at.map_or_else(row, Expr::nat);
+                self.leaf(expression_to_lean(query, &row))
+            },
+        }
+    }
+
+    // The Lean for a folded value
+    pub(super) fn to_lean(&self, value: &Term) -> Expr {
+        match value {
+            Term::Add(lhs, rhs) => match rhs.as_ref() {
+                Term::Neg(rhs) => bin(BinOp::Sub, self.to_lean(lhs), self.to_lean(rhs)),
+                Term::Const(rhs) if rhs.is_negative() => bin(BinOp::Sub, self.to_lean(lhs), term(&Term::Const(-rhs))),
+                rhs => bin(BinOp::Add, self.to_lean(lhs), self.to_lean(rhs)),
+            },
+            Term::Mul(lhs, rhs) => bin(BinOp::Mul, self.to_lean(lhs), self.to_lean(rhs)),
+            Term::Neg(inner) => Expr::negate(self.to_lean(inner)),
+            Term::Raw(text) => self.leaves.get(text).cloned().unwrap_or_else(|| term(value)),
+            value => term(value),
+        }
+    }
+}
+
+fn expr_rotation(expr: &ModelExpr) -> i32 {
+    match expr {
+        ModelExpr::Fixed { rotation, .. } | ModelExpr::Advice { rotation, .. } | ModelExpr::Instance { rotation, .. } => *rotation,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lean::test::{advice, column, constant, enabled, sample_model};
+    use crate::model::FixedFill;
+
+    fn fixed(column: usize) -> ModelExpr {
+        ModelExpr::Fixed { column, rotation: 0 }
+    }
+
+    #[test]
+    fn test_folding() {
+        let mut model = sample_model();
+        model.selectors.insert(0, enabled([1]));
+        model.fixed.insert(0, column([1, 2], |row| row as i64 + 2));
+        model.fixed_fill.insert(1, FixedFill { row: 2, value: constant(7) });
+        let b = Box::new;
+        let shown = |evaluator: &mut Evaluator, expr: &ModelExpr, at: Option<usize>| {
+            let value = evaluator.evaluate(expr, at);
+            evaluator.to_lean(&value.to_term()).to_string()
+        };
+
+        // q * (f * a + 2)
+        let gate = ModelExpr::Product(
+            b(ModelExpr::Selector(0)),
+            b(ModelExpr::Sum(b(ModelExpr::Product(b(fixed(0)), b(advice(0, 0)))), b(ModelExpr::Constant(constant(2))))),
+        );
+        let mut evaluator = Evaluator::new(&model);
+        assert_eq!(shown(&mut evaluator, &gate, Some(1)), "3 * c.get_advice 0 1 + 2");
+        assert!(!evaluator.is_known());
+
+        // A disabled selector makes the whole product vanish, without reading the advice cell
+        let mut evaluator = Evaluator::new(&model);
+        assert!(is_zero(&evaluator.evaluate(&gate, Some(0))));
+        assert!(evaluator.is_known());
+        assert!(is_zero(&evaluator.evaluate(&ModelExpr::Scaled(b(advice(0, 0)), constant(0)), Some(3))));
+
+        // Fixed cells are their assigned value or fill, if they have one
+        let mut evaluator = Evaluator::new(&model);
+        assert_eq!(shown(&mut evaluator, &fixed(0), Some(5)), "c.1.FixedUnassigned 0 5");
+        assert_eq!(shown(&mut evaluator, &fixed(1), Some(5)), "7");
+        // After the extracted rows the fill only holds below `c.usable_rows`
+        assert_eq!(shown(&mut evaluator, &fixed(1), None), "c.get_fixed 1 row");
+        assert_eq!(shown(&mut evaluator.usable(), &fixed(1), None), "7");
+
+        // Queries past the extracted rows keep their reduction modulo `c.n`
+        let mut evaluator = Evaluator::new(&model);
+        assert_eq!(shown(&mut evaluator, &ModelExpr::Negated(b(advice(1, 1))), Some(7)), "-c.get_advice 1 ((7 + 1) % c.n)");
+        let difference = ModelExpr::Sum(b(fixed(0)), b(ModelExpr::Negated(b(advice(1, -1)))));
+        assert_eq!(shown(&mut evaluator, &difference, Some(2)), "4 - c.get_advice 1 1");
+    }
+}
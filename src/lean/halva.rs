@@ -0,0 +1,303 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::{bin, nat_type, row, write_items, zmod, BinOp, Binder, Def, Expr, Item, MATHLIB_IMPORTS};
+
+/// The version of the `Halva` library the generated Lean is written against
+///
+/// Bump this whenever a definition the generated code relies on changes
+pub const HALVA_VERSION: u32 = 1;
+
+/// The module every extracted circuit imports
+pub const HALVA_MODULE: &str = "Halva";
+
+// The first line of the library, used to tell whether an existing copy is current
+fn header() -> String {
+    format!("Halva support library version {HALVA_VERSION}, generated by halo2_extr. Do not edit")
+}
+
+fn table() -> Expr {
+    Expr::raw("ℕ → ℕ → ZMod P")
+}
+
+fn halva_circuit() -> Vec<Binder> {
+    vec![Binder::new(&["c"], Expr::raw("Circuit P P_Prime"))]
+}
+
+// A definition in `Halva.Circuit`, taking the circuit as its first explicit argument
+fn circuit_def(name: &str, params: Vec<Binder>, ty: Option<Expr>, body: Expr) -> Item {
+    Def::new(name, [halva_circuit(), params].concat(), ty, body).into()
+}
+
+// `c.{name} col row = c.{field} col row`, so proofs can see through the getters
+fn getter_lemma(name: &str, field: &str) -> Item {
+    let args = || [Expr::ident("col"), row()];
+    Def::new(
+        format!("{name}_eq"),
+        [halva_circuit(), vec![Binder::new(&["col", "row"], nat_type())]].concat(),
+        Some(bin(BinOp::Eq, Expr::app(format!("c.{name}"), args()), Expr::app(format!("c.{field}"), args()))),
+        Expr::raw("rfl"),
+    )
+    .theorem()
+    .with_attribute("simp")
+    .into()
+}
+
+fn circuit_structure() -> Item {
+    let fields = [
+        ("Advice", table()),
+        ("AdviceUnassigned", table()),
+        ("AdvicePhase", Expr::raw("ℕ → ℕ")),
+        ("Fixed", table()),
+        ("FixedUnassigned", table()),
+        ("Instance", table()),
+        ("InstanceUnassigned", table()),
+        ("Selector", table()),
+        ("Challenges", Expr::raw("(ℕ → ℕ → ZMod P) → ℕ → ℕ → ZMod P")),
+        ("num_blinding_factors", nat_type()),
+        ("S", nat_type()),
+        ("T", nat_type()),
+        ("k", nat_type()),
+        ("mult_gen", zmod()),
+    ];
+    Item::Structure {
+        name: "Circuit".to_string(),
+        params: vec![
+            Binder::new(&["P"], nat_type()),
+            Binder::new(&["P_Prime"], Expr::raw("Nat.Prime P")),
+        ],
+        extends: None,
+        fields: fields.into_iter().map(|(name, ty)| (name.to_string(), ty)).collect(),
+    }
+}
+
+// Definitions about a circuit that don't depend on which circuit it is
+fn circuit_namespace() -> Vec<Item> {
+    let getter = |name: &str, field: &str| {
+        circuit_def(
+            name,
+            vec![],
+            Some(table()),
+            Expr::lambda(&["col", "row"], Expr::app(field, [Expr::ident("col"), row()])),
+        )
+    };
+    let pow = |base: &str, exponent: Expr| bin(BinOp::Pow, Expr::ident(base), exponent);
+
+    let advice = |name: &str| Expr::app(name, [Expr::ident("col"), row()]);
+    let challenges = |advice: &str| {
+        Expr::app("c.Challenges", [Expr::ident(advice), Expr::ident("i"), Expr::ident("phase")])
+    };
+    let same_advice = Expr::forall(
+        vec![Binder::untyped(&["row", "col"])],
+        bin(
+            BinOp::Implies,
+            Expr::and([
+                bin(BinOp::Lt, Expr::ident("col"), Expr::ident("num_advice_columns")),
+                bin(BinOp::Le, Expr::app("c.AdvicePhase", [Expr::ident("col")]), Expr::ident("phase")),
+            ]),
+            bin(BinOp::Eq, advice("advice1"), advice("advice2")),
+        ),
+    );
+    let same_challenges = Expr::forall(
+        vec![Binder::untyped(&["i"])],
+        bin(BinOp::Eq, challenges("advice1"), challenges("advice2")),
+    );
+    let challenges_depend_on_earlier_phases = Expr::forall(
+        vec![Binder::new(&["advice1", "advice2"], table()), Binder::new(&["phase"], nat_type())],
+        bin(BinOp::Implies, same_advice, same_challenges),
+    );
+
+    let shuffle = |row: Expr| Expr::app("shuffle", [row]);
+    let is_shuffle = Expr::exists(
+        vec![Binder::new(&["inv"], Expr::raw("ℕ → ℕ"))],
+        Expr::forall(
+            vec![Binder::new(&["row"], nat_type())],
+            Expr::and([
+                bin(BinOp::Eq, Expr::app("inv", [shuffle(row())]), row()),
+                bin(
+                    BinOp::Implies,
+                    bin(BinOp::Ge, row(), Expr::ident("c.usable_rows")),
+                    bin(BinOp::Eq, shuffle(row()), row()),
+                ),
+            ]),
+        ),
+    );
+
+    let num_advice_columns = || vec![Binder::new(&["num_advice_columns"], nat_type())];
+    vec![
+        getter("get_advice", "c.Advice"),
+        getter("get_fixed", "c.Fixed"),
+        getter("get_instance", "c.Instance"),
+        getter("get_selector", "c.Selector"),
+        circuit_def(
+            "get_challenge",
+            vec![],
+            Some(table()),
+            Expr::lambda(
+                &["idx", "phase"],
+                Expr::app("c.Challenges", [Expr::ident("c.Advice"), Expr::ident("idx"), Expr::ident("phase")]),
+            ),
+        ),
+        circuit_def("n", vec![], Some(nat_type()), pow("2", Expr::ident("c.k"))),
+        circuit_def(
+            "usable_rows",
+            vec![],
+            Some(nat_type()),
+            bin(
+                BinOp::Sub,
+                Expr::ident("c.n"),
+                bin(BinOp::Add, Expr::ident("c.num_blinding_factors"), Expr::nat(1)),
+            ),
+        ),
+        circuit_def("root_of_unity", vec![], Some(zmod()), pow("c.mult_gen", Expr::ident("c.T"))),
+        circuit_def("delta", vec![], Some(zmod()), pow("c.mult_gen", pow("2", Expr::ident("c.S")))),
+        Item::Blank,
+        circuit_def(
+            "challenges_valid",
+            num_advice_columns(),
+            Some(Expr::raw("Prop")),
+            challenges_depend_on_earlier_phases,
+        ),
+        circuit_def(
+            "isValid",
+            num_advice_columns(),
+            Some(Expr::raw("Prop")),
+            Expr::and([
+                Expr::app("S_T_from_P", [Expr::ident("c.S"), Expr::ident("c.T"), Expr::ident("P")]),
+                Expr::app("multiplicative_generator", [Expr::ident("P"), Expr::ident("c.mult_gen")]),
+                Expr::app("c.challenges_valid", [Expr::ident("num_advice_columns")]),
+            ]),
+        ),
+        circuit_def(
+            "sufficient_rows",
+            vec![Binder::new(&["minimum_rows"], nat_type())],
+            Some(Expr::raw("Prop")),
+            bin(BinOp::Ge, Expr::ident("c.n"), Expr::ident("minimum_rows")),
+        ),
+        circuit_def(
+            "is_shuffle",
+            vec![Binder::new(&["shuffle"], Expr::raw("ℕ → ℕ"))],
+            Some(Expr::raw("Prop")),
+            is_shuffle,
+        ),
+        Item::Blank,
+        getter_lemma("get_advice", "Advice"),
+        getter_lemma("get_fixed", "Fixed"),
+        getter_lemma("get_instance", "Instance"),
+        getter_lemma("get_selector", "Selector"),
+    ]
+}
+
+// The library, ending with its namespace so that more can follow it in the same file
+pub(super) fn halva() -> Vec<Item> {
+    let mut items = vec![Item::Comment(header())];
+    items.extend(MATHLIB_IMPORTS.map(|module| Item::Import(module.to_string())));
+    items.extend([
+        Item::Blank,
+        Item::SetOption("linter.unusedVariables".to_string(), "false".to_string()),
+        Item::Blank,
+        Item::Namespace(HALVA_MODULE.to_string()),
+        Item::Blank,
+        Def::new("version", vec![], Some(nat_type()), Expr::nat(HALVA_VERSION as usize)).into(),
+        Item::Blank,
+    ]);
+
+    let [s, t, p] = ["S", "T", "P"].map(Expr::ident);
+    let p_minus_one = || bin(BinOp::Sub, Expr::ident("P"), Expr::nat(1));
+    let two_pow = |exponent: Expr| bin(BinOp::Pow, Expr::nat(2), exponent);
+    items.push(
+        Def::new(
+            "S_T_from_P",
+            vec![Binder::new(&["S", "T", "P"], nat_type())],
+            Some(Expr::raw("Prop")),
+            Expr::and([
+                bin(BinOp::Eq, bin(BinOp::Mul, two_pow(s.clone()), t), p_minus_one()),
+                Expr::forall(
+                    vec![Binder::new(&["s'", "t'"], nat_type())],
+                    bin(
+                        BinOp::Implies,
+                        bin(BinOp::Eq, bin(BinOp::Mul, two_pow(Expr::ident("s'")), Expr::ident("t'")), p_minus_one()),
+                        bin(BinOp::Le, Expr::ident("s'"), s),
+                    ),
+                ),
+            ]),
+        )
+        .into(),
+    );
+    items.push(
+        Def::new(
+            "multiplicative_generator",
+            vec![Binder::new(&["P"], nat_type()), Binder::new(&["mult_gen"], zmod())],
+            Some(Expr::raw("Prop")),
+            bin(BinOp::Eq, bin(BinOp::Pow, Expr::ident("mult_gen"), p), Expr::nat(1)),
+        )
+        .into(),
+    );
+
+    items.push(circuit_structure());
+    items.push(Item::Variable(vec![
+        Binder::implicit(&["P"], nat_type()),
+        Binder::implicit(&["P_Prime"], Expr::raw("Nat.Prime P")),
+    ]));
+    items.push(Item::Namespace("Circuit".to_string()));
+    items.extend(circuit_namespace());
+    items.push(Item::End("Circuit".to_string()));
+    items.push(Item::End(HALVA_MODULE.to_string()));
+    items
+}
+
+/// Write the `Halva` library that every extracted circuit imports to `out`
+pub fn print_halva(out: &mut impl Write) -> io::Result<()> {
+    write_items(out, halva())
+}
+
+/// Write `Halva.lean` to `dir`, unless the same version is already there
+///
+/// Returns the path if it was written
+pub fn write_halva(dir: impl AsRef<Path>) -> io::Result<Option<PathBuf>> {
+    let path = dir.as_ref().join(format!("{HALVA_MODULE}.lean"));
+    let current = match File::open(&path) {
+        Ok(file) => BufReader::new(file).lines().next().transpose()?.is_some_and(|line| line == format!("-- {}", header())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => false,
+        Err(err) => return Err(err),
+    };
+    if current {
+        return Ok(None);
+    }
+    fs::create_dir_all(dir.as_ref())?;
+    let mut out = BufWriter::new(File::create(&path)?);
+    print_halva(&mut out)?;
+    out.flush()?;
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_halva() {
+        let dir = std::env::temp_dir().join("halo2_extr_write_halva_test");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("Halva.lean");
+        assert_eq!(write_halva(&dir).unwrap(), Some(path.clone()));
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(written.starts_with(&format!("-- {}\n", header())));
+        assert!(written.contains(&format!("def version : ℕ := {HALVA_VERSION}\n")));
+
+        // The same version isn't written again, even if it was changed
+        let edited = written.replace("namespace Halva", "-- edited\nnamespace Halva");
+        fs::write(&path, &edited).unwrap();
+        assert_eq!(write_halva(&dir).unwrap(), None);
+        assert_eq!(fs::read_to_string(&path).unwrap(), edited);
+
+        // An older version is replaced
+        let older = written.replacen(&format!("version {HALVA_VERSION},"), &format!("version {},", HALVA_VERSION - 1), 1);
+        assert_ne!(older, written);
+        fs::write(&path, older).unwrap();
+        assert_eq!(write_halva(&dir).unwrap(), Some(path.clone()));
+        assert_eq!(fs::read_to_string(&path).unwrap(), written);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::model::CircuitModel;
+
+use super::{
+    advice_phase, column_annotations, copy_constraints, extraction_assumptions, fixed, gates, lookups, meets_constraints,
+    preamble, regions, selectors, shuffles, variables, write_halva, write_items, GateMode, Item, Section, HALVA_MODULE,
+};
+
+// Units are packed into parts of roughly this many bytes of Lean. Parts only import the preamble,
+// so Lake can elaborate all of them in parallel
+const PART_SIZE: usize = 128 * 1024;
+
+// A section is written as `{Root}/{name}.lean`, importing `{Root}/{name}/Part{i}.lean` if it had to be split
+pub(crate) struct Module {
+    pub(crate) name: String,
+    pub(crate) imports: Vec<String>,
+    pub(crate) variables: bool,
+    pub(crate) items: Vec<Item>,
+}
+
+/// The path of the Lean module `name` in a source tree rooted at `dir`, e.g. `dir/A/B.lean` for `A.B`
+pub fn module_path(dir: &Path, name: &str) -> PathBuf {
+    let mut path = dir.to_path_buf();
+    path.extend(name.split('.'));
+    path.set_extension("lean");
+    path
+}
+
+impl Module {
+    pub(crate) fn path(&self, dir: &Path) -> PathBuf {
+        module_path(dir, &self.name)
+    }
+
+    pub(crate) fn write(self, dir: &Path, model: &CircuitModel) -> io::Result<PathBuf> {
+        let path = self.path(dir);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = BufWriter::new(File::create(&path)?);
+        let mut items = self.imports.into_iter().map(Item::Import).collect::<Vec<_>>();
+        items.extend([
+            Item::Blank,
+            Item::SetOption("linter.unusedVariables".to_string(), "false".to_string()),
+            Item::Blank,
+            Item::Namespace(model.namespace.clone()),
+        ]);
+        if self.variables {
+            items.push(variables(model));
+        }
+        items.push(Item::Blank);
+        items.extend(self.items);
+        items.push(Item::End(model.namespace.clone()));
+        write_items(&mut out, items)?;
+        out.flush()?;
+        Ok(path)
+    }
+}
+
+// Pack whole units into parts of about PART_SIZE bytes
+fn pack(units: Vec<Vec<Item>>) -> Vec<Vec<Item>> {
+    let mut parts: Vec<Vec<Item>> = vec![];
+    let mut size = 0;
+    for unit in units {
+        let unit_size: usize = unit.iter().map(|item| item.to_string().len()).sum();
+        match parts.last_mut() {
+            Some(part) if size + unit_size <= PART_SIZE => {
+                part.extend(unit);
+                size += unit_size;
+            },
+            _ => {
+                parts.push(unit);
+                size = unit_size;
+            },
+        }
+    }
+    parts
+}
+
+// The module for `section`, preceded by its parts if it is too big for one module
+fn section_modules(root: &str, name: &str, section: Section, extra: Vec<Item>) -> Vec<Module> {
+    let preamble = format!("{root}.Preamble");
+    let mut parts = pack(section.units);
+    let mut summary = extra;
+    summary.extend(section.summary);
+
+    if parts.len() <= 1 {
+        let mut items = parts.pop().unwrap_or_default();
+        items.extend(summary);
+        return vec![Module { name: format!("{root}.{name}"), imports: vec![preamble], variables: true, items }];
+    }
+
+    let mut modules = parts
+        .into_iter()
+        .enumerate()
+        .map(|(idx, items)| Module {
+            name: format!("{root}.{name}.Part{idx}"),
+            imports: vec![preamble.clone()],
+            variables: true,
+            items,
+        })
+        .collect::<Vec<_>>();
+    let imports = modules.iter().map(|module| module.name.clone()).collect();
+    modules.push(Module { name: format!("{root}.{name}"), imports, variables: true, items: summary });
+    modules
+}
+
+// Part files left over from an earlier extraction that needed more of them
+fn remove_stale_parts(dir: &Path) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let is_part = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("Part"))
+            .and_then(|name| name.strip_suffix(".lean"))
+            .is_some_and(|idx| !idx.is_empty() && idx.chars().all(|c| c.is_ascii_digit()));
+        if is_part {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write `model` as a tree of Lean modules rooted at `dir`, returning the paths written
+///
+/// The modules are `{namespace}.Preamble`, then `Fixed`, `Selectors`, `Gates`, `Lookups`, `Copies` and `Shuffles`,
+/// each of which only imports the preamble, and finally `MeetsConstraints`, which imports all of them.
+/// `{namespace}.lean` imports `MeetsConstraints`. A namespace such as `Tutorial.MyCircuit` is written to `Tutorial/MyCircuit/`.
+/// Sections too large for one module are split into `{Section}/Part{i}.lean`, so that a large circuit can be
+/// elaborated in parallel. `Halva.lean` is written to `dir` as well, if it is missing or out of date.
+pub fn write_modules(dir: impl AsRef<Path>, model: &CircuitModel) -> io::Result<Vec<PathBuf>> {
+    write_modules_with(dir, model, GateMode::default())
+}
+
+/// `write_modules`, stating gates as `mode` says
+pub fn write_modules_with(dir: impl AsRef<Path>, model: &CircuitModel, mode: GateMode) -> io::Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    let root = &model.namespace;
+
+    let mut modules = vec![Module {
+        name: format!("{root}.Preamble"),
+        imports: vec![HALVA_MODULE.to_string()],
+        // The preamble declares its own variables, after defining P if the modulus is known
+        variables: false,
+        items: preamble(model),
+    }];
+
+    let sections = [
+        ("Fixed", fixed(model), vec![]),
+        ("Selectors", selectors(model), vec![advice_phase(model)]),
+        ("Gates", gates(model, mode), vec![]),
+        ("Lookups", lookups(model), vec![]),
+        ("Copies", copy_constraints(model), vec![]),
+        ("Shuffles", shuffles(model), vec![]),
+    ];
+    let mut section_names = vec![];
+    for (name, section, extra) in sections {
+        remove_stale_parts(&module_path(dir, &format!("{root}.{name}")).with_extension(""))?;
+        section_names.push(format!("{root}.{name}"));
+        modules.extend(section_modules(root, name, section, extra));
+    }
+
+    let mut items = regions(model);
+    items.extend(column_annotations("Advice", &model.advice_annotations));
+    items.extend(column_annotations("Instance", &model.instance_annotations));
+    items.push(extraction_assumptions(model));
+    items.push(meets_constraints(model));
+    modules.push(Module {
+        name: format!("{root}.MeetsConstraints"),
+        imports: section_names,
+        variables: true,
+        items,
+    });
+
+    let mut paths = write_halva(dir)?.into_iter().collect::<Vec<_>>();
+    for module in modules {
+        paths.push(module.write(dir, model)?);
+    }
+
+    let root_path = module_path(dir, root);
+    let mut out = BufWriter::new(File::create(&root_path)?);
+    write_items(&mut out, [Item::Import(format!("{root}.MeetsConstraints"))])?;
+    out.flush()?;
+    paths.push(root_path);
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::lean::test::{advice, sample_model};
+    use crate::model::{Constraint, Gate};
+
+    #[test]
+    fn test_write_modules() {
+        let mut model = sample_model();
+        // Enough gates that they are split into parts
+        model.gates = (0..2000)
+            .map(|idx| Gate {
+                name: format!("g{idx}"),
+                constraints: vec![Constraint { name: "c".into(), polynomial: advice(0, 0) }],
+            })
+            .collect();
+        let dir = std::env::temp_dir().join("halo2_extr_write_modules_test");
+        let _ = fs::remove_dir_all(&dir);
+        let paths = write_modules(&dir, &model).unwrap();
+        let paths = paths.iter().map(|path| path.strip_prefix(&dir).unwrap().display().to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "Halva.lean",
+                "Test/Preamble.lean",
+                "Test/Fixed.lean",
+                "Test/Selectors.lean",
+                "Test/Gates/Part0.lean",
+                "Test/Gates/Part1.lean",
+                "Test/Gates/Part2.lean",
+                "Test/Gates/Part3.lean",
+                "Test/Gates.lean",
+                "Test/Lookups.lean",
+                "Test/Copies.lean",
+                "Test/Shuffles.lean",
+                "Test/MeetsConstraints.lean",
+                "Test.lean",
+            ]
+        );
+
+        let read = |path: &str| fs::read_to_string(dir.join(path)).unwrap();
+        assert_eq!(read("Test.lean"), "import Test.MeetsConstraints\n");
+        let header = "\n\nset_option linter.unusedVariables false\n\nnamespace Test\nvariable {P : ℕ} {P_Prime : Nat.Prime P}\n\n";
+        // The parts only import the preamble, and the section imports the parts
+        assert!(read("Test/Gates/Part1.lean").starts_with(&format!("import Test.Preamble{header}def gate_g")));
+        let parts = (0..4).map(|idx| format!("import Test.Gates.Part{idx}")).join("\n");
+        assert!(read("Test/Gates.lean").starts_with(&format!("{parts}{header}def all_gates_")));
+        let expected = r#"import Test.Fixed
+import Test.Selectors
+import Test.Gates
+import Test.Lookups
+import Test.Copies
+import Test.Shuffles
+
+set_option linter.unusedVariables false
+
+namespace Test
+variable {P : ℕ} {P_Prime : Nat.Prime P}
+
+-- Advice column annotations:
+-- None
+-- Instance column annotations:
+-- None
+def extraction_assumptions (c : ValidCircuit P P_Prime) : Prop := True
+def meets_constraints (c : ValidCircuit P P_Prime) : Prop :=
+  sufficient_rows c ∧
+  c.1.num_blinding_factors = 5 ∧
+  c.1.Selector = selector_func c ∧
+  c.1.Fixed = fixed_func c ∧
+  c.1.AdvicePhase = advice_phase c ∧
+  c.usable_rows ≥ 8 ∧
+  all_gates c ∧
+  all_copy_constraints c ∧
+  all_lookups c ∧
+  all_shuffles c ∧
+  (∀ col row : ℕ, row < c.n ∧ row ≥ c.usable_rows → c.1.Instance col row = c.1.InstanceUnassigned col row)
+end Test
+"#;
+        assert_eq!(read("Test/MeetsConstraints.lean"), expected);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
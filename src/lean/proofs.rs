@@ -0,0 +1,150 @@
+use std::io::{self, Write};
+
+use crate::model::CircuitModel;
+
+use super::{
+    bin, c, circuit_param, conjunct_projection, gate_description, gate_names, meets_constraints_conjuncts, write_items,
+    BinOp, Binder, Def, Expr, Item, Namer,
+};
+
+// `(h : {prop} c)`
+fn hypothesis(name: &str, prop: &str) -> Binder {
+    Binder::new(&[name], Expr::app(prop, [c()]))
+}
+
+fn theorem(name: impl Into<String>, params: Vec<Binder>, statement: Expr, proof: &str) -> Def {
+    Def::new(name, [circuit_param(), params].concat(), Some(statement), Expr::raw(proof)).theorem()
+}
+
+// `meets_constraints_{name} : meets_constraints c → {conjunct}` for each conjunct of `meets_constraints`
+fn unfolding_lemmas(model: &CircuitModel) -> Vec<Item> {
+    let conjuncts = meets_constraints_conjuncts(model);
+    let len = conjuncts.len();
+    conjuncts
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (name, conjunct))| {
+            let projection = conjunct_projection(idx, len);
+            theorem(
+                format!("meets_constraints_{name}"),
+                vec![hypothesis("h", "meets_constraints")],
+                conjunct,
+                &format!("by unfold meets_constraints at h; exact h{projection}"),
+            )
+            .into()
+        })
+        .collect()
+}
+
+// `{gate}_spec` for each gate, to be stated as what the gate's constraints mean
+fn gate_specs(model: &CircuitModel) -> Vec<Item> {
+    let mut namer = Namer::default();
+    let names = gate_names(model, &mut namer);
+    let mut items = vec![];
+    for (gate_idx, (gate, names)) in model.gates.iter().zip(names).enumerate() {
+        if !names.is_defined() {
+            continue;
+        }
+        let mut doc = gate_description(gate_idx, gate);
+        let constraints = gate.constraints.iter().map(|constraint| constraint.name.as_str()).filter(|name| !name.is_empty());
+        let constraints = constraints.collect::<Vec<_>>().join(", ");
+        if !constraints.is_empty() {
+            doc.push_str(&format!(", constraints: {constraints}"));
+        }
+        let spec = theorem(
+            namer.name(format!("{}_spec", names.gate)),
+            vec![],
+            bin(BinOp::Iff, Expr::app(&names.gate, [c()]), Expr::raw("sorry")),
+            "by sorry",
+        )
+        .with_doc(doc);
+        items.push(spec.into());
+    }
+    items
+}
+
+/// The proof skeleton for `model`: lemmas unfolding `meets_constraints`, a stub for each gate,
+/// and the soundness and completeness theorems relating it to `spec` from the circuit's `Spec` module
+pub fn proofs(model: &CircuitModel) -> Vec<Item> {
+    let mut items = vec![Item::Comment("Unfolding meets_constraints".to_string())];
+    items.extend(unfolding_lemmas(model));
+    items.push(Item::Blank);
+
+    items.push(Item::Comment("The meaning of each gate".to_string()));
+    items.extend(gate_specs(model));
+    items.push(Item::Blank);
+
+    let soundness = theorem(
+        "soundness",
+        vec![hypothesis("h_assumptions", "extraction_assumptions"), hypothesis("h", "meets_constraints")],
+        Expr::app("spec", [c()]),
+        "by sorry",
+    )
+    .with_doc("Every assignment that meets the constraints satisfies the specification");
+    let completeness = theorem(
+        "completeness",
+        vec![hypothesis("h_assumptions", "extraction_assumptions"), hypothesis("h_spec", "spec")],
+        Expr::exists(
+            vec![Binder::new(&["c'"], Expr::raw("ValidCircuit P P_Prime"))],
+            Expr::and([
+                bin(BinOp::Eq, Expr::ident("c'.1.Instance"), Expr::ident("c.1.Instance")),
+                Expr::app("meets_constraints", [Expr::ident("c'")]),
+            ]),
+        ),
+        "by sorry",
+    )
+    .with_doc("Every instance the specification allows has an assignment that meets the constraints");
+    items.extend([soundness.into(), Item::Blank, completeness.into()]);
+    items
+}
+
+/// Write the proof skeleton for `model` to `out`, see `proofs`
+pub fn print_proofs(out: &mut impl Write, model: &CircuitModel) -> io::Result<()> {
+    write_items(out, proofs(model))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lean::test::{advice, constant, sample_model};
+    use crate::model::{Constraint, Gate, ModelExpr};
+
+    use super::*;
+
+    #[test]
+    fn test_proofs() {
+        let mut model = sample_model();
+        let constraint = |name: &str, polynomial| Constraint { name: name.to_string(), polynomial };
+        let sum = ModelExpr::Sum(Box::new(advice(0, 0)), Box::new(ModelExpr::Negated(Box::new(advice(1, 0)))));
+        model.gates = vec![
+            Gate { name: "add".to_string(), constraints: vec![constraint("sum", sum), constraint("", advice(2, 1))] },
+            Gate { name: "empty".to_string(), constraints: vec![constraint("zero", ModelExpr::Constant(constant(0)))] },
+        ];
+        let mut out = vec![];
+        print_proofs(&mut out, &model).unwrap();
+        // The trivially true gate has no spec, and the last conjunct is projected without `.1`
+        let expected = r#"-- Unfolding meets_constraints
+theorem meets_constraints_sufficient_rows (c : ValidCircuit P P_Prime) (h : meets_constraints c) : sufficient_rows c := by unfold meets_constraints at h; exact h.1
+theorem meets_constraints_num_blinding_factors (c : ValidCircuit P P_Prime) (h : meets_constraints c) : c.1.num_blinding_factors = 5 := by unfold meets_constraints at h; exact h.2.1
+theorem meets_constraints_selector (c : ValidCircuit P P_Prime) (h : meets_constraints c) : c.1.Selector = selector_func c := by unfold meets_constraints at h; exact h.2.2.1
+theorem meets_constraints_fixed (c : ValidCircuit P P_Prime) (h : meets_constraints c) : c.1.Fixed = fixed_func c := by unfold meets_constraints at h; exact h.2.2.2.1
+theorem meets_constraints_advice_phase (c : ValidCircuit P P_Prime) (h : meets_constraints c) : c.1.AdvicePhase = advice_phase c := by unfold meets_constraints at h; exact h.2.2.2.2.1
+theorem meets_constraints_usable_rows (c : ValidCircuit P P_Prime) (h : meets_constraints c) : c.usable_rows ≥ 8 := by unfold meets_constraints at h; exact h.2.2.2.2.2.1
+theorem meets_constraints_all_gates (c : ValidCircuit P P_Prime) (h : meets_constraints c) : all_gates c := by unfold meets_constraints at h; exact h.2.2.2.2.2.2.1
+theorem meets_constraints_all_copy_constraints (c : ValidCircuit P P_Prime) (h : meets_constraints c) : all_copy_constraints c := by unfold meets_constraints at h; exact h.2.2.2.2.2.2.2.1
+theorem meets_constraints_all_lookups (c : ValidCircuit P P_Prime) (h : meets_constraints c) : all_lookups c := by unfold meets_constraints at h; exact h.2.2.2.2.2.2.2.2.1
+theorem meets_constraints_all_shuffles (c : ValidCircuit P P_Prime) (h : meets_constraints c) : all_shuffles c := by unfold meets_constraints at h; exact h.2.2.2.2.2.2.2.2.2.1
+theorem meets_constraints_instance_unassigned (c : ValidCircuit P P_Prime) (h : meets_constraints c) : ∀ col row : ℕ, row < c.n ∧ row ≥ c.usable_rows → c.1.Instance col row = c.1.InstanceUnassigned col row := by unfold meets_constraints at h; exact h.2.2.2.2.2.2.2.2.2.2
+
+-- The meaning of each gate
+/-- Gate number 1 name: "add", constraints: sum -/
+theorem gate_add_spec (c : ValidCircuit P P_Prime) : gate_add c ↔ sorry := by sorry
+
+/-- Every assignment that meets the constraints satisfies the specification -/
+theorem soundness (c : ValidCircuit P P_Prime) (h_assumptions : extraction_assumptions c) (h : meets_constraints c) : spec c := by sorry
+
+/-- Every instance the specification allows has an assignment that meets the constraints -/
+theorem completeness (c : ValidCircuit P P_Prime) (h_assumptions : extraction_assumptions c) (h_spec : spec c) : ∃ c' : ValidCircuit P P_Prime, c'.1.Instance = c.1.Instance ∧ meets_constraints c' := by sorry
+"#;
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::field::Term;
+use crate::model::ModelExpr;
+
+use super::{bin, c, circuit_def, expression_to_lean, row, term, BinOp, Expr, Item};
+
+// Subexpressions smaller than this many nodes are repeated rather than shared
+const MIN_SHARED_SIZE: usize = 5;
+
+// A subexpression with its children replaced by their ids, so that equal subtrees get equal ids
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Node {
+    // A constant, query or challenge
+    Leaf(ModelExpr),
+    Negated(usize),
+    Sum(usize, usize),
+    Product(usize, usize),
+    Scaled(usize, Term),
+}
+
+impl Node {
+    fn children(&self) -> Vec<usize> {
+        match self {
+            Node::Leaf(_) => vec![],
+            Node::Negated(id) | Node::Scaled(id, _) => vec![*id],
+            Node::Sum(lhs, rhs) | Node::Product(lhs, rhs) => vec![*lhs, *rhs],
+        }
+    }
+}
+
+/// Polynomials whose repeated subexpressions are pulled out into helper definitions
+///
+/// Subexpressions are identified structurally, so the output grows with the number of distinct subexpressions
+/// rather than the size of the expanded trees. This matters for conditions multiplied into every constraint of a gate
+pub(crate) struct Sharing {
+    nodes: Vec<Node>,
+    ids: HashMap<Node, usize>,
+    // The number of nodes in each subexpression's tree
+    sizes: Vec<usize>,
+    roots: Vec<usize>,
+    // Shared subexpressions and their helpers. Children have smaller ids than their parents, so this is also the
+    // order they have to be defined in
+    shared: BTreeMap<usize, String>,
+}
+
+impl Sharing {
+    /// Share the subexpressions of `polynomials`, naming the helpers with `name`
+    pub(crate) fn new<'a>(
+        polynomials: impl IntoIterator<Item = &'a ModelExpr>,
+        mut name: impl FnMut(usize) -> String,
+    ) -> Self {
+        let mut sharing = Sharing {
+            nodes: vec![],
+            ids: HashMap::new(),
+            sizes: vec![],
+            roots: vec![],
+            shared: BTreeMap::new(),
+        };
+        for polynomial in polynomials {
+            let id = sharing.intern(polynomial);
+            sharing.roots.push(id);
+        }
+
+        let mut uses = vec![0; sharing.nodes.len()];
+        for &root in &sharing.roots {
+            sharing.count_uses(root, &mut uses);
+        }
+        let shared = (0..sharing.nodes.len())
+            .filter(|&id| uses[id] > 1 && sharing.sizes[id] >= MIN_SHARED_SIZE)
+            .collect::<Vec<_>>();
+        sharing.shared = shared.into_iter().enumerate().map(|(idx, id)| (id, name(idx))).collect();
+        sharing
+    }
+
+    fn intern(&mut self, expr: &ModelExpr) -> usize {
+        let node = match expr {
+            ModelExpr::Negated(inner) => Node::Negated(self.intern(inner)),
+            ModelExpr::Sum(lhs, rhs) => Node::Sum(self.intern(lhs), self.intern(rhs)),
+            ModelExpr::Product(lhs, rhs) => Node::Product(self.intern(lhs), self.intern(rhs)),
+            ModelExpr::Scaled(inner, factor) => Node::Scaled(self.intern(inner), factor.clone()),
+            leaf => Node::Leaf(leaf.clone()),
+        };
+        if let Some(&id) = self.ids.get(&node) {
+            return id;
+        }
+        let id = self.nodes.len();
+        self.sizes.push(1 + node.children().iter().map(|&child| self.sizes[child]).sum::<usize>());
+        self.nodes.push(node.clone());
+        self.ids.insert(node, id);
+        id
+    }
+
+    // A subexpression used more than once is written once, so its own children only count as used once
+    fn count_uses(&self, id: usize, uses: &mut [usize]) {
+        uses[id] += 1;
+        if uses[id] == 1 {
+            for child in self.nodes[id].children() {
+                self.count_uses(child, uses);
+            }
+        }
+    }
+
+    // The value of `id` at `row`, referring to the helpers for shared subexpressions other than itself
+    fn expr(&self, id: usize) -> Expr {
+        let child = |id: usize| match self.shared.get(&id) {
+            Some(name) => Expr::app(name, [c(), row()]),
+            None => self.expr(id),
+        };
+        match &self.nodes[id] {
+            Node::Leaf(leaf) => expression_to_lean(leaf, &row()),
+            Node::Negated(inner) => Expr::negate(child(*inner)),
+            Node::Sum(lhs, rhs) => bin(BinOp::Add, child(*lhs), child(*rhs)),
+            Node::Product(lhs, rhs) => bin(BinOp::Mul, child(*lhs), child(*rhs)),
+            Node::Scaled(inner, factor) => bin(BinOp::Mul, term(factor), child(*inner)),
+        }
+    }
+
+    /// The helper definitions, in the order they have to be defined in
+    pub(crate) fn helpers(&self) -> Vec<Item> {
+        self.shared
+            .iter()
+            .map(|(&id, name)| circuit_def(name, Expr::raw("ℕ → ZMod P"), Expr::lambda(&["row"], self.expr(id))))
+            .collect()
+    }
+
+    /// The value at `row` of the `idx`th polynomial
+    pub(crate) fn polynomial(&self, idx: usize) -> Expr {
+        let id = self.roots[idx];
+        match self.shared.get(&id) {
+            Some(name) => Expr::app(name, [c(), row()]),
+            None => self.expr(id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lean::test::{advice, between, constant, printed, sample_model};
+    use crate::lean::GateMode;
+    use crate::model::{Constraint, Gate, ModelExpr};
+
+    #[test]
+    fn test_shared_subexpressions() {
+        let mut model = sample_model();
+        let b = Box::new;
+        // q * (1 - a), as multiplied into every constraint by a condition
+        let condition = ModelExpr::Product(
+            b(ModelExpr::Selector(0)),
+            b(ModelExpr::Sum(b(ModelExpr::Constant(constant(1))), b(ModelExpr::Negated(b(advice(0, 0)))))),
+        );
+        let square = ModelExpr::Product(b(advice(1, 1)), b(advice(1, 1)));
+        let inner = ModelExpr::Sum(b(square.clone()), b(ModelExpr::Scaled(b(square), constant(3))));
+        let big = ModelExpr::Sum(b(inner.clone()), b(ModelExpr::Product(b(inner), b(advice(0, -1)))));
+        model.gates = vec![Gate {
+            name: "cond".into(),
+            constraints: vec![
+                Constraint { name: "x".into(), polynomial: ModelExpr::Product(b(condition.clone()), b(advice(1, 0))) },
+                Constraint { name: "y".into(), polynomial: ModelExpr::Product(b(condition), b(big.clone())) },
+                Constraint { name: "z".into(), polynomial: big },
+            ],
+        }];
+        let text = printed(&model, GateMode::Quantified);
+
+        let expected = r#"def gate_cond_shared_0 (c : ValidCircuit P P_Prime) : ℕ → ZMod P :=
+  λ row => c.get_selector 0 row * (1 + -c.get_advice 0 row)
+def gate_cond_shared_1 (c : ValidCircuit P P_Prime) : ℕ → ZMod P :=
+  λ row => c.get_advice 1 ((row + 1) % c.n) * c.get_advice 1 ((row + 1) % c.n) + 3 * (c.get_advice 1 ((row + 1) % c.n) * c.get_advice 1 ((row + 1) % c.n))
+def gate_cond_shared_2 (c : ValidCircuit P P_Prime) : ℕ → ZMod P :=
+  λ row => gate_cond_shared_1 c row + gate_cond_shared_1 c row * c.get_advice 0 ((row + c.n - 1 % c.n) % c.n)
+def gate_cond_x (c : ValidCircuit P P_Prime) : Prop :=
+  -- Gate number 1 name: "cond" part 1/3 x
+  ∀ row : ℕ, gate_cond_shared_0 c row * c.get_advice 1 row = 0
+def gate_cond_y (c : ValidCircuit P P_Prime) : Prop :=
+  -- Gate number 1 name: "cond" part 2/3 y
+  ∀ row : ℕ, gate_cond_shared_0 c row * gate_cond_shared_2 c row = 0
+def gate_cond_z (c : ValidCircuit P P_Prime) : Prop :=
+  -- Gate number 1 name: "cond" part 3/3 z
+  ∀ row : ℕ, gate_cond_shared_2 c row = 0
+def gate_cond (c : ValidCircuit P P_Prime) : Prop :=
+  gate_cond_x c ∧
+  gate_cond_y c ∧
+  gate_cond_z c
+"#;
+        assert_eq!(between(&text, "def gate_cond_shared_0", "def all_gates"), expected);
+    }
+}
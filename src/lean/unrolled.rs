@@ -0,0 +1,116 @@
+use crate::model::CircuitModel;
+
+use super::evaluator::{is_zero, Evaluator};
+use super::{
+    bin, c, constraint_description, expression_to_lean, gate_names, nat_type, prop_def, prop_groups, row, BinOp, Binder,
+    Expr, Item, Namer, Section, GROUPING_SIZE,
+};
+
+/// The gates of `model` stated row by row, see `GateMode::Unrolled`
+///
+/// Each constraint is `{constraint}_row_{row}` for every extracted row it isn't trivially true on, and `{constraint}_rest`
+/// for the rows after them, unless every selector it uses being off makes it trivially true there
+pub(crate) fn unrolled_gates(model: &CircuitModel) -> Section {
+    let mut units = vec![];
+    let mut members = vec![];
+    let mut namer = Namer::default();
+    let names = gate_names(model, &mut namer);
+    for (gate_idx, (gate, names)) in model.gates.iter().zip(names).enumerate() {
+        let mut items = vec![];
+        for (poly_idx, (constraint, name)) in gate.constraints.iter().zip(&names.constraints).enumerate() {
+            let description = constraint_description(gate_idx, gate, poly_idx);
+            let Some(name) = name else {
+                items.push(Item::Comment(format!("{description} is trivially true")));
+                continue;
+            };
+            items.push(Item::Comment(description));
+
+            let mut conjuncts = vec![];
+            for at in 0..model.usable_rows {
+                let mut evaluator = Evaluator::new(model);
+                let value = evaluator.evaluate(&constraint.polynomial, Some(at));
+                if is_zero(&value) {
+                    continue;
+                }
+                let row_name = namer.name(format!("{name}_row_{at}"));
+                items.push(prop_def(&row_name, bin(BinOp::Eq, evaluator.to_lean(&value.to_term()), Expr::nat(0))));
+                conjuncts.push(row_name);
+            }
+            if !is_zero(&Evaluator::new(model).evaluate(&constraint.polynomial, None)) {
+                let rest = namer.name(format!("{name}_rest"));
+                let prop = Expr::forall(
+                    vec![Binder::new(&["row"], nat_type())],
+                    bin(
+                        BinOp::Implies,
+                        bin(BinOp::Ge, row(), Expr::nat(model.usable_rows)),
+                        bin(BinOp::Eq, expression_to_lean(&constraint.polynomial, &row()), Expr::nat(0)),
+                    ),
+                );
+                items.push(prop_def(&rest, prop));
+                conjuncts.push(rest);
+            }
+            items.extend(prop_groups(name, conjuncts, GROUPING_SIZE));
+        }
+        if names.is_defined() {
+            let conjuncts = names.constraints.iter().flatten().map(|name| Expr::app(name, [c()]));
+            items.push(prop_def(&names.gate, Expr::and(conjuncts)));
+            members.push(names.gate);
+        }
+        units.push(items);
+    }
+
+    Section {
+        units,
+        summary: prop_groups("all_gates", members, GROUPING_SIZE),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lean::test::{advice, between, column, constant, enabled, printed, sample_model};
+    use crate::lean::GateMode;
+    use crate::model::{Constraint, Gate, ModelExpr};
+
+    #[test]
+    fn test_unrolled_gates() {
+        let mut model = sample_model();
+        model.usable_rows = 4;
+        let b = Box::new;
+        // q * (a + b - c), on the rows q is enabled on, and f * (a(next) + 5), where f is a fixed column
+        let sum = ModelExpr::Sum(b(ModelExpr::Sum(b(advice(0, 0)), b(advice(1, 0)))), b(ModelExpr::Negated(b(advice(2, 0)))));
+        let next = ModelExpr::Sum(b(advice(0, 1)), b(ModelExpr::Constant(constant(5))));
+        model.gates = vec![Gate {
+            name: "add".into(),
+            constraints: vec![
+                Constraint { name: "sum".into(), polynomial: ModelExpr::Product(b(ModelExpr::Selector(0)), b(sum)) },
+                Constraint { name: "fixed".into(), polynomial: ModelExpr::Product(b(ModelExpr::Fixed { column: 0, rotation: 0 }), b(next)) },
+            ],
+        }];
+        model.selectors.insert(0, enabled([1, 2]));
+        model.fixed.insert(0, column([0, 1, 3], |row| [0, 2, 0, 1][row]));
+        let text = printed(&model, GateMode::Unrolled);
+
+        let expected = r#"-- Gate number 1 name: "add" part 1/2 sum
+def gate_add_sum_row_1 (c : ValidCircuit P P_Prime) : Prop := c.get_advice 0 1 + c.get_advice 1 1 - c.get_advice 2 1 = 0
+def gate_add_sum_row_2 (c : ValidCircuit P P_Prime) : Prop := c.get_advice 0 2 + c.get_advice 1 2 - c.get_advice 2 2 = 0
+def gate_add_sum (c : ValidCircuit P P_Prime) : Prop :=
+  gate_add_sum_row_1 c ∧
+  gate_add_sum_row_2 c
+-- Gate number 1 name: "add" part 2/2 fixed
+def gate_add_fixed_row_1 (c : ValidCircuit P P_Prime) : Prop := 2 * (c.get_advice 0 2 + 5) = 0
+def gate_add_fixed_row_2 (c : ValidCircuit P P_Prime) : Prop := c.1.FixedUnassigned 0 2 * (c.get_advice 0 3 + 5) = 0
+def gate_add_fixed_row_3 (c : ValidCircuit P P_Prime) : Prop := c.get_advice 0 ((3 + 1) % c.n) + 5 = 0
+def gate_add_fixed_rest (c : ValidCircuit P P_Prime) : Prop :=
+  ∀ row : ℕ, row ≥ 4 → c.get_fixed 0 row * (c.get_advice 0 ((row + 1) % c.n) + 5) = 0
+def gate_add_fixed (c : ValidCircuit P P_Prime) : Prop :=
+  gate_add_fixed_row_1 c ∧
+  gate_add_fixed_row_2 c ∧
+  gate_add_fixed_row_3 c ∧
+  gate_add_fixed_rest c
+def gate_add (c : ValidCircuit P P_Prime) : Prop :=
+  gate_add_sum c ∧
+  gate_add_fixed c
+"#;
+        assert_eq!(between(&text, "-- Gate number 1", "def all_gates"), expected);
+    }
+}
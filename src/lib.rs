@@ -1,4 +1,7 @@
 pub mod extraction;
 pub mod field;
+pub mod lean;
+pub mod model;
+pub mod project;
 pub mod scroll;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
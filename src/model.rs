@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use halo2_proofs::plonk::{Any, Column, Expression};
+use serde::{Deserialize, Serialize};
+
+use crate::field::{Assumption, PolicyReport, SymbolDecl, SymbolicField, Term};
+
+/// Bumped whenever the serialized form of `CircuitModel` changes incompatibly
+pub const MODEL_VERSION: u32 = 8;
+
+/// Everything the extractor learns about a circuit, independent of any output format
+///
+/// The Lean printer in `crate::lean` is one consumer of this. Other tools can read it
+/// from JSON via `CircuitModel::from_json`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CircuitModel {
+    pub version: u32,
+    pub namespace: String,
+    // The prime the circuit was extracted over, in decimal, or None if it is the symbolic P
+    pub modulus: Option<String>,
+    pub symbols: Vec<SymbolDecl>,
+    pub num_advice_columns: usize,
+    pub num_fixed_columns: usize,
+    pub num_instance_columns: usize,
+    pub num_selectors: usize,
+    // The phase of each advice column, indexed by column
+    pub advice_phases: Vec<u8>,
+    pub minimum_rows: usize,
+    pub blinding_factors: usize,
+    pub usable_rows: usize,
+    pub regions: Vec<RegionEvent>,
+    // The selectors enabled in each region, in the order the regions were entered
+    pub region_selectors: Vec<RegionSelectors>,
+    pub copies: Vec<CopyConstraint>,
+    // column -> row -> annotation, for every enabled selector cell
+    pub selectors: BTreeMap<usize, BTreeMap<usize, String>>,
+    // column -> row -> value
+    pub fixed: BTreeMap<usize, BTreeMap<usize, Term>>,
+    pub fixed_fill: BTreeMap<usize, FixedFill>,
+    pub advice_annotations: BTreeMap<usize, ColumnAnnotations>,
+    pub fixed_annotations: BTreeMap<usize, ColumnAnnotations>,
+    pub instance_annotations: BTreeMap<usize, ColumnAnnotations>,
+    pub gates: Vec<Gate>,
+    pub lookups: Vec<Lookup>,
+    pub shuffles: Vec<Shuffle>,
+    // Everything the extraction policy assumed about symbolic values while the circuit was synthesized
+    pub assumptions: Vec<Assumption>,
+    pub policy_report: PolicyReport,
+}
+
+impl CircuitModel {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn to_json_writer(&self, writer: impl Write) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(writer, self)
+    }
+
+    /// Read a model written by `to_json`
+    ///
+    /// serde_json refuses input nested more than 128 levels deep. Each `ModelExpr` or `Term` operator takes two of
+    /// those levels, so a model with an expression more than about 60 operators deep can be written but not read back.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Read a model written by `to_json_writer`, with the same nesting limit as `from_json`
+    pub fn from_json_reader(reader: impl Read) -> serde_json::Result<Self> {
+        serde_json::from_reader(reader)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionEvent {
+    Entered(String),
+    Exited(String),
+}
+
+// The selectors enabled while a region was entered, by which a region assigned repeatedly is folded
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RegionSelectors {
+    pub name: String,
+    // (column, row) of each selector enabled
+    pub enabled: Vec<(usize, usize)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnKind {
+    Advice,
+    Fixed,
+    Instance,
+}
+
+impl From<&Any> for ColumnKind {
+    fn from(value: &Any) -> Self {
+        match value {
+            Any::Advice => ColumnKind::Advice,
+            Any::Fixed => ColumnKind::Fixed,
+            Any::Instance => ColumnKind::Instance,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Cell {
+    pub kind: ColumnKind,
+    pub column: usize,
+    pub row: usize,
+}
+
+impl Cell {
+    pub fn new(column: &Column<Any>, row: usize) -> Self {
+        Cell {
+            kind: column.column_type().into(),
+            column: column.index(),
+            row,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CopyConstraint {
+    pub left: Cell,
+    pub right: Cell,
+}
+
+// Every row from `row` up to the usable rows holds `value` unless explicitly assigned
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FixedFill {
+    pub row: usize,
+    pub value: Term,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ColumnAnnotations {
+    pub column: Option<String>,
+    pub rows: BTreeMap<usize, String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Gate {
+    pub name: String,
+    pub constraints: Vec<Constraint>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Constraint {
+    pub name: String,
+    pub polynomial: ModelExpr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Lookup {
+    pub name: String,
+    pub inputs: Vec<ModelExpr>,
+    pub table: Vec<ModelExpr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Shuffle {
+    pub name: String,
+    pub inputs: Vec<ModelExpr>,
+    pub shuffles: Vec<ModelExpr>,
+}
+
+/// An owned, serializable mirror of halo2's `Expression`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelExpr {
+    Constant(Term),
+    Selector(usize),
+    Fixed { column: usize, rotation: i32 },
+    Advice { column: usize, rotation: i32 },
+    Instance { column: usize, rotation: i32 },
+    Challenge { index: usize, phase: u8 },
+    Negated(Box<ModelExpr>),
+    Sum(Box<ModelExpr>, Box<ModelExpr>),
+    Product(Box<ModelExpr>, Box<ModelExpr>),
+    Scaled(Box<ModelExpr>, Term),
+}
+
+impl ModelExpr {
+    pub fn is_zero(&self) -> bool {
+        matches!(self, ModelExpr::Constant(value) if value.is_zero())
+    }
+}
+
+impl<F: SymbolicField> From<&Expression<F>> for ModelExpr {
+    fn from(expr: &Expression<F>) -> Self {
+        match expr {
+            Expression::Constant(value) => ModelExpr::Constant(value.to_term()),
+            Expression::Selector(selector) => ModelExpr::Selector(selector.0),
+            Expression::Fixed(query) => ModelExpr::Fixed { column: query.column_index(), rotation: query.rotation().0 },
+            Expression::Advice(query) => ModelExpr::Advice { column: query.column_index(), rotation: query.rotation().0 },
+            Expression::Instance(query) => ModelExpr::Instance { column: query.column_index(), rotation: query.rotation().0 },
+            Expression::Challenge(challenge) => ModelExpr::Challenge { index: challenge.index(), phase: challenge.phase() },
+            Expression::Negated(expression) => ModelExpr::Negated(Box::new(expression.as_ref().into())),
+            Expression::Sum(lhs, rhs) => ModelExpr::Sum(Box::new(lhs.as_ref().into()), Box::new(rhs.as_ref().into())),
+            Expression::Product(lhs, rhs) => ModelExpr::Product(Box::new(lhs.as_ref().into()), Box::new(rhs.as_ref().into())),
+            Expression::Scaled(expression, factor) => ModelExpr::Scaled(Box::new(expression.as_ref().into()), factor.to_term()),
+        }
+    }
+}
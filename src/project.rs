@@ -0,0 +1,194 @@
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::lean::{proofs, write_modules_with, Binder, Def, Expr, GateMode, Item, Module, HALVA_MODULE};
+use crate::model::CircuitModel;
+
+/// The Lean release generated projects are pinned to
+pub const LEAN_TOOLCHAIN: &str = "leanprover/lean4:v4.15.0";
+/// The Mathlib tag generated projects require, which must be built with `LEAN_TOOLCHAIN`
+pub const MATHLIB_REV: &str = "v4.15.0";
+
+const MATHLIB_GIT: &str = "https://github.com/leanprover-community/mathlib4.git";
+
+/// A Lake project that extracted circuits are written into
+///
+/// The generated modules are replaced every time a circuit is written. `lakefile.lean`, `lean-toolchain`,
+/// `.gitignore` and each circuit's `Spec.lean` and `Proofs.lean` are only created if they don't exist, so they can
+/// be edited freely
+#[derive(Clone, Debug)]
+pub struct LakeProject {
+    dir: PathBuf,
+    toolchain: String,
+    mathlib_rev: String,
+    gate_mode: GateMode,
+}
+
+impl LakeProject {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        LakeProject {
+            dir: dir.into(),
+            toolchain: LEAN_TOOLCHAIN.to_string(),
+            mathlib_rev: MATHLIB_REV.to_string(),
+            gate_mode: GateMode::default(),
+        }
+    }
+
+    /// Pin a different Lean toolchain, such as `leanprover/lean4:v4.16.0`
+    pub fn with_toolchain(self, toolchain: &str) -> Self {
+        LakeProject {
+            toolchain: toolchain.to_string(),
+            ..self
+        }
+    }
+
+    /// Require a different Mathlib tag or commit, which must match the toolchain
+    pub fn with_mathlib_rev(self, rev: &str) -> Self {
+        LakeProject {
+            mathlib_rev: rev.to_string(),
+            ..self
+        }
+    }
+
+    /// State gates as `mode` says, such as one proposition per row with `GateMode::Unrolled`
+    pub fn with_gate_mode(self, mode: GateMode) -> Self {
+        LakeProject {
+            gate_mode: mode,
+            ..self
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Write `model` into the project, returning the paths written
+    ///
+    /// The modules follow the namespace, so `Tutorial.MyCircuit` is written to `Tutorial/MyCircuit.lean` and
+    /// `Tutorial/MyCircuit/`, and is built as part of the `Tutorial` library
+    pub fn write(&self, model: &CircuitModel) -> io::Result<Vec<PathBuf>> {
+        let library = model.namespace.split('.').next().unwrap_or_default();
+        let mut paths = vec![];
+
+        let toolchain = self.dir.join("lean-toolchain");
+        if create_new(&toolchain, &format!("{}\n", self.toolchain))? {
+            paths.push(toolchain);
+        }
+        let lakefile = self.dir.join("lakefile.lean");
+        if create_new(&lakefile, &self.lakefile(&model.namespace))? {
+            paths.push(lakefile);
+        } else if !fs::read_to_string(&lakefile)?.contains(&format!("lean_lib «{library}»")) {
+            log::warn!("{} has no lean_lib «{library}», so {} won't be built", lakefile.display(), model.namespace);
+        }
+        let gitignore = self.dir.join(".gitignore");
+        if create_new(&gitignore, "/.lake\n")? {
+            paths.push(gitignore);
+        }
+
+        paths.extend(write_modules_with(&self.dir, model, self.gate_mode)?);
+
+        for module in [spec_module(model), proofs_module(model)] {
+            if !module.path(&self.dir).exists() {
+                paths.push(module.write(&self.dir, model)?);
+            }
+        }
+        Ok(paths)
+    }
+
+    fn lakefile(&self, namespace: &str) -> String {
+        let library = namespace.split('.').next().unwrap_or_default();
+        // Every module under the library is built, including proofs added next to the generated ones.
+        // A nested namespace has no `{library}.lean` of its own
+        let glob = if namespace.contains('.') { "submodules" } else { "andSubmodules" };
+        format!(
+            "import Lake\nopen Lake DSL\n\npackage «{library}»\n\nrequire mathlib from git\n  \"{MATHLIB_GIT}\" @ \"{}\"\n\nlean_lib «{HALVA_MODULE}»\n\n@[default_target]\nlean_lib «{library}» where\n  globs := #[.{glob} `{library}]\n",
+            self.mathlib_rev
+        )
+    }
+}
+
+// Write `contents` to `path` unless it already exists, returning whether it was written
+fn create_new(path: &Path, contents: &str) -> io::Result<bool> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(mut file) => {
+            file.write_all(contents.as_bytes())?;
+            Ok(true)
+        },
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+// The stub users state the circuit's intended behaviour in
+fn spec_module(model: &CircuitModel) -> Module {
+    let spec = Def::new(
+        "spec",
+        vec![Binder::new(&["c"], Expr::raw("ValidCircuit P P_Prime"))],
+        Some(Expr::raw("Prop")),
+        Expr::raw("True"),
+    )
+    .with_doc("The intended behaviour of the circuit. This file is never regenerated");
+    Module {
+        name: format!("{}.Spec", model.namespace),
+        imports: vec![model.namespace.clone()],
+        variables: true,
+        items: vec![spec.into(), Item::Blank],
+    }
+}
+
+// The proof skeleton, see `lean::proofs`
+fn proofs_module(model: &CircuitModel) -> Module {
+    let mut items = proofs(model);
+    items.push(Item::Blank);
+    Module {
+        name: format!("{}.Proofs", model.namespace),
+        imports: vec![format!("{}.Spec", model.namespace)],
+        variables: true,
+        items,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lean::test::sample_model;
+
+    use super::*;
+
+    #[test]
+    fn test_rewrite() {
+        let dir = std::env::temp_dir().join("halo2_extr_project_test");
+        let _ = fs::remove_dir_all(&dir);
+        let project = LakeProject::new(&dir);
+        let mut model = sample_model();
+        model.namespace = "Tutorial.MyCircuit".to_string();
+
+        let relative = |paths: Vec<PathBuf>| paths.iter().map(|path| path.strip_prefix(&dir).unwrap().display().to_string()).collect::<Vec<_>>();
+        let first = relative(project.write(&model).unwrap());
+        let kept = ["lean-toolchain", "lakefile.lean", ".gitignore", "Tutorial/MyCircuit/Spec.lean", "Tutorial/MyCircuit/Proofs.lean"];
+        for path in kept {
+            assert!(first.iter().any(|written| written == path), "{path} wasn't created");
+        }
+        let lakefile = fs::read_to_string(dir.join("lakefile.lean")).unwrap();
+        assert!(lakefile.contains(&format!("require mathlib from git\n  \"{MATHLIB_GIT}\" @ \"{MATHLIB_REV}\"\n")));
+        assert!(lakefile.contains("lean_lib «Tutorial» where\n  globs := #[.submodules `Tutorial]\n"));
+
+        // Everything the user might have edited is left alone, and everything generated is written again
+        let edited = "-- edited\n";
+        for path in kept.into_iter().chain(["Tutorial/MyCircuit.lean"]) {
+            fs::write(dir.join(path), edited).unwrap();
+        }
+        let second = relative(project.write(&model).unwrap());
+        // Halva.lean is also skipped, as it is still up to date
+        let regenerated = first.iter().filter(|path| !kept.contains(&path.as_str()) && *path != "Halva.lean").cloned().collect::<Vec<_>>();
+        assert_eq!(second, regenerated);
+        for path in kept {
+            assert_eq!(fs::read_to_string(dir.join(path)).unwrap(), edited);
+        }
+        assert_eq!(fs::read_to_string(dir.join("Tutorial/MyCircuit.lean")).unwrap(), "import Tutorial.MyCircuit.MeetsConstraints\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
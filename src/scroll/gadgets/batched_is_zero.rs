@@ -245,6 +245,6 @@ mod test {
             expect_is_zero: Some(true),
             _marker: PhantomData,
         };
-        ExtractingAssignment::run(&circuit, "BatchedIsZero", &[]).unwrap();
+        ExtractingAssignment::extract_to_string(&circuit, "BatchedIsZero", &[]).unwrap();
     }
 }
\ No newline at end of file
@@ -312,6 +312,6 @@ mod test {
     #[test]
     fn test_circuit() {
         let circuit = TestCircuit {};
-        ExtractingAssignment::run(&circuit, "BinaryNumber", &[]).unwrap();
+        ExtractingAssignment::extract_to_string(&circuit, "BinaryNumber", &[]).unwrap();
     }
 }
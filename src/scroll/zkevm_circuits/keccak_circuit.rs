@@ -1084,10 +1084,9 @@ impl KeccakCircuit {
     /// a particular row number depending on current Keccak params
     pub fn capacity_for_row(num_rows: usize) -> Option<usize> {
         if num_rows > 0 {
-            println!("-- NUM_ROUNDS: {NUM_ROUNDS}");
-            println!("-- num_rows_per_round: {}", get_num_rows_per_round());
-            println!("-- num_rows: {num_rows}");
-            println!("-- {}", num_rows / ((NUM_ROUNDS + 1) * get_num_rows_per_round()) - 2);
+            log::debug!("NUM_ROUNDS: {NUM_ROUNDS}");
+            log::debug!("num_rows_per_round: {}", get_num_rows_per_round());
+            log::debug!("num_rows: {num_rows}");
             // Subtract two for unusable rows
             Some(num_rows / ((NUM_ROUNDS + 1) * get_num_rows_per_round()) - 2)
         } else {
@@ -6,6 +6,7 @@ use super::param::*;
 // use eth_types::Word;
 // use gadgets::ToScalar;
 use halo2_proofs::circuit::Value;
+use num_bigint::BigUint;
 use std::env::var;
 
 /// Description of which bits (positions) a part contains
@@ -136,9 +137,17 @@ pub(crate) fn pack_part(bits: &[u8], info: &PartInfo) -> u64 {
 }
 
 // TERMFIELD: cannot be done for symbolic values with non-symbolic return type
+// unless a sample witness is installed, in which case its concrete value is unpacked
 /// Unpack a sparse keccak word into bits in the range [0,BIT_SIZE[
-pub(crate) fn unpack(_packed: TermField) -> [u8; NUM_BITS_PER_WORD] {
-    [0; NUM_BITS_PER_WORD]
+pub(crate) fn unpack(packed: TermField) -> [u8; NUM_BITS_PER_WORD] {
+    let mut bits = [0; NUM_BITS_PER_WORD];
+    if let Some(packed) = packed.shadow() {
+        let mask = BigUint::from(BIT_SIZE - 1);
+        for (idx, bit) in bits.iter_mut().enumerate() {
+            *bit = u8::try_from((&packed >> (idx * BIT_COUNT)) & &mask).expect("Masked to BIT_SIZE");
+        }
+    }
+    bits
     // let mut bits = [0; NUM_BITS_PER_WORD];
     // let packed = Word::from_little_endian(packed.to_repr().as_ref());
     // let mask = Word::from(BIT_SIZE - 1);
@@ -161,7 +170,7 @@ pub(crate) fn pack_u64(value: u64) -> TermField {
 /// Calculates a ^ b with a and b field elements
 pub(crate) fn field_xor(a: TermField, b: TermField) -> TermField {
     // panic!("If field_xor is required it will require a custom implementation")
-    TermField::from(format!("(field_xor ({a}) ({b}))"))
+    TermField::apply("field_xor", &[a, b])
     // let mut bytes = [0u8; 32];
     // for (idx, (a, b)) in a
     //     .to_repr()
@@ -1,8 +1,12 @@
 use std::collections::BTreeMap;
 
 use itertools::Itertools;
+use num_bigint::BigInt;
+use num_traits::{One, Zero};
 
-// start and end are inclusive
+use crate::model::ColumnAnnotations;
+
+// start and end are inclusive. One line per run of rows with the same annotation
 pub fn get_group_annotations(annotations: &BTreeMap<usize, String>, start: usize, end: usize) -> Option<String> {
     let mut grouped_comments = vec![];
     for row in start..end+1 {
@@ -25,17 +29,17 @@ pub fn get_group_annotations(annotations: &BTreeMap<usize, String>, start: usize
             .iter()
             .map(|(start, end, comment)| {
                 if *start == *end {
-                    format!("  -- {start}: {comment}")
+                    format!("{start}: {comment}")
                 } else {
-                    format!("  -- {start}-{end}: {comment}")
+                    format!("{start}-{end}: {comment}")
                 }
             })
             .join("\n"))
     }
 }
 
-pub fn group_values(column: &BTreeMap<usize, String>) -> Vec<(String, usize, Option<usize>)> {
-    let mut res: Vec<(String, usize, Option<usize>)> = vec![];
+pub fn group_values<T: Clone + PartialEq>(column: &BTreeMap<usize, T>) -> Vec<(T, usize, Option<usize>)> {
+    let mut res: Vec<(T, usize, Option<usize>)> = vec![];
 
     for (row, value) in column.iter() {
         if let Some((val, start, end)) = res.last_mut() {
@@ -52,77 +56,173 @@ pub fn group_values(column: &BTreeMap<usize, String>) -> Vec<(String, usize, Opt
     res
 }
 
-pub fn make_lean_comment(text: &str) -> String {
-    text
-        .split("\n")
-        .map(|line| format!("  --{line}"))
-        .join("\n")
+/// The shortest period of `values`, which is `values.len()` if they don't repeat
+///
+/// `values[i] == values[i + period]` wherever both are defined, though the last repetition may be cut short
+pub fn find_period<T: PartialEq>(values: &[T]) -> usize {
+    // The KMP failure function: the length of the longest proper prefix of values[..=i] that is also its suffix
+    let mut failure = vec![0; values.len()];
+    for i in 1..values.len() {
+        let mut len = failure[i - 1];
+        while len > 0 && values[i] != values[len] {
+            len = failure[len - 1];
+        }
+        if values[i] == values[len] {
+            len += 1;
+        }
+        failure[i] = len;
+    }
+    values.len() - failure.last().copied().unwrap_or(0)
 }
 
-pub fn print_grouped_props(prefix: &str, final_name: &str, props: &[String], group_size: usize) {
-    assert!(group_size > 1);
-    let mut groups = vec![vec![]];
-
-    for (idx, prop) in props.iter().enumerate() {
-        let name = format!("{prefix}{idx}");
-        println!("def {name} (c: ValidCircuit P P_Prime) : Prop :=");
-        println!("  {prop}");
-        groups[0].push((idx, idx, name));
-        let mut i = 0;
-        while i < groups.len() {
-            if groups[i].len() >= group_size {
-                let start = groups[i][0].0;
-                let end = groups[i][groups[i].len()-1].1;
-                let name = format!("{prefix}{start}_to_{end}");
-                let body = groups[i]
-                    .iter()
-                    .map(|(_, _, name)| format!("{name} c"))
-                    .join(" ∧ ");
-                println!("def {name} (c: ValidCircuit P P_Prime) : Prop :=");
-                println!("  {body}");
-                if groups.len() == i+1 {
-                    groups.push(vec![]);
-                }
-                groups[i+1].push((start, end, name));
-                groups[i] = vec![];
-            }
-            i += 1;
+/// The coefficients, lowest degree first, of the polynomial of degree at most `max_degree` giving `values[k]` for each `k`
+///
+/// Only polynomials with integer coefficients are found, and only if there are at least twice as many values as
+/// coefficients, so that the fit isn't a coincidence
+pub fn find_polynomial(values: &[BigInt], max_degree: usize) -> Option<Vec<BigInt>> {
+    // The leading forward differences, until they become constant
+    let mut leading = vec![];
+    let mut differences = values.to_vec();
+    loop {
+        let first = differences.first()?.clone();
+        leading.push(first.clone());
+        if differences.iter().all(|value| *value == first) {
+            break;
+        }
+        if leading.len() > max_degree {
+            return None;
         }
+        differences = differences.windows(2).map(|pair| &pair[1] - &pair[0]).collect();
+    }
+    let degree = leading.len() - 1;
+    if values.len() < 2 * (degree + 1) {
+        return None;
     }
 
-    let final_body = groups
-        .iter()
-        .rev()
-        .flatten()
-        .map(|(_, _, name)| format!("{name} c"))
-        .join(" ∧ ");
+    // By Newton's formula values[k] = Σ leading[i] * C(k, i), and C(k, i) = k(k-1)...(k-i+1) / i!
+    // Scale everything by degree! to stay in the integers
+    let factorial = |n: usize| (1..=n).fold(BigInt::one(), |acc, i| acc * i);
+    let scale = factorial(degree);
+    let mut scaled = vec![BigInt::zero(); degree + 1];
+    let mut falling = vec![BigInt::one()];
+    for (i, difference) in leading.iter().enumerate() {
+        let weight = difference * (&scale / factorial(i));
+        for (coefficient, term) in scaled.iter_mut().zip(&falling) {
+            *coefficient += &weight * term;
+        }
+        // Multiply by (k - i)
+        let mut next = vec![BigInt::zero(); falling.len() + 1];
+        for (j, term) in falling.iter().enumerate() {
+            next[j + 1] += term;
+            next[j] -= term * i;
+        }
+        falling = next;
+    }
+    scaled
+        .into_iter()
+        .map(|coefficient| (&coefficient % &scale).is_zero().then(|| coefficient / &scale))
+        .collect()
+}
 
-    let final_body = if final_body == "" {
-        "true"
-    } else {
-        &final_body
+/// The classes of the equivalence relation generated by `pairs`, in the order each class is first mentioned
+///
+/// Each class is sorted, and only values mentioned in some pair are included
+pub fn equivalence_classes<T: Copy + Ord>(pairs: impl IntoIterator<Item = (T, T)>) -> Vec<Vec<T>> {
+    // Union-find over the indices of the values, in the order they are first seen
+    let mut indices = BTreeMap::new();
+    let mut values = vec![];
+    let mut parents: Vec<usize> = vec![];
+    let mut index = |value: T, values: &mut Vec<T>, parents: &mut Vec<usize>| {
+        *indices.entry(value).or_insert_with(|| {
+            values.push(value);
+            parents.push(parents.len());
+            parents.len() - 1
+        })
     };
+    fn root(parents: &mut [usize], mut idx: usize) -> usize {
+        while parents[idx] != idx {
+            // Halve the path as we go, so that long chains of copies stay cheap
+            parents[idx] = parents[parents[idx]];
+            idx = parents[idx];
+        }
+        idx
+    }
+    for (lhs, rhs) in pairs {
+        let lhs = index(lhs, &mut values, &mut parents);
+        let rhs = index(rhs, &mut values, &mut parents);
+        let (lhs, rhs) = (root(&mut parents, lhs), root(&mut parents, rhs));
+        // The earlier root is kept, so each class is ordered by the first of its values to be seen
+        parents[lhs.max(rhs)] = lhs.min(rhs);
+    }
 
-    println!("def {final_name} (c: ValidCircuit P P_Prime): Prop :=");
-    println!("  {final_body}");
+    let mut classes: BTreeMap<usize, Vec<T>> = BTreeMap::new();
+    for (idx, value) in values.into_iter().enumerate() {
+        classes.entry(root(&mut parents, idx)).or_default().push(value);
+    }
+    classes
+        .into_values()
+        .map(|mut class| {
+            class.sort();
+            class
+        })
+        .collect()
 }
 
-pub fn update_column_annotation(annotations: &mut BTreeMap<usize, (Option<String>, BTreeMap<usize, String>)>, col: usize, annotation: String) {
-    let current = annotations.get_mut(&col);
-    if let Some((column, _)) = current {
-        *column = Some(annotation);
-    } else {
-        annotations.insert(col, (Some(annotation), BTreeMap::new()));
-    }
+pub fn update_column_annotation(annotations: &mut BTreeMap<usize, ColumnAnnotations>, col: usize, annotation: String) {
+    annotations.entry(col).or_default().column = Some(annotation);
 }
 
-pub fn update_row_annotation(annotations: &mut BTreeMap<usize, (Option<String>, BTreeMap<usize, String>)>, col: usize, row: usize, annotation: String) {
-    let current = annotations.get_mut(&col);
-    if let Some((_, rows)) = current {
-        rows.insert(row, annotation);
-    } else {
-        let mut rows = BTreeMap::new();
-        rows.insert(row, annotation);
-        annotations.insert(col, (None, rows));
+pub fn update_row_annotation(annotations: &mut BTreeMap<usize, ColumnAnnotations>, col: usize, row: usize, annotation: String) {
+    annotations.entry(col).or_default().rows.insert(row, annotation);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_equivalence_classes() {
+        let classes = equivalence_classes([(3, 4), (1, 2), (4, 5), (2, 6), (7, 7), (5, 3)]);
+        assert_eq!(classes, vec![vec![3, 4, 5], vec![1, 2, 6], vec![7]]);
+
+        // A chain is merged however it is ordered
+        let chain = (0..100).rev().map(|i| (i, i + 1));
+        assert_eq!(equivalence_classes(chain), vec![(0..=100).collect_vec()]);
+
+        assert!(equivalence_classes(Vec::<(u8, u8)>::new()).is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_find_period() {
+        assert_eq!(find_period(&[1, 2, 1, 2, 1]), 2);
+        assert_eq!(find_period(&[0, 0, 1, 0, 0, 1, 0, 0]), 3);
+        assert_eq!(find_period(&[1, 1, 1]), 1);
+        assert_eq!(find_period(&[1, 2, 3]), 3);
+        assert_eq!(find_period(&[1, 2, 1, 1]), 3);
+        assert_eq!(find_period::<u8>(&[]), 0);
+    }
+
+    #[test]
+    fn test_find_polynomial() {
+        let values = |f: fn(i64) -> i64| (0..10).map(|k| BigInt::from(f(k))).collect_vec();
+        let coefficients = |coefficients: &[i64]| Some(coefficients.iter().map(|&c| BigInt::from(c)).collect_vec());
+
+        assert_eq!(find_polynomial(&values(|k| 2 * k + 5), 3), coefficients(&[5, 2]));
+        assert_eq!(find_polynomial(&values(|k| 3 * k * k - 2 * k + 7), 3), coefficients(&[7, -2, 3]));
+        assert_eq!(find_polynomial(&values(|k| k * k * k - 5), 3), coefficients(&[-5, 0, 0, 1]));
+        assert_eq!(find_polynomial(&values(|_| 4), 3), coefficients(&[4]));
+        // Too high a degree
+        assert_eq!(find_polynomial(&values(|k| k * k * k * k), 3), None);
+        // Integer valued, but with rational coefficients
+        assert_eq!(find_polynomial(&values(|k| k * (k + 1) / 2), 3), None);
+        // Too few values to trust the fit
+        assert_eq!(find_polynomial(&values(|k| k * k)[..5], 3), None);
+        assert_eq!(find_polynomial(&[], 3), None);
+    }
+
+    #[test]
+    fn test_group_values() {
+        let column: BTreeMap<usize, u8> = [(0, 1), (1, 1), (2, 2), (4, 2), (5, 2)].into_iter().collect();
+        assert_eq!(group_values(&column), vec![(1, 0, Some(1)), (2, 2, None), (2, 4, Some(5))]);
+    }
+}